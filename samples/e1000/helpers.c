@@ -0,0 +1,352 @@
+// SPDX-License-Identifier: GPL-2.0
+
+/*
+ * C helpers for the Rust e1000 driver.
+ *
+ * The netdev/skb API is mostly static inline functions and macros, which
+ * bindgen cannot see. These wrappers give the Rust side something to link
+ * against, the same way rust/helpers does for the in-tree abstractions.
+ */
+
+#include <linux/dma-mapping.h>
+#include <linux/etherdevice.h>
+#include <linux/if_vlan.h>
+#include <linux/netdevice.h>
+#include <linux/skbuff.h>
+#include <linux/tcp.h>
+#include <linux/workqueue.h>
+#include <net/checksum.h>
+#include <net/ip.h>
+#include <net/page_pool/helpers.h>
+
+void rust_e1000_set_netdev_dev(struct net_device *ndev, struct device *dev)
+{
+	SET_NETDEV_DEV(ndev, dev);
+}
+
+struct sk_buff *rust_e1000_netdev_alloc_skb_ip_align(struct net_device *ndev,
+						      unsigned int length)
+{
+	return netdev_alloc_skb_ip_align(ndev, length);
+}
+
+void rust_e1000_skb_eth_type_trans(struct sk_buff *skb, struct net_device *ndev)
+{
+	skb->protocol = eth_type_trans(skb, ndev);
+}
+
+void rust_e1000_skb_checksum_unnecessary(struct sk_buff *skb)
+{
+	skb->ip_summed = CHECKSUM_UNNECESSARY;
+}
+
+/* The tag to insert on transmit, or -1 if the skb carries none. */
+int rust_e1000_skb_vlan_tag(const struct sk_buff *skb)
+{
+	return skb_vlan_tag_present(skb) ? skb_vlan_tag_get(skb) : -1;
+}
+
+void rust_e1000_skb_put_vlan_tag(struct sk_buff *skb, u16 tci)
+{
+	__vlan_hwaccel_put_tag(skb, htons(ETH_P_8021Q), tci);
+}
+
+void rust_e1000_dev_kfree_skb_any(struct sk_buff *skb)
+{
+	dev_kfree_skb_any(skb);
+}
+
+void rust_e1000_dev_consume_skb_any(struct sk_buff *skb)
+{
+	dev_consume_skb_any(skb);
+}
+
+unsigned long rust_e1000_local_irq_save(void)
+{
+	unsigned long flags;
+
+	local_irq_save(flags);
+	return flags;
+}
+
+void rust_e1000_local_irq_restore(unsigned long flags)
+{
+	local_irq_restore(flags);
+}
+
+void rust_e1000_netif_napi_add(struct net_device *ndev, struct napi_struct *napi,
+			       int (*poll)(struct napi_struct *, int))
+{
+	netif_napi_add(ndev, napi, poll);
+}
+
+void rust_e1000_eth_hw_addr_random(struct net_device *ndev)
+{
+	eth_hw_addr_random(ndev);
+}
+
+void rust_e1000_netdev_set_unicast_flt(struct net_device *ndev)
+{
+	ndev->priv_flags |= IFF_UNICAST_FLT;
+}
+
+/*
+ * Copy up to @max addresses into @addrs, ETH_ALEN bytes each, and return how
+ * many there are. The caller holds the address list lock.
+ */
+unsigned int rust_e1000_netdev_uc_addrs(struct net_device *ndev, u8 *addrs,
+					unsigned int max)
+{
+	struct netdev_hw_addr *ha;
+	unsigned int n = 0;
+
+	netdev_for_each_uc_addr(ha, ndev) {
+		if (n < max)
+			memcpy(addrs + n * ETH_ALEN, ha->addr, ETH_ALEN);
+		n++;
+	}
+	return n;
+}
+
+unsigned int rust_e1000_netdev_mc_addrs(struct net_device *ndev, u8 *addrs,
+					unsigned int max)
+{
+	struct netdev_hw_addr *ha;
+	unsigned int n = 0;
+
+	netdev_for_each_mc_addr(ha, ndev) {
+		if (n < max)
+			memcpy(addrs + n * ETH_ALEN, ha->addr, ETH_ALEN);
+		n++;
+	}
+	return n;
+}
+
+bool rust_e1000_netif_carrier_ok(const struct net_device *ndev)
+{
+	return netif_carrier_ok(ndev);
+}
+
+bool rust_e1000_netif_running(const struct net_device *ndev)
+{
+	return netif_running(ndev);
+}
+
+void rust_e1000_netif_tx_disable(struct net_device *ndev)
+{
+	netif_tx_disable(ndev);
+}
+
+void rust_e1000_netif_tx_wake_all_queues(struct net_device *ndev)
+{
+	netif_tx_wake_all_queues(ndev);
+}
+
+void rust_e1000_netif_stop_queue(struct net_device *ndev)
+{
+	netif_stop_queue(ndev);
+}
+
+void rust_e1000_netif_wake_queue(struct net_device *ndev)
+{
+	netif_wake_queue(ndev);
+}
+
+bool rust_e1000_netif_queue_stopped(const struct net_device *ndev)
+{
+	return netif_queue_stopped(ndev);
+}
+
+bool rust_e1000_skb_csum_partial(const struct sk_buff *skb, unsigned int *start,
+				 unsigned int *offset)
+{
+	if (skb->ip_summed != CHECKSUM_PARTIAL)
+		return false;
+	*start = skb_checksum_start_offset(skb);
+	*offset = *start + skb->csum_offset;
+	return true;
+}
+
+int rust_e1000_skb_tso_prep(struct sk_buff *skb, unsigned int *ip_start,
+			    unsigned int *tcp_start, unsigned int *hdr_len,
+			    unsigned int *mss)
+{
+	struct iphdr *iph;
+	int err;
+
+	if (!skb_is_gso(skb) || !(skb_shinfo(skb)->gso_type & SKB_GSO_TCPV4))
+		return 0;
+
+	err = skb_cow_head(skb, 0);
+	if (err < 0)
+		return err;
+
+	/* The NIC fills in the length and checksum of every segment. */
+	iph = ip_hdr(skb);
+	iph->tot_len = 0;
+	iph->check = 0;
+	tcp_hdr(skb)->check =
+		~csum_tcpudp_magic(iph->saddr, iph->daddr, 0, IPPROTO_TCP, 0);
+
+	*ip_start = skb_network_offset(skb);
+	*tcp_start = skb_transport_offset(skb);
+	*hdr_len = skb_tcp_all_headers(skb);
+	*mss = skb_shinfo(skb)->gso_size;
+	return 1;
+}
+
+unsigned int rust_e1000_skb_nr_frags(const struct sk_buff *skb)
+{
+	return skb_shinfo(skb)->nr_frags;
+}
+
+const void *rust_e1000_skb_frag_address(const struct sk_buff *skb,
+					unsigned int i)
+{
+	return skb_frag_address(&skb_shinfo(skb)->frags[i]);
+}
+
+unsigned int rust_e1000_skb_frag_size(const struct sk_buff *skb, unsigned int i)
+{
+	return skb_frag_size(&skb_shinfo(skb)->frags[i]);
+}
+
+/*
+ * Map the linear part of an skb or one of its page fragments for the device
+ * to read. Both are in the direct map, so this goes by page, the way
+ * dma_map_single() and skb_frag_dma_map() end up doing.
+ */
+int rust_e1000_dma_map_to_device(struct device *dev, const void *addr,
+				 size_t len, dma_addr_t *handle)
+{
+	dma_addr_t dma = dma_map_page(dev, virt_to_page(addr),
+				      offset_in_page(addr), len, DMA_TO_DEVICE);
+
+	if (dma_mapping_error(dev, dma))
+		return -ENOMEM;
+	*handle = dma;
+	return 0;
+}
+
+void rust_e1000_dma_unmap_to_device(struct device *dev, dma_addr_t handle,
+				    size_t len)
+{
+	dma_unmap_page(dev, handle, len, DMA_TO_DEVICE);
+}
+
+/*
+ * RX buffers are page pool fragments with room for an skb around the
+ * buf_size bytes the device writes: headroom in front, skb_shared_info
+ * behind, so that build_skb() can take the fragment over.
+ */
+#define RUST_E1000_RX_HEADROOM (NET_SKB_PAD + NET_IP_ALIGN)
+
+static unsigned int rust_e1000_rx_truesize(unsigned int buf_size)
+{
+	return SKB_DATA_ALIGN(RUST_E1000_RX_HEADROOM + buf_size) +
+	       SKB_DATA_ALIGN(sizeof(struct skb_shared_info));
+}
+
+int rust_e1000_page_pool_create(struct device *dev, unsigned int pool_size,
+				unsigned int buf_size, struct page_pool **pool)
+{
+	unsigned int order = get_order(rust_e1000_rx_truesize(buf_size));
+	struct page_pool_params pp = {
+		.order = order,
+		.flags = PP_FLAG_DMA_MAP | PP_FLAG_DMA_SYNC_DEV,
+		.pool_size = pool_size,
+		.nid = NUMA_NO_NODE,
+		.dev = dev,
+		.dma_dir = DMA_FROM_DEVICE,
+		.max_len = PAGE_SIZE << order,
+	};
+	struct page_pool *p = page_pool_create(&pp);
+
+	if (IS_ERR(p))
+		return PTR_ERR(p);
+	*pool = p;
+	return 0;
+}
+
+void rust_e1000_page_pool_destroy(struct page_pool *pool)
+{
+	page_pool_destroy(pool);
+}
+
+int rust_e1000_rx_frag_alloc(struct page_pool *pool, unsigned int buf_size,
+			     struct page **page, unsigned int *offset)
+{
+	struct page *p = page_pool_dev_alloc_frag(pool, offset,
+						  rust_e1000_rx_truesize(buf_size));
+
+	if (!p)
+		return -ENOMEM;
+	*page = p;
+	return 0;
+}
+
+void rust_e1000_rx_frag_put(struct page_pool *pool, struct page *page)
+{
+	page_pool_put_full_page(pool, page, false);
+}
+
+void *rust_e1000_rx_frag_data(struct page *page, unsigned int offset)
+{
+	return page_address(page) + offset + RUST_E1000_RX_HEADROOM;
+}
+
+dma_addr_t rust_e1000_rx_frag_dma(struct page *page, unsigned int offset)
+{
+	return page_pool_get_dma_addr(page) + offset + RUST_E1000_RX_HEADROOM;
+}
+
+void rust_e1000_rx_frag_sync_for_cpu(struct page_pool *pool, struct page *page,
+				     unsigned int offset, unsigned int len)
+{
+	page_pool_dma_sync_for_cpu(pool, page, offset + RUST_E1000_RX_HEADROOM,
+				   len);
+}
+
+void rust_e1000_rx_frag_sync_for_device(struct page_pool *pool,
+					struct page *page, unsigned int offset,
+					unsigned int len)
+{
+	dma_sync_single_range_for_device(pool->p.dev,
+					 page_pool_get_dma_addr(page),
+					 offset + RUST_E1000_RX_HEADROOM, len,
+					 DMA_FROM_DEVICE);
+}
+
+struct sk_buff *rust_e1000_rx_build_skb(struct page *page, unsigned int offset,
+					unsigned int buf_size, unsigned int len)
+{
+	struct sk_buff *skb = napi_build_skb(page_address(page) + offset,
+					     rust_e1000_rx_truesize(buf_size));
+
+	if (!skb)
+		return NULL;
+	skb_reserve(skb, RUST_E1000_RX_HEADROOM);
+	__skb_put(skb, len);
+	skb_mark_for_recycle(skb);
+	return skb;
+}
+
+void rust_e1000_skb_add_rx_frag(struct sk_buff *skb, struct page *page,
+				unsigned int offset, unsigned int buf_size,
+				unsigned int len)
+{
+	skb_add_rx_frag(skb, skb_shinfo(skb)->nr_frags, page,
+			offset + RUST_E1000_RX_HEADROOM, len,
+			rust_e1000_rx_truesize(buf_size));
+}
+
+void rust_e1000_init_delayed_work(struct delayed_work *dwork, work_func_t func)
+{
+	INIT_DELAYED_WORK(dwork, func);
+}
+
+bool rust_e1000_schedule_delayed_work(struct delayed_work *dwork,
+				      unsigned int msecs)
+{
+	return schedule_delayed_work(dwork, msecs_to_jiffies(msecs));
+}
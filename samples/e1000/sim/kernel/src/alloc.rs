@@ -0,0 +1,79 @@
+//! `KVec` backed by `std::vec::Vec`.
+
+use core::ops::{Deref, DerefMut};
+
+pub mod flags {
+    /// Allocation flags; ignored on the host.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Flags(u32);
+
+    pub const GFP_ATOMIC: Flags = Flags(0);
+    pub const GFP_KERNEL: Flags = Flags(1);
+}
+
+use flags::Flags;
+
+/// The allocation failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocError;
+
+/// A vector with the fallible, flag-taking API of the kernel's `KVec`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct KVec<T>(Vec<T>);
+
+impl<T> KVec<T> {
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn with_capacity(capacity: usize, _flags: Flags) -> Result<Self, AllocError> {
+        Ok(Self(Vec::with_capacity(capacity)))
+    }
+
+    pub fn push(&mut self, v: T, _flags: Flags) -> Result<(), AllocError> {
+        self.0.push(v);
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.0.pop()
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear()
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.0.truncate(len)
+    }
+}
+
+impl<T: Clone> KVec<T> {
+    pub fn extend_from_slice(&mut self, other: &[T], _flags: Flags) -> Result<(), AllocError> {
+        self.0.extend_from_slice(other);
+        Ok(())
+    }
+}
+
+impl<T> Deref for KVec<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for KVec<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.0
+    }
+}
+
+impl<'a, T> IntoIterator for &'a KVec<T> {
+    type Item = &'a T;
+    type IntoIter = core::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
@@ -0,0 +1,35 @@
+//! Errno-style errors.
+
+use crate::alloc::AllocError;
+
+/// A kernel error code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Error(i32);
+
+impl Error {
+    /// The negative errno value, as returned to C.
+    pub fn to_errno(self) -> i32 {
+        self.0
+    }
+}
+
+impl From<AllocError> for Error {
+    fn from(_: AllocError) -> Self {
+        code::ENOMEM
+    }
+}
+
+pub type Result<T = (), E = Error> = core::result::Result<T, E>;
+
+pub mod code {
+    use super::Error;
+
+    pub const EIO: Error = Error(-5);
+    pub const ENXIO: Error = Error(-6);
+    pub const ENOMEM: Error = Error(-12);
+    pub const EBUSY: Error = Error(-16);
+    pub const ENODEV: Error = Error(-19);
+    pub const EINVAL: Error = Error(-22);
+    pub const ENOSPC: Error = Error(-28);
+    pub const ETIMEDOUT: Error = Error(-110);
+}
@@ -0,0 +1,94 @@
+//! Memory mapped I/O.
+//!
+//! The kernel checks offsets against the known size of a mapping at build
+//! time (`build_assert!`); here the check happens at run time instead.
+
+use crate::error::{Result, code::EINVAL};
+use core::ptr::{read_volatile, write_volatile};
+
+/// The address and size of an I/O mapping, at least `SIZE` bytes long.
+pub struct MmioRaw<const SIZE: usize = 0> {
+    addr: usize,
+    maxsize: usize,
+}
+
+impl<const SIZE: usize> MmioRaw<SIZE> {
+    pub fn new(addr: usize, maxsize: usize) -> Result<Self> {
+        if maxsize < SIZE || !addr.is_multiple_of(4) {
+            return Err(EINVAL);
+        }
+        Ok(Self { addr, maxsize })
+    }
+
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    pub fn maxsize(&self) -> usize {
+        self.maxsize
+    }
+}
+
+/// An I/O mapping, accessed through [`Io`].
+#[repr(transparent)]
+pub struct Mmio<const SIZE: usize = 0>(MmioRaw<SIZE>);
+
+impl<const SIZE: usize> Mmio<SIZE> {
+    /// # Safety
+    ///
+    /// `raw` must describe a valid mapping for the lifetime of the result.
+    pub unsafe fn from_raw(raw: &MmioRaw<SIZE>) -> &Self {
+        // SAFETY: `Mmio` is a transparent wrapper around `MmioRaw`.
+        unsafe { &*(raw as *const MmioRaw<SIZE>).cast::<Self>() }
+    }
+}
+
+/// Register accessors of an I/O mapping.
+pub trait Io {
+    fn addr(&self) -> usize;
+
+    fn maxsize(&self) -> usize;
+
+    /// Read at `offset`, which must be known to be in bounds.
+    fn read32(&self, offset: usize) -> u32 {
+        self.try_read32(offset).expect("I/O offset out of bounds")
+    }
+
+    /// Write at `offset`, which must be known to be in bounds.
+    fn write32(&self, value: u32, offset: usize) {
+        self.try_write32(value, offset)
+            .expect("I/O offset out of bounds")
+    }
+
+    fn try_read32(&self, offset: usize) -> Result<u32> {
+        check::<u32>(offset, self.maxsize())?;
+        // SAFETY: In bounds and aligned, checked above.
+        Ok(unsafe { read_volatile((self.addr() + offset) as *const u32) })
+    }
+
+    fn try_write32(&self, value: u32, offset: usize) -> Result {
+        check::<u32>(offset, self.maxsize())?;
+        // SAFETY: In bounds and aligned, checked above.
+        unsafe { write_volatile((self.addr() + offset) as *mut u32, value) };
+        Ok(())
+    }
+}
+
+impl<const SIZE: usize> Io for Mmio<SIZE> {
+    fn addr(&self) -> usize {
+        self.0.addr
+    }
+
+    fn maxsize(&self) -> usize {
+        self.0.maxsize
+    }
+}
+
+fn check<T>(offset: usize, maxsize: usize) -> Result {
+    let size = size_of::<T>();
+    if offset.is_multiple_of(size) && offset.checked_add(size).is_some_and(|end| end <= maxsize) {
+        Ok(())
+    } else {
+        Err(EINVAL)
+    }
+}
@@ -0,0 +1,17 @@
+//! Host stand-in for the parts of the kernel crate used by `src/e1000`.
+//!
+//! Only what the driver core needs is provided, with the same names and
+//! signatures, so the core compiles unchanged against it.
+
+pub mod alloc;
+pub mod error;
+pub mod io;
+pub mod print;
+pub mod sync;
+pub mod transmute;
+
+pub mod prelude {
+    pub use crate::alloc::{KVec, flags::*};
+    pub use crate::error::{Error, Result, code::*};
+    pub use crate::{pr_debug, pr_err, pr_info, pr_warn};
+}
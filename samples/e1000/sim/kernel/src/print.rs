@@ -0,0 +1,26 @@
+//! `pr_*` macros, printed to stderr (captured by the test harness).
+
+#[doc(hidden)]
+pub fn log(level: &str, args: core::fmt::Arguments<'_>) {
+    eprintln!("[{level}] {args}");
+}
+
+#[macro_export]
+macro_rules! pr_err {
+    ($($arg:tt)*) => { $crate::print::log("err", format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! pr_warn {
+    ($($arg:tt)*) => { $crate::print::log("warn", format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! pr_info {
+    ($($arg:tt)*) => { $crate::print::log("info", format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! pr_debug {
+    ($($arg:tt)*) => { $crate::print::log("debug", format_args!($($arg)*)) };
+}
@@ -0,0 +1,28 @@
+//! `Arc` backed by `std::sync::Arc`.
+
+use crate::alloc::{AllocError, flags::Flags};
+use core::ops::Deref;
+
+/// A reference-counted pointer with the fallible, flag-taking constructor
+/// of the kernel's `Arc`.
+pub struct Arc<T>(std::sync::Arc<T>);
+
+impl<T> Arc<T> {
+    pub fn new(contents: T, _flags: Flags) -> Result<Self, AllocError> {
+        Ok(Self(std::sync::Arc::new(contents)))
+    }
+}
+
+impl<T> Clone for Arc<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Deref for Arc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
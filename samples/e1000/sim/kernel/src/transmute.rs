@@ -0,0 +1,28 @@
+//! Traits for types that can be converted to and from raw bytes.
+
+/// Types for which any bit pattern is a valid value.
+///
+/// # Safety
+///
+/// All bit patterns must be valid for this type.
+pub unsafe trait FromBytes {}
+
+/// Types that can be viewed as bytes: no padding, no interior mutability.
+///
+/// # Safety
+///
+/// Values of this type may not contain any uninitialized bytes.
+pub unsafe trait AsBytes {}
+
+macro_rules! impl_bytes {
+    ($($t:ty),*) => {
+        $(
+            // SAFETY: Plain integers have no padding and no invalid values.
+            unsafe impl FromBytes for $t {}
+            // SAFETY: Plain integers have no padding and no invalid values.
+            unsafe impl AsBytes for $t {}
+        )*
+    };
+}
+
+impl_bytes!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
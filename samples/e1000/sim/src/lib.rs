@@ -0,0 +1,14 @@
+//! Host-side test harness for the e1000 driver core.
+//!
+//! The hardware-independent part of the driver (`src/e1000`) is compiled
+//! unchanged against the [`kernel`] shim, and [`model`] stands in for the
+//! NIC: an in-memory register file, heap "DMA" memory and a software model
+//! that consumes TX descriptors and produces RX descriptors.
+
+#[path = "../../src/e1000/mod.rs"]
+pub mod e1000;
+
+#[path = "../../src/utils.rs"]
+mod utils;
+
+pub mod model;
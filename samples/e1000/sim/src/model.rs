@@ -0,0 +1,1169 @@
+//! Software model of the parts of an 8254x the driver core talks to.
+//!
+//! Nothing runs concurrently: the test drives the driver, then calls
+//! [`SimNic::process_tx`] / [`SimNic::inject_rx`] to play the hardware's
+//! part, then drives the driver again. Register side effects the driver
+//! busy-waits for (e.g. EEPROM reads) happen in [`Hardware::tick`], which
+//! [`MockKernel`] runs on every `udelay` and `usleep_range`.
+
+use crate::e1000::{
+    DmaBuffer, DmaMapping, E1000Config, E1000Device, E1000Regs, KernelFunc, RxBuffer, RxFrame,
+    RxPool, TxFrame,
+};
+use kernel::prelude::*;
+use kernel::transmute::{AsBytes, FromBytes};
+use std::alloc::{Layout, alloc_zeroed, dealloc};
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use std::ptr::{read_volatile, write_volatile};
+use std::rc::Rc;
+
+/// Size of the memory mapped register space (BAR 0).
+pub const MMIO_SIZE: usize = 0x20000;
+
+const PAGE_SIZE: usize = 4096;
+
+/// Register byte offsets, spelled out here rather than taken from the
+/// driver so that a wrong constant in the driver shows up as a failure.
+pub mod reg {
+    pub const CTRL: usize = 0x00000;
+    pub const STATUS: usize = 0x00008;
+    pub const EERD: usize = 0x00014;
+    pub const CTRL_EXT: usize = 0x00018;
+    pub const VET: usize = 0x00038;
+    pub const ICR: usize = 0x000C0;
+    pub const ITR: usize = 0x000C4;
+    pub const IMS: usize = 0x000D0;
+    pub const IMC: usize = 0x000D8;
+    pub const RCTL: usize = 0x00100;
+    pub const TCTL: usize = 0x00400;
+    pub const RDBAL: usize = 0x02800;
+    pub const RDBAH: usize = 0x02804;
+    pub const RDLEN: usize = 0x02808;
+    pub const RDH: usize = 0x02810;
+    pub const RDT: usize = 0x02818;
+    pub const RDTR: usize = 0x02820;
+    pub const RADV: usize = 0x0282C;
+    pub const TDBAL: usize = 0x03800;
+    pub const TDBAH: usize = 0x03804;
+    pub const TDLEN: usize = 0x03808;
+    pub const TDH: usize = 0x03810;
+    pub const TDT: usize = 0x03818;
+    pub const TIDV: usize = 0x03820;
+    pub const TADV: usize = 0x0382C;
+    pub const CRCERRS: usize = 0x04000;
+    pub const MPC: usize = 0x04010;
+    pub const GPRC: usize = 0x04074;
+    pub const GPTC: usize = 0x04080;
+    pub const GORCL: usize = 0x04088;
+    pub const GORCH: usize = 0x0408C;
+    pub const ROC: usize = 0x040AC;
+    pub const TSCTC: usize = 0x040F8;
+    pub const TSCTFC: usize = 0x040FC;
+    pub const RXCSUM: usize = 0x05000;
+    pub const MTA: usize = 0x05200;
+    pub const RAL0: usize = 0x05400;
+    pub const RAH0: usize = 0x05404;
+    pub const VFTA: usize = 0x05600;
+}
+
+const DESC_SIZE: usize = 16;
+
+const TXD_CMD_EOP: u8 = 0x01;
+const TXD_CMD_RS: u8 = 0x08;
+const TXD_CMD_TSE: u8 = 0x04;
+const TXD_CMD_DEXT: u8 = 0x20;
+const TXD_CMD_VLE: u8 = 0x40;
+const TXD_DTYP_MASK: u8 = 0xf0;
+const TXD_DTYP_D: u8 = 0x10;
+const TXD_POPTS_IXSM: u8 = 0x01;
+const TXD_POPTS_TXSM: u8 = 0x02;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_PSH: u8 = 0x08;
+const TXD_STAT_DD: u8 = 0x01;
+
+const RXD_STAT_DD: u8 = 0x01;
+const RXD_STAT_EOP: u8 = 0x02;
+const RXD_STAT_VP: u8 = 0x08;
+const RXD_STAT_TCPCS: u8 = 0x20;
+const RXD_STAT_IPCS: u8 = 0x40;
+
+/// RX descriptor error bits, as passed to [`SimNic::inject_rx_error`].
+pub mod rxd_err {
+    pub const CE: u8 = 0x01;
+    pub const SE: u8 = 0x02;
+    pub const TCPE: u8 = 0x20;
+    pub const IPE: u8 = 0x40;
+    pub const RXE: u8 = 0x80;
+}
+
+const EERD_START: u32 = 0x01;
+const EERD_DONE: u32 = 0x10;
+const EERD_DONE_82574: u32 = 0x02;
+
+const CTRL_GIO_MASTER_DISABLE: u32 = 1 << 2;
+const CTRL_RST: u32 = 1 << 26;
+const CTRL_VME: u32 = 1 << 30;
+const CTRL_EXT_EE_RST: u32 = 1 << 13;
+
+const STATUS_FD: u32 = 0x01;
+const STATUS_LU: u32 = 0x02;
+const STATUS_GIO_MASTER_EN: u32 = 1 << 19;
+
+/// Address Valid bit in RAH.
+pub const RAH_AV: u32 = 1 << 31;
+
+const ICR_LSC: u32 = 0x04;
+const ICR_RXT0: u32 = 0x80;
+const ICR_TXDW: u32 = 0x01;
+
+const RCTL_LPE: u32 = 1 << 5;
+const RCTL_VFE: u32 = 1 << 18;
+const RCTL_BSEX: u32 = 1 << 25;
+
+const RXCSUM_IPOFL: u32 = 0x100;
+const RXCSUM_TUOFL: u32 = 0x200;
+
+/// A page aligned, zeroed heap allocation.
+struct HostMem {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+impl HostMem {
+    fn new(size: usize) -> Self {
+        let layout = Layout::from_size_align(size, PAGE_SIZE).unwrap();
+        // SAFETY: `layout` has a non-zero size.
+        let ptr = unsafe { alloc_zeroed(layout) };
+        assert!(!ptr.is_null(), "host allocation failed");
+        Self { ptr, layout }
+    }
+}
+
+impl Drop for HostMem {
+    fn drop(&mut self) {
+        // SAFETY: `ptr` was allocated with `layout`.
+        unsafe { dealloc(self.ptr, self.layout) };
+    }
+}
+
+/// In-memory register file handed to the driver as its "mapped BAR".
+///
+/// Clones share the same registers.
+#[derive(Clone)]
+pub struct RegisterFile {
+    mem: Rc<HostMem>,
+}
+
+impl RegisterFile {
+    pub fn new() -> Self {
+        Self {
+            mem: Rc::new(HostMem::new(MMIO_SIZE)),
+        }
+    }
+
+    /// The register handle to pass to `E1000Device::new`.
+    pub fn base(&self) -> E1000Regs {
+        // SAFETY: Devices built in a test do not outlive its `SimNic`.
+        unsafe { E1000Regs::new(self.mem.ptr as usize, MMIO_SIZE) }.unwrap()
+    }
+
+    pub fn read(&self, offset: usize) -> u32 {
+        assert!(offset + 4 <= MMIO_SIZE && offset.is_multiple_of(4));
+        // SAFETY: In bounds and aligned, checked above.
+        unsafe { read_volatile(self.mem.ptr.add(offset).cast::<u32>()) }
+    }
+
+    pub fn write(&self, offset: usize, value: u32) {
+        assert!(offset + 4 <= MMIO_SIZE && offset.is_multiple_of(4));
+        // SAFETY: In bounds and aligned, checked above.
+        unsafe { write_volatile(self.mem.ptr.add(offset).cast::<u32>(), value) }
+    }
+}
+
+impl Default for RegisterFile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Bookkeeping shared between a [`MockKernel`] and the test.
+#[derive(Default)]
+pub struct DmaState {
+    /// Address and size of each allocation not freed yet.
+    live: Vec<(usize, usize)>,
+    /// Number of `dma_alloc_coherent` calls so far.
+    pub allocs: usize,
+    /// Number of `dma_free_coherent` calls so far.
+    pub frees: usize,
+    /// Fail the allocation made when `allocs` reaches this count.
+    pub fail_at: Option<usize>,
+    /// Address of each streaming mapping not unmapped yet.
+    mapped: Vec<usize>,
+    /// Number of `dma_map` calls so far.
+    pub maps: usize,
+    /// Fail the mapping made when `maps` reaches this count.
+    pub fail_map_at: Option<usize>,
+    /// Number of RX buffers not freed yet.
+    pub rx_live: usize,
+    /// Number of RX buffer allocations so far.
+    pub rx_allocs: usize,
+    /// Fail the RX buffer allocations made from when `rx_allocs` reaches
+    /// this count.
+    pub fail_rx_alloc_from: Option<usize>,
+    /// Number of RX buffers handed back to the NIC for reuse.
+    pub rx_recycles: usize,
+}
+
+impl DmaState {
+    /// Number of allocations that have not been freed.
+    pub fn live(&self) -> usize {
+        self.live.len()
+    }
+
+    /// Total size of the allocations that have not been freed.
+    pub fn live_bytes(&self) -> usize {
+        self.live.iter().map(|&(_, size)| size).sum()
+    }
+
+    /// Number of streaming mappings that have not been unmapped.
+    pub fn mapped(&self) -> usize {
+        self.mapped.len()
+    }
+
+    /// Whether `ptr` points into a live allocation.
+    pub fn contains(&self, ptr: *const u8) -> bool {
+        self.live
+            .iter()
+            .any(|&(start, size)| (start..start + size).contains(&(ptr as usize)))
+    }
+}
+
+/// Heap memory handed out by [`MockKernel`] as DMA memory.
+///
+/// Dropping it frees it and records the free in the [`DmaState`].
+pub struct MockDmaBuffer<T> {
+    mem: HostMem,
+    count: usize,
+    dma: Rc<RefCell<DmaState>>,
+    _type: PhantomData<T>,
+}
+
+impl<T> DmaBuffer<T> for MockDmaBuffer<T> {
+    fn dma_handle(&self) -> usize {
+        self.mem.ptr as usize
+    }
+
+    fn as_slice(&self) -> &[T] {
+        // SAFETY: `mem` holds `count` zero initialised `T`s, for which any
+        // bit pattern is valid, and is aligned to a page.
+        unsafe { std::slice::from_raw_parts(self.mem.ptr.cast(), self.count) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        // SAFETY: As above, and `&mut self` makes the access exclusive.
+        unsafe { std::slice::from_raw_parts_mut(self.mem.ptr.cast(), self.count) }
+    }
+}
+
+impl<T> Drop for MockDmaBuffer<T> {
+    fn drop(&mut self) {
+        let mut dma = self.dma.borrow_mut();
+        let addr = self.mem.ptr as usize;
+        let i = dma
+            .live
+            .iter()
+            .position(|&(start, _)| start == addr)
+            .expect("freeing memory that was not allocated");
+        dma.live.swap_remove(i);
+        dma.frees += 1;
+    }
+}
+
+/// A streaming mapping handed out by [`MockKernel`], the CPU address of the
+/// mapped memory.
+///
+/// Dropping it records the unmap in the [`DmaState`].
+pub struct MockDmaMapping {
+    addr: usize,
+    dma: Rc<RefCell<DmaState>>,
+}
+
+impl DmaMapping for MockDmaMapping {
+    fn dma_handle(&self) -> usize {
+        self.addr
+    }
+}
+
+impl Drop for MockDmaMapping {
+    fn drop(&mut self) {
+        let mut dma = self.dma.borrow_mut();
+        let i = dma
+            .mapped
+            .iter()
+            .position(|&addr| addr == self.addr)
+            .expect("unmapping memory that was not mapped");
+        dma.mapped.swap_remove(i);
+    }
+}
+
+/// The RX buffers of one ring, allocated as [`DmaState`] says.
+pub struct MockRxPool {
+    size: usize,
+    dma: Rc<RefCell<DmaState>>,
+}
+
+impl RxPool for MockRxPool {
+    type Buffer = MockRxBuffer;
+
+    fn alloc(&mut self) -> Result<MockRxBuffer> {
+        let mut dma = self.dma.borrow_mut();
+        if dma
+            .fail_rx_alloc_from
+            .is_some_and(|from| dma.rx_allocs >= from)
+        {
+            return Err(ENOMEM);
+        }
+        dma.rx_allocs += 1;
+        dma.rx_live += 1;
+        Ok(MockRxBuffer {
+            mem: HostMem::new(self.size),
+            size: self.size,
+            dma: self.dma.clone(),
+        })
+    }
+}
+
+/// A receive buffer handed out by [`MockRxPool`], heap memory whose CPU
+/// address is the bus address.
+///
+/// Dropping it records the free in the [`DmaState`].
+pub struct MockRxBuffer {
+    mem: HostMem,
+    size: usize,
+    dma: Rc<RefCell<DmaState>>,
+}
+
+impl RxBuffer for MockRxBuffer {
+    fn dma_handle(&self) -> usize {
+        self.mem.ptr as usize
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        // SAFETY: `mem` holds `size` zero initialised bytes.
+        unsafe { std::slice::from_raw_parts(self.mem.ptr, self.size) }
+    }
+
+    fn sync_for_cpu(&mut self, len: usize) {
+        assert!(len <= self.size);
+    }
+
+    fn sync_for_device(&mut self) {
+        self.dma.borrow_mut().rx_recycles += 1;
+    }
+}
+
+impl Drop for MockRxBuffer {
+    fn drop(&mut self) {
+        self.dma.borrow_mut().rx_live -= 1;
+    }
+}
+
+/// What became of a [`MockFrame`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameFate {
+    /// Still held by the driver.
+    Held,
+    /// Consumed, i.e. sent.
+    Consumed,
+    /// Dropped without being sent.
+    Dropped,
+}
+
+/// A frame for [`E1000Device::e1000_transmit_frame`], reporting what the
+/// driver did with it.
+///
+/// [`E1000Device::e1000_transmit_frame`]: crate::e1000::E1000Device::e1000_transmit_frame
+pub struct MockFrame {
+    frags: Vec<Vec<u8>>,
+    fate: Rc<Cell<FrameFate>>,
+}
+
+impl MockFrame {
+    pub fn new(frags: &[&[u8]]) -> (Self, Rc<Cell<FrameFate>>) {
+        let fate = Rc::new(Cell::new(FrameFate::Held));
+        let frame = MockFrame {
+            frags: frags.iter().map(|frag| frag.to_vec()).collect(),
+            fate: fate.clone(),
+        };
+        (frame, fate)
+    }
+}
+
+impl TxFrame for MockFrame {
+    fn fragments(&self) -> impl Iterator<Item = &[u8]> + Clone {
+        self.frags.iter().map(Vec::as_slice)
+    }
+
+    fn consume(self) {
+        self.fate.set(FrameFate::Consumed);
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for MockFrame {
+    fn drop(&mut self) {
+        self.fate.set(FrameFate::Dropped);
+    }
+}
+
+/// [`KernelFunc`] handing out page aligned heap memory as "DMA" memory.
+///
+/// The bus address is the CPU address, so the model can follow
+/// descriptor addresses directly.
+#[derive(Clone)]
+pub struct MockKernel {
+    pub dma: Rc<RefCell<DmaState>>,
+    /// Total time spent in `udelay`, in microseconds.
+    pub delayed_us: Rc<Cell<usize>>,
+    /// Total time spent in `usleep_range`, in microseconds.
+    pub slept_us: Rc<Cell<usize>>,
+    hw: Hardware,
+}
+
+impl MockKernel {
+    pub fn new(nic: &SimNic) -> Self {
+        Self {
+            dma: Rc::default(),
+            delayed_us: Rc::default(),
+            slept_us: Rc::default(),
+            hw: nic.hardware(),
+        }
+    }
+}
+
+impl KernelFunc for MockKernel {
+    type DmaBuffer<T: AsBytes + FromBytes> = MockDmaBuffer<T>;
+    type DmaMapping = MockDmaMapping;
+    type TxFrame = MockFrame;
+    type RxBuffer = MockRxBuffer;
+    type RxPool = MockRxPool;
+
+    fn dma_alloc_coherent<T: AsBytes + FromBytes>(
+        &mut self,
+        count: usize,
+    ) -> Result<MockDmaBuffer<T>> {
+        let mut dma = self.dma.borrow_mut();
+        if dma.fail_at == Some(dma.allocs) {
+            return Err(ENOMEM);
+        }
+        let size = count * size_of::<T>();
+        let mem = HostMem::new(size);
+        dma.allocs += 1;
+        dma.live.push((mem.ptr as usize, size));
+        Ok(MockDmaBuffer {
+            mem,
+            count,
+            dma: self.dma.clone(),
+            _type: PhantomData,
+        })
+    }
+
+    fn dma_map(&mut self, data: &[u8]) -> Result<MockDmaMapping> {
+        let mut dma = self.dma.borrow_mut();
+        if dma.fail_map_at == Some(dma.maps) {
+            return Err(ENOMEM);
+        }
+        let addr = data.as_ptr() as usize;
+        dma.maps += 1;
+        dma.mapped.push(addr);
+        Ok(MockDmaMapping {
+            addr,
+            dma: self.dma.clone(),
+        })
+    }
+
+    fn rx_pool(&mut self, _count: usize, size: usize) -> Result<MockRxPool> {
+        Ok(MockRxPool {
+            size,
+            dma: self.dma.clone(),
+        })
+    }
+
+    fn udelay(&self, us: usize) {
+        self.delayed_us.set(self.delayed_us.get() + us);
+        self.hw.tick();
+    }
+
+    fn usleep_range(&self, min_us: usize, _max_us: usize) {
+        self.slept_us.set(self.slept_us.get() + min_us);
+        self.hw.tick();
+    }
+}
+
+/// Number of 16-bit words in the simulated EEPROM.
+pub const EEPROM_WORDS: usize = 64;
+
+/// Reset bookkeeping and injected faults, shared by a [`SimNic`] and its
+/// [`Hardware`].
+#[derive(Default)]
+struct ResetState {
+    /// Number of device resets completed.
+    resets: Cell<usize>,
+    /// Keep CTRL.RST set, as a wedged device would.
+    stuck: Cell<bool>,
+    /// Never answer EEPROM reads.
+    no_eeprom: Cell<bool>,
+    /// Report bus mastering in STATUS.GIO_MASTER_EN, as PCIe parts do.
+    pcie: Cell<bool>,
+    /// Keep mastering the bus after CTRL.GIO_MASTER_DISABLE.
+    dma_stuck: Cell<bool>,
+    /// Lay EERD out as the 82574 does.
+    eerd_82574: Cell<bool>,
+}
+
+/// The register side effects of the NIC, shared with [`MockKernel`].
+#[derive(Clone)]
+pub struct Hardware {
+    regs: RegisterFile,
+    eeprom: Rc<RefCell<[u16; EEPROM_WORDS]>>,
+    reset: Rc<ResetState>,
+}
+
+impl Hardware {
+    /// Complete whatever the driver is waiting for.
+    pub fn tick(&self) {
+        let ctrl = self.regs.read(reg::CTRL);
+        if ctrl & CTRL_RST != 0 && !self.reset.stuck.get() {
+            // Receive and transmit stop, the rings start over and the
+            // statistics counters clear.
+            for r in [reg::RCTL, reg::TCTL, reg::RDH, reg::RDT, reg::TDH, reg::TDT] {
+                self.regs.write(r, 0);
+            }
+            for r in (reg::CRCERRS..=reg::TSCTFC).step_by(4) {
+                self.regs.write(r, 0);
+            }
+            self.regs
+                .write(reg::CTRL, ctrl & !(CTRL_RST | CTRL_GIO_MASTER_DISABLE));
+            self.reset.resets.set(self.reset.resets.get() + 1);
+            self.reset.dma_stuck.set(false);
+            if self.reset.pcie.get() {
+                self.set_status(STATUS_GIO_MASTER_EN, true);
+            }
+        } else if ctrl & CTRL_GIO_MASTER_DISABLE != 0 && !self.reset.dma_stuck.get() {
+            self.set_status(STATUS_GIO_MASTER_EN, false);
+        }
+        let ctrl_ext = self.regs.read(reg::CTRL_EXT);
+        if ctrl_ext & CTRL_EXT_EE_RST != 0 {
+            self.regs.write(reg::CTRL_EXT, ctrl_ext & !CTRL_EXT_EE_RST);
+        }
+        if self.reset.no_eeprom.get() {
+            return;
+        }
+        let (addr_shift, addr_mask, done) = if self.reset.eerd_82574.get() {
+            (2, 0x3fff, EERD_DONE_82574)
+        } else {
+            (8, 0xff, EERD_DONE)
+        };
+        let eerd = self.regs.read(reg::EERD);
+        if eerd & EERD_START != 0 && eerd & done == 0 {
+            let word = (eerd >> addr_shift) as usize & addr_mask;
+            let data = self.eeprom.borrow().get(word).copied().unwrap_or(0xffff);
+            let addr = eerd & (addr_mask as u32) << addr_shift;
+            self.regs
+                .write(reg::EERD, (data as u32) << 16 | done | addr);
+        }
+    }
+
+    fn set_status(&self, bits: u32, on: bool) {
+        let status = self.regs.read(reg::STATUS) & !bits;
+        self.regs
+            .write(reg::STATUS, if on { status | bits } else { status });
+    }
+}
+
+/// The NIC side of the rings.
+pub struct SimNic {
+    pub regs: RegisterFile,
+    eeprom: Rc<RefCell<[u16; EEPROM_WORDS]>>,
+    reset: Rc<ResetState>,
+    tx_partial: Vec<u8>,
+    /// DCMD and POPTS of the first data descriptor of `tx_partial`.
+    tx_partial_cmd: u8,
+    tx_partial_popts: u8,
+    tx_context: TxContext,
+    /// Number of context descriptors processed so far.
+    pub tx_contexts: usize,
+}
+
+/// Offsets and segmentation parameters loaded by the last TCP/IP
+/// context descriptor.
+#[derive(Default, Clone, Copy)]
+struct TxContext {
+    ipcss: usize,
+    ipcso: usize,
+    /// Last byte of the IP header.
+    ipcse: usize,
+    tucss: usize,
+    tucso: usize,
+    /// Last byte summed, zero for the end of the frame.
+    tucse: usize,
+    paylen: usize,
+    hdr_len: usize,
+    mss: usize,
+}
+
+impl SimNic {
+    pub fn new() -> Self {
+        Self {
+            regs: RegisterFile::new(),
+            eeprom: Rc::new(RefCell::new([0xffff; EEPROM_WORDS])),
+            reset: Rc::default(),
+            tx_partial: Vec::new(),
+            tx_partial_cmd: 0,
+            tx_partial_popts: 0,
+            tx_context: TxContext::default(),
+            tx_contexts: 0,
+        }
+    }
+
+    /// Probes a device on this NIC, and returns it with the mock kernel it
+    /// allocates from.
+    pub fn device(&self, config: E1000Config) -> (MockKernel, E1000Device<MockKernel>) {
+        let kfn = MockKernel::new(self);
+        let dev = E1000Device::new(kfn.clone(), self.regs.base(), config).unwrap();
+        (kfn, dev)
+    }
+
+    /// A handle for the side effects that happen while the driver waits.
+    pub fn hardware(&self) -> Hardware {
+        Hardware {
+            regs: self.regs.clone(),
+            eeprom: self.eeprom.clone(),
+            reset: self.reset.clone(),
+        }
+    }
+
+    /// Number of device resets completed so far.
+    pub fn resets(&self) -> usize {
+        self.reset.resets.get()
+    }
+
+    /// Make the device hang in reset (or recover from it).
+    pub fn set_stuck_in_reset(&self, stuck: bool) {
+        self.reset.stuck.set(stuck);
+    }
+
+    /// Behave like a PCIe part: report bus mastering in STATUS and stop it
+    /// on CTRL.GIO_MASTER_DISABLE.
+    pub fn set_pcie(&self) {
+        self.reset.pcie.set(true);
+        self.hardware().set_status(STATUS_GIO_MASTER_EN, true);
+    }
+
+    /// Keep DMA going after the driver asks it to stop, until a reset.
+    pub fn set_dma_stuck(&self) {
+        self.reset.dma_stuck.set(true);
+    }
+
+    /// Lay EERD out as the 82574 does: DONE in bit 1, the address in
+    /// bits 15:2.
+    pub fn set_eerd_82574(&self) {
+        self.reset.eerd_82574.set(true);
+    }
+
+    /// Make EEPROM reads never complete, as on a part without one.
+    pub fn remove_eeprom(&self) {
+        self.reset.no_eeprom.set(true);
+    }
+
+    /// Store `mac` in EEPROM words 0-2, little endian as on real parts.
+    pub fn set_eeprom_mac(&self, mac: [u8; 6]) {
+        let mut eeprom = self.eeprom.borrow_mut();
+        for (word, bytes) in eeprom.iter_mut().zip(mac.chunks_exact(2)) {
+            *word = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+    }
+
+    /// Preload RA[0] the way the hardware does from the EEPROM at power up.
+    pub fn set_ra0(&self, mac: [u8; 6]) {
+        self.regs.write(
+            reg::RAL0,
+            u32::from_le_bytes([mac[0], mac[1], mac[2], mac[3]]),
+        );
+        self.regs.write(
+            reg::RAH0,
+            u32::from_le_bytes([mac[4], mac[5], 0, 0]) | RAH_AV,
+        );
+    }
+
+    fn ring(&self, bal: usize, bah: usize, len: usize) -> (*mut u8, usize) {
+        let base = (self.regs.read(bah) as u64) << 32 | self.regs.read(bal) as u64;
+        (
+            base as usize as *mut u8,
+            self.regs.read(len) as usize / DESC_SIZE,
+        )
+    }
+
+    /// Consume every descriptor between TDH and TDT, like the DMA engine
+    /// would, and return the frames that were completed (EOP seen).
+    ///
+    /// Offloads are applied as asked for: checksums are inserted, and a
+    /// TSO frame comes back as the segments that went on the wire.
+    pub fn process_tx(&mut self) -> Vec<Vec<u8>> {
+        let (ring, count) = self.ring(reg::TDBAL, reg::TDBAH, reg::TDLEN);
+        let mut frames = Vec::new();
+        let mut head = self.regs.read(reg::TDH) as usize;
+        let tail = self.regs.read(reg::TDT) as usize;
+
+        while head != tail {
+            // SAFETY: The driver programmed TDBA/TDLEN to a live ring of `count` descriptors.
+            unsafe {
+                let desc = ring.add(head * DESC_SIZE);
+                let lower = read_volatile(desc.cast::<u64>());
+                let len = read_volatile(desc.add(8).cast::<u16>()) as usize;
+                let dtyp = read_volatile(desc.add(10)) & TXD_DTYP_MASK;
+                let cmd = read_volatile(desc.add(11));
+                let popts = read_volatile(desc.add(13));
+                if cmd & TXD_CMD_DEXT != 0 && dtyp != TXD_DTYP_D {
+                    // Context descriptor: IPCSS, IPCSO, IPCSE, TUCSS, TUCSO,
+                    // TUCSE, then PAYLEN, HDRLEN and MSS.
+                    self.tx_context = TxContext {
+                        ipcss: lower as u8 as usize,
+                        ipcso: (lower >> 8) as u8 as usize,
+                        ipcse: (lower >> 16) as u16 as usize,
+                        tucss: (lower >> 32) as u8 as usize,
+                        tucso: (lower >> 40) as u8 as usize,
+                        tucse: (lower >> 48) as u16 as usize,
+                        paylen: len | ((read_volatile(desc.add(10)) as usize & 0x0f) << 16),
+                        hdr_len: popts as usize,
+                        mss: read_volatile(desc.add(14).cast::<u16>()) as usize,
+                    };
+                    self.tx_contexts += 1;
+                } else {
+                    if self.tx_partial.is_empty() {
+                        // POPTS is taken from the first data descriptor.
+                        let dext = cmd & TXD_CMD_DEXT != 0;
+                        self.tx_partial_cmd = cmd;
+                        self.tx_partial_popts = if dext { popts } else { 0 };
+                    }
+                    let addr = lower as usize as *const u8;
+                    self.tx_partial
+                        .extend_from_slice(std::slice::from_raw_parts(addr, len));
+                    if cmd & TXD_CMD_EOP != 0 {
+                        let frame = std::mem::take(&mut self.tx_partial);
+                        let mut out = self.offload(frame);
+                        // VLE and the tag are taken from the EOP descriptor.
+                        if cmd & TXD_CMD_VLE != 0 && self.regs.read(reg::CTRL) & CTRL_VME != 0 {
+                            let tci = read_volatile(desc.add(14).cast::<u16>());
+                            out.iter_mut().for_each(|f| *f = vlan_insert(f, tci));
+                        }
+                        frames.extend(out);
+                    }
+                }
+                if cmd & TXD_CMD_RS != 0 {
+                    let status = desc.add(12);
+                    write_volatile(status, read_volatile(status) | TXD_STAT_DD);
+                }
+            }
+            head = (head + 1) % count;
+        }
+
+        self.regs.write(reg::TDH, head as u32);
+        if !frames.is_empty() {
+            self.raise(ICR_TXDW);
+        }
+        frames
+    }
+
+    /// Apply the offloads asked for by the descriptors of `frame`.
+    fn offload(&mut self, mut frame: Vec<u8>) -> Vec<Vec<u8>> {
+        let ctx = self.tx_context;
+        let popts = self.tx_partial_popts;
+        if self.tx_partial_cmd & TXD_CMD_DEXT != 0 && self.tx_partial_cmd & TXD_CMD_TSE != 0 {
+            self.count(reg::TSCTC, 1);
+            return segment(&frame, &ctx);
+        }
+        if popts & TXD_POPTS_IXSM != 0 {
+            insert_ip_checksum(&mut frame, &ctx);
+        }
+        if popts & TXD_POPTS_TXSM != 0 {
+            insert_checksum(&mut frame, ctx);
+        }
+        vec![frame]
+    }
+
+    /// Receive buffer size programmed in RCTL.BSIZE/BSEX.
+    pub fn rx_buffer_size(&self) -> usize {
+        let rctl = self.regs.read(reg::RCTL);
+        let bsize = (rctl >> 16) & 0b11;
+        if rctl & RCTL_BSEX == 0 {
+            2048 >> bsize
+        } else {
+            assert_ne!(bsize, 0, "reserved buffer size");
+            32768 >> bsize
+        }
+    }
+
+    /// Receive one frame into the descriptors starting at RDH, spreading
+    /// it over as many buffers as it needs (EOP on the last one).
+    ///
+    /// Returns false, like a real NIC dropping the frame, when there are
+    /// not enough free descriptors between RDH and RDT, or when the frame
+    /// is longer than 1522 bytes (with CRC) and RCTL.LPE is clear.
+    ///
+    /// IPv4 checksums are checked as enabled in RXCSUM. Tagged frames are
+    /// filtered through the VFTA if RCTL.VFE is set, and stripped of their
+    /// tag if CTRL.VME is.
+    pub fn inject_rx(&mut self, frame: &[u8]) -> bool {
+        self.inject_rx_error(frame, 0)
+    }
+
+    /// Like [`Self::inject_rx`], but report `errors` (see [`rxd_err`]) with
+    /// the frame, as if it had been damaged on the wire.
+    pub fn inject_rx_error(&mut self, frame: &[u8], errors: u8) -> bool {
+        if frame.len() + 4 > 1522 && self.regs.read(reg::RCTL) & RCTL_LPE == 0 {
+            self.count(reg::ROC, 1);
+            return false;
+        }
+        let stripped;
+        let (mut frame, mut vlan_status, mut tci) = (frame, 0, 0);
+        if frame.len() >= 16 && frame[12..14] == [0x81, 0x00] {
+            let tag = u16::from_be_bytes([frame[14], frame[15]]);
+            let vid = (tag & 0xfff) as usize;
+            let vfta = self.regs.read(reg::VFTA + 4 * (vid >> 5));
+            if self.regs.read(reg::RCTL) & RCTL_VFE != 0 && vfta & (1 << (vid & 31)) == 0 {
+                return false;
+            }
+            if self.regs.read(reg::CTRL) & CTRL_VME != 0 {
+                stripped = [&frame[..12], &frame[16..]].concat();
+                frame = &stripped;
+                vlan_status = RXD_STAT_VP;
+                tci = tag;
+            }
+        }
+
+        let (ring, count) = self.ring(reg::RDBAL, reg::RDBAH, reg::RDLEN);
+        let mut head = self.regs.read(reg::RDH) as usize;
+        let tail = self.regs.read(reg::RDT) as usize;
+        let free = (tail + count - head) % count;
+        let chunks: Vec<&[u8]> = frame.chunks(self.rx_buffer_size()).collect();
+        if chunks.len() > free {
+            return false;
+        }
+        let (csum_status, csum_errors) = self.rx_checksum(frame);
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            let eop = if i == chunks.len() - 1 {
+                RXD_STAT_EOP
+            } else {
+                0
+            };
+            // SAFETY: The driver programmed RDBA/RDLEN to a live ring of `count` descriptors,
+            // each pointing at a buffer of at least `rx_buffer_size()` bytes.
+            unsafe {
+                let desc = ring.add(head * DESC_SIZE);
+                let addr = read_volatile(desc.cast::<u64>()) as usize as *mut u8;
+                std::ptr::copy_nonoverlapping(chunk.as_ptr(), addr, chunk.len());
+                write_volatile(desc.add(8).cast::<u16>(), chunk.len() as u16);
+                if eop != 0 {
+                    write_volatile(desc.add(12), RXD_STAT_DD | eop | csum_status | vlan_status);
+                    write_volatile(desc.add(13), errors | csum_errors);
+                    write_volatile(desc.add(14).cast::<u16>(), tci);
+                } else {
+                    write_volatile(desc.add(12), RXD_STAT_DD);
+                    write_volatile(desc.add(13), 0);
+                }
+            }
+            head = (head + 1) % count;
+        }
+
+        self.regs.write(reg::RDH, head as u32);
+        self.raise(ICR_RXT0);
+        true
+    }
+
+    /// The checksum status and error bits for a received frame.
+    fn rx_checksum(&self, frame: &[u8]) -> (u8, u8) {
+        let rxcsum = self.regs.read(reg::RXCSUM);
+        let Some(ip) = frame.get(IP_START..IP_START + 20) else {
+            return (0, 0);
+        };
+        if frame[12..14] != [0x08, 0x00] || ip[0] != 0x45 {
+            return (0, 0);
+        }
+        let (mut status, mut errors) = (0, 0);
+        if rxcsum & RXCSUM_IPOFL != 0 {
+            status |= RXD_STAT_IPCS;
+            if ones_complement_sum(ip) != 0xffff {
+                errors |= rxd_err::IPE;
+            }
+        }
+        if rxcsum & RXCSUM_TUOFL != 0 && matches!(ip[9], IPPROTO_TCP | IPPROTO_UDP) {
+            status |= RXD_STAT_TCPCS;
+            if !l4_checksum_ok(frame) {
+                errors |= rxd_err::TCPE;
+            }
+        }
+        (status, errors)
+    }
+
+    /// Buffer address of TX descriptor `index`.
+    pub fn tx_addr(&self, index: usize) -> usize {
+        let (ring, count) = self.ring(reg::TDBAL, reg::TDBAH, reg::TDLEN);
+        assert!(index < count);
+        // SAFETY: The driver programmed TDBA/TDLEN to a live ring of `count` descriptors.
+        unsafe { read_volatile(ring.add(index * DESC_SIZE).cast::<u64>()) as usize }
+    }
+
+    /// Command byte of TX descriptor `index`.
+    pub fn tx_cmd(&self, index: usize) -> u8 {
+        let (ring, count) = self.ring(reg::TDBAL, reg::TDBAH, reg::TDLEN);
+        assert!(index < count);
+        // SAFETY: The driver programmed TDBA/TDLEN to a live ring of `count` descriptors.
+        unsafe { read_volatile(ring.add(index * DESC_SIZE + 11)) }
+    }
+
+    /// Status byte of TX descriptor `index`.
+    pub fn tx_status(&self, index: usize) -> u8 {
+        let (ring, count) = self.ring(reg::TDBAL, reg::TDBAH, reg::TDLEN);
+        assert!(index < count);
+        // SAFETY: The driver programmed TDBA/TDLEN to a live ring of `count` descriptors.
+        unsafe { read_volatile(ring.add(index * DESC_SIZE + 12)) }
+    }
+
+    /// Overwrite the status byte of TX descriptor `index`, e.g. to undo a
+    /// write-back that has not happened yet.
+    pub fn set_tx_status(&self, index: usize, status: u8) {
+        let (ring, count) = self.ring(reg::TDBAL, reg::TDBAH, reg::TDLEN);
+        assert!(index < count);
+        // SAFETY: The driver programmed TDBA/TDLEN to a live ring of `count` descriptors.
+        unsafe { write_volatile(ring.add(index * DESC_SIZE + 12), status) }
+    }
+
+    /// Buffer address of RX descriptor `index`.
+    pub fn rx_addr(&self, index: usize) -> usize {
+        let (ring, count) = self.ring(reg::RDBAL, reg::RDBAH, reg::RDLEN);
+        assert!(index < count);
+        // SAFETY: The driver programmed RDBA/RDLEN to a live ring of `count` descriptors.
+        unsafe { read_volatile(ring.add(index * DESC_SIZE).cast::<u64>()) as usize }
+    }
+
+    /// Status byte of RX descriptor `index`.
+    pub fn rx_status(&self, index: usize) -> u8 {
+        let (ring, count) = self.ring(reg::RDBAL, reg::RDBAH, reg::RDLEN);
+        assert!(index < count);
+        // SAFETY: The driver programmed RDBA/RDLEN to a live ring of `count` descriptors.
+        unsafe { read_volatile(ring.add(index * DESC_SIZE + 12)) }
+    }
+
+    /// Overwrite the status byte of RX descriptor `index`, e.g. to model a
+    /// write-back that has not happened yet.
+    pub fn set_rx_status(&self, index: usize, status: u8) {
+        let (ring, count) = self.ring(reg::RDBAL, reg::RDBAH, reg::RDLEN);
+        assert!(index < count);
+        // SAFETY: The driver programmed RDBA/RDLEN to a live ring of `count` descriptors.
+        unsafe { write_volatile(ring.add(index * DESC_SIZE + 12), status) }
+    }
+
+    /// Bring the link up at `mbps` (10, 100 or 1000) or, with `None`, take
+    /// it down, and signal a link status change.
+    pub fn set_link(&mut self, link: Option<(u32, bool)>) {
+        let status = match link {
+            None => 0,
+            Some((mbps, full_duplex)) => {
+                let speed = match mbps {
+                    10 => 0x00,
+                    100 => 0x40,
+                    1000 => 0x80,
+                    _ => panic!("unsupported speed {mbps}"),
+                };
+                STATUS_LU | speed | if full_duplex { STATUS_FD } else { 0 }
+            }
+        };
+        let gio = self.regs.read(reg::STATUS) & STATUS_GIO_MASTER_EN;
+        self.regs.write(reg::STATUS, status | gio);
+        self.raise(ICR_LSC);
+    }
+
+    /// Add `n` to a 32-bit statistics register.
+    pub fn count(&self, offset: usize, n: u32) {
+        self.regs
+            .write(offset, self.regs.read(offset).wrapping_add(n));
+    }
+
+    /// Clear the statistics registers, as reading them does on real hardware.
+    pub fn clear_stats(&self) {
+        for offset in (reg::CRCERRS..=reg::TSCTFC).step_by(4) {
+            self.regs.write(offset, 0);
+        }
+    }
+
+    /// Set interrupt cause bits, as the hardware does whether or not they are masked.
+    pub fn raise(&mut self, causes: u32) {
+        self.regs.write(reg::ICR, self.regs.read(reg::ICR) | causes);
+    }
+}
+
+impl Default for SimNic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Store the complement of the ones' complement sum over the context's
+/// range, which includes the seed already in the checksum field.
+fn insert_checksum(frame: &mut [u8], ctx: TxContext) {
+    let end = if ctx.tucse == 0 {
+        frame.len()
+    } else {
+        ctx.tucse + 1
+    };
+    let csum = !ones_complement_sum(&frame[ctx.tucss..end]);
+    frame[ctx.tucso..ctx.tucso + 2].copy_from_slice(&csum.to_be_bytes());
+}
+
+/// `frame` with an 802.1Q tag carrying `tci` after the MAC addresses.
+pub fn vlan_insert(frame: &[u8], tci: u16) -> Vec<u8> {
+    let [t0, t1] = tci.to_be_bytes();
+    [&frame[..12], &[0x81, 0x00, t0, t1], &frame[12..]].concat()
+}
+
+/// Compute the IP header checksum over IPCSS..=IPCSE into IPCSO.
+fn insert_ip_checksum(frame: &mut [u8], ctx: &TxContext) {
+    frame[ctx.ipcso..ctx.ipcso + 2].fill(0);
+    let csum = !ones_complement_sum(&frame[ctx.ipcss..=ctx.ipcse]);
+    frame[ctx.ipcso..ctx.ipcso + 2].copy_from_slice(&csum.to_be_bytes());
+}
+
+/// Cut a TSO frame into segments of MSS payload bytes behind copies of
+/// the headers, with IP length and id, TCP sequence number, flags and
+/// both checksums fixed up the way the 8254x does.
+fn segment(frame: &[u8], ctx: &TxContext) -> Vec<Vec<u8>> {
+    let (headers, payload) = frame.split_at(ctx.hdr_len);
+    assert_eq!(payload.len(), ctx.paylen, "PAYLEN does not match the frame");
+    let (ip, tcp) = (ctx.ipcss, ctx.tucss);
+    let id = u16::from_be_bytes([headers[ip + 4], headers[ip + 5]]);
+    let seq = u32::from_be_bytes(headers[tcp + 4..tcp + 8].try_into().unwrap());
+    let last = payload.len().div_ceil(ctx.mss) - 1;
+
+    payload
+        .chunks(ctx.mss)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut seg = [headers, chunk].concat();
+            let ip_len = (seg.len() - ip) as u16;
+            seg[ip + 2..ip + 4].copy_from_slice(&ip_len.to_be_bytes());
+            seg[ip + 4..ip + 6].copy_from_slice(&id.wrapping_add(i as u16).to_be_bytes());
+            let seq = seq.wrapping_add((i * ctx.mss) as u32);
+            seg[tcp + 4..tcp + 8].copy_from_slice(&seq.to_be_bytes());
+            if i != last {
+                seg[tcp + 13] &= !(TCP_FIN | TCP_PSH);
+            }
+            insert_ip_checksum(&mut seg, ctx);
+
+            // The seed in the TCP checksum leaves out the length.
+            let tcp_len = (seg.len() - tcp) as u32;
+            let mut sum = ones_complement_sum(&seg[tcp..]) as u32 + tcp_len;
+            while sum > 0xffff {
+                sum = (sum & 0xffff) + (sum >> 16);
+            }
+            seg[ctx.tucso..ctx.tucso + 2].copy_from_slice(&(!(sum as u16)).to_be_bytes());
+            seg
+        })
+        .collect()
+}
+
+/// The 16-bit ones' complement sum of `data`, as used by the IP, TCP and
+/// UDP checksums.
+pub fn ones_complement_sum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|w| u16::from_be_bytes([w[0], *w.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+/// Offset of the IPv4 header in the frames built by [`ipv4_frame`].
+pub const IP_START: usize = 14;
+/// Offset of the TCP/UDP header in the frames built by [`ipv4_frame`].
+pub const L4_START: usize = IP_START + 20;
+pub const IPPROTO_TCP: u8 = 6;
+pub const IPPROTO_UDP: u8 = 17;
+
+/// Offset of the checksum field in a TCP or UDP header at [`L4_START`].
+pub fn l4_csum_offset(proto: u8) -> usize {
+    match proto {
+        IPPROTO_TCP => L4_START + 16,
+        IPPROTO_UDP => L4_START + 6,
+        _ => panic!("no checksum for protocol {proto}"),
+    }
+}
+
+/// The IPv4 pseudo-header of a frame built by [`ipv4_frame`].
+fn pseudo_header(frame: &[u8]) -> Vec<u8> {
+    let mut ph = frame[IP_START + 12..IP_START + 20].to_vec();
+    ph.extend_from_slice(&[0, frame[IP_START + 9]]);
+    ph.extend_from_slice(&((frame.len() - L4_START) as u16).to_be_bytes());
+    ph
+}
+
+/// A TCP or UDP over IPv4 frame of `len` bytes, tagged with `seq`, with a
+/// valid IP header checksum. The L4 checksum field holds the pseudo-header
+/// sum, the way the stack leaves it for the NIC to finish; see
+/// [`finish_l4_checksum`].
+pub fn ipv4_frame(seq: u32, len: usize, proto: u8) -> Vec<u8> {
+    let mut frame = test_frame(seq, len);
+    frame[12..14].copy_from_slice(&[0x08, 0x00]);
+    let ip = &mut frame[IP_START..L4_START];
+    ip[0] = 0x45;
+    ip[2..4].copy_from_slice(&((len - IP_START) as u16).to_be_bytes());
+    ip[9] = proto;
+    ip[10..12].fill(0);
+    let ip_csum = !ones_complement_sum(ip);
+    ip[10..12].copy_from_slice(&ip_csum.to_be_bytes());
+
+    let csum = l4_csum_offset(proto);
+    frame[csum..csum + 2].fill(0);
+    let seed = ones_complement_sum(&pseudo_header(&frame));
+    frame[csum..csum + 2].copy_from_slice(&seed.to_be_bytes());
+    frame
+}
+
+/// Complete the L4 checksum of a frame built by [`ipv4_frame`], as the
+/// sender's stack or NIC would.
+pub fn finish_l4_checksum(frame: &mut [u8]) {
+    let tucso = l4_csum_offset(frame[IP_START + 9]);
+    insert_checksum(
+        frame,
+        TxContext {
+            tucss: L4_START,
+            tucso,
+            ..TxContext::default()
+        },
+    );
+}
+
+/// Whether the TCP/UDP checksum of a frame built by [`ipv4_frame`] is correct.
+pub fn l4_checksum_ok(frame: &[u8]) -> bool {
+    let mut data = pseudo_header(frame);
+    data.extend_from_slice(&frame[L4_START..]);
+    ones_complement_sum(&data) == 0xffff
+}
+
+/// Copy a received frame out of the buffers it was lent from.
+pub fn frame_bytes(frame: &RxFrame<'_, MockKernel>) -> Vec<u8> {
+    frame.segments().flatten().copied().collect()
+}
+
+/// A deterministic test frame of `len` bytes, tagged with `seq`.
+pub fn test_frame(seq: u32, len: usize) -> Vec<u8> {
+    assert!(len >= 4);
+    let mut frame: Vec<u8> = (0..len).map(|i| (i as u8).wrapping_mul(31)).collect();
+    frame[..4].copy_from_slice(&seq.to_be_bytes());
+    frame
+}
@@ -0,0 +1,167 @@
+//! Interrupt moderation.
+
+mod common;
+
+use common::setup;
+use e1000_sim::e1000::{E1000_MAX_DELAY_US, E1000Coalesce, E1000Config, E1000Device};
+use e1000_sim::model::{MockKernel, SimNic, reg, test_frame};
+use kernel::error::code::EINVAL;
+
+const TXD_CMD_IDE: u8 = 0x80;
+
+/// ITR, RDTR, RADV, TIDV, TADV
+fn timers(nic: &SimNic) -> [u32; 5] {
+    [reg::ITR, reg::RDTR, reg::RADV, reg::TIDV, reg::TADV].map(|r| nic.regs.read(r))
+}
+
+/// ITR value for `rate` interrupts per second (256ns units)
+fn itr(rate: u32) -> u32 {
+    1_000_000_000 / (rate * 256)
+}
+
+fn adaptive() -> E1000Coalesce {
+    E1000Coalesce {
+        adaptive: true,
+        ..E1000Coalesce::default()
+    }
+}
+
+/// Send `n` frames of `len` bytes and complete them.
+fn send(nic: &mut SimNic, dev: &mut E1000Device<MockKernel>, n: u32, len: usize) {
+    for seq in 0..n {
+        assert_eq!(dev.tx.e1000_transmit(&test_frame(seq, len)), len as i32);
+    }
+    nic.process_tx();
+}
+
+#[test]
+fn defaults_are_programmed() {
+    let (nic, _, dev) = setup(E1000Config::default());
+    assert_eq!(dev.hw.coalesce(), E1000Coalesce::default());
+    // 20000 interrupts/s, then 0, 8, 8 and 32us in 1.024us units
+    assert_eq!(timers(&nic), [195, 0, 8, 8, 31]);
+}
+
+#[test]
+fn settings_change_at_runtime() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    let coalesce = E1000Coalesce {
+        itr: 8000,
+        adaptive: false,
+        rx_delay_us: 32,
+        rx_abs_delay_us: 128,
+        tx_delay_us: 0,
+        tx_abs_delay_us: 1024,
+    };
+    dev.set_coalesce(coalesce).unwrap();
+    assert_eq!(dev.hw.coalesce(), coalesce);
+    assert_eq!(timers(&nic), [488, 31, 125, 0, 1000]);
+
+    // No TX delay, so no descriptor asks for one.
+    send(&mut nic, &mut dev, 1, 60);
+    assert_eq!(nic.tx_cmd(0) & TXD_CMD_IDE, 0);
+
+    dev.set_coalesce(E1000Coalesce::default()).unwrap();
+    send(&mut nic, &mut dev, 1, 60);
+    assert_eq!(nic.tx_cmd(1) & TXD_CMD_IDE, TXD_CMD_IDE);
+}
+
+#[test]
+fn no_limit_clears_itr() {
+    let (nic, _, mut dev) = setup(E1000Config::default());
+    dev.set_coalesce(E1000Coalesce {
+        itr: 0,
+        ..E1000Coalesce::default()
+    })
+    .unwrap();
+    assert_eq!(nic.regs.read(reg::ITR), 0);
+}
+
+#[test]
+fn invalid_settings_are_rejected() {
+    let (nic, _, mut dev) = setup(E1000Config::default());
+    let before = timers(&nic);
+    for bad in [
+        E1000Coalesce {
+            itr: 50,
+            ..E1000Coalesce::default()
+        },
+        E1000Coalesce {
+            itr: 200_000,
+            ..E1000Coalesce::default()
+        },
+        E1000Coalesce {
+            rx_abs_delay_us: E1000_MAX_DELAY_US + 1,
+            ..E1000Coalesce::default()
+        },
+        E1000Coalesce {
+            tx_delay_us: 100_000,
+            ..E1000Coalesce::default()
+        },
+    ] {
+        assert_eq!(dev.set_coalesce(bad), Err(EINVAL), "{bad:?}");
+    }
+    assert_eq!(dev.hw.coalesce(), E1000Coalesce::default());
+    assert_eq!(timers(&nic), before);
+}
+
+#[test]
+fn adaptive_rate_follows_traffic() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    dev.set_coalesce(adaptive()).unwrap();
+    assert_eq!(nic.regs.read(reg::ITR), itr(20_000));
+
+    // Full sized frames: bulk traffic, few interrupts.
+    send(&mut nic, &mut dev, 40, 1514);
+    dev.e1000_update_itr();
+    assert_eq!(nic.regs.read(reg::ITR), itr(4_000));
+
+    // Small frames both ways: the rate climbs back in steps.
+    let mut rates = Vec::new();
+    for seq in 0..5 {
+        send(&mut nic, &mut dev, 1, 60);
+        assert!(nic.inject_rx(&test_frame(seq, 60)));
+        assert_eq!(dev.rx.e1000_recv(16, |_| {}), 1);
+        dev.e1000_update_itr();
+        rates.push(nic.regs.read(reg::ITR));
+    }
+    // 4000 + 70000 / 4 and so on
+    assert_eq!(rates, [21_500, 39_000, 56_500, 70_000, 70_000].map(itr));
+
+    // No traffic, no change.
+    dev.e1000_update_itr();
+    assert_eq!(nic.regs.read(reg::ITR), itr(70_000));
+}
+
+#[test]
+fn fixed_rate_ignores_traffic() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    send(&mut nic, &mut dev, 40, 1514);
+    dev.e1000_update_itr();
+    assert_eq!(nic.regs.read(reg::ITR), itr(20_000));
+}
+
+#[test]
+fn take_over_keeps_coalescing() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    dev.set_coalesce(E1000Coalesce {
+        rx_delay_us: 16,
+        ..adaptive()
+    })
+    .unwrap();
+    send(&mut nic, &mut dev, 40, 1514);
+    dev.e1000_update_itr();
+    let before = timers(&nic);
+
+    let config = E1000Config {
+        rx_ring_size: 512,
+        ..E1000Config::default()
+    };
+    let (_, mut new) = nic.device(config);
+    assert_eq!(timers(&nic), [195, 0, 8, 8, 31]);
+    new.take_over(&dev.hw, &dev.rx);
+
+    assert_eq!(new.hw.coalesce(), dev.hw.coalesce());
+    assert_eq!(timers(&nic), before);
+    assert_eq!(nic.regs.read(reg::ITR), itr(4_000));
+}
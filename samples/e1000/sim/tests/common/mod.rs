@@ -0,0 +1,22 @@
+//! Setup shared by the tests.
+
+// Each test crate uses only some of these.
+#![allow(dead_code)]
+
+use e1000_sim::e1000::{E1000Config, E1000Device};
+use e1000_sim::model::{MockKernel, SimNic};
+
+/// A fresh NIC with a device probed on it.
+pub fn setup(config: E1000Config) -> (SimNic, MockKernel, E1000Device<MockKernel>) {
+    let nic = SimNic::new();
+    let (kfn, dev) = nic.device(config);
+    (nic, kfn, dev)
+}
+
+/// The default configuration with `mtu`.
+pub fn mtu(mtu: usize) -> E1000Config {
+    E1000Config {
+        mtu,
+        ..E1000Config::default()
+    }
+}
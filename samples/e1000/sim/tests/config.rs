@@ -0,0 +1,175 @@
+//! Runtime ring and buffer configuration.
+
+mod common;
+
+use common::setup;
+use e1000_sim::e1000::{E1000Config, E1000Device, E1000Error};
+use e1000_sim::model::{MockKernel, SimNic, frame_bytes, reg, test_frame};
+
+fn config(tx_ring_size: usize, rx_ring_size: usize, buffer_size: usize) -> E1000Config {
+    E1000Config {
+        tx_ring_size,
+        rx_ring_size,
+        buffer_size,
+        ..E1000Config::default()
+    }
+}
+
+#[test]
+fn ring_sizes_are_programmed() {
+    let (nic, _kfn, dev) = setup(config(64, 128, 2048));
+
+    assert_eq!(dev.hw.config(), config(64, 128, 2048));
+    assert_eq!(nic.regs.read(reg::TDLEN), 64 * 16);
+    assert_eq!(nic.regs.read(reg::RDLEN), 128 * 16);
+    assert_eq!(nic.regs.read(reg::RDT), 127);
+}
+
+#[test]
+fn buffer_size_is_encoded_in_rctl() {
+    for size in [256, 512, 1024, 2048, 4096, 8192, 16384] {
+        let (nic, _kfn, _dev) = setup(config(64, 64, size));
+        assert_eq!(nic.rx_buffer_size(), size);
+    }
+}
+
+#[test]
+fn invalid_config_is_rejected_before_allocating() {
+    for (bad, expected) in [
+        (config(40, 256, 2048), E1000Error::InvalidRingSize(40)),
+        (config(256, 4104, 2048), E1000Error::InvalidRingSize(4104)),
+        (config(252, 256, 2048), E1000Error::InvalidRingSize(252)),
+        (config(256, 100, 2048), E1000Error::InvalidRingSize(100)),
+        (config(256, 256, 3000), E1000Error::InvalidBufferSize(3000)),
+        (
+            config(256, 256, 32768),
+            E1000Error::InvalidBufferSize(32768),
+        ),
+    ] {
+        let nic = SimNic::new();
+        let kfn = MockKernel::new(&nic);
+        let err = E1000Device::new(kfn.clone(), nic.regs.base(), bad).err();
+        assert_eq!(err, Some(expected), "{bad:?}");
+        assert_eq!(kfn.dma.borrow().allocs, 0);
+    }
+}
+
+#[test]
+fn failed_dma_allocation_is_rolled_back() {
+    // TX ring, RX ring, TX buffers
+    for fail_at in 0..3 {
+        let nic = SimNic::new();
+        let kfn = MockKernel::new(&nic);
+        kfn.dma.borrow_mut().fail_at = Some(fail_at);
+        let err = E1000Device::new(kfn.clone(), nic.regs.base(), E1000Config::default()).err();
+        assert_eq!(err, Some(E1000Error::NoMemory), "allocation {fail_at}");
+
+        let dma = kfn.dma.borrow();
+        assert_eq!((dma.allocs, dma.frees, dma.live()), (fail_at, fail_at, 0));
+    }
+}
+
+#[test]
+fn failed_rx_buffer_allocation_is_rolled_back() {
+    let nic = SimNic::new();
+    let kfn = MockKernel::new(&nic);
+    kfn.dma.borrow_mut().fail_rx_alloc_from = Some(100);
+    let err = E1000Device::new(kfn.clone(), nic.regs.base(), E1000Config::default()).err();
+    assert_eq!(err, Some(E1000Error::NoMemory));
+
+    let dma = kfn.dma.borrow();
+    assert_eq!((dma.rx_allocs, dma.rx_live, dma.live()), (100, 0, 0));
+}
+
+#[test]
+fn register_handle_needs_the_whole_bar() {
+    use e1000_sim::e1000::{E1000_MMIO_SIZE, E1000Regs};
+    use e1000_sim::model::MMIO_SIZE;
+    assert_eq!(E1000_MMIO_SIZE, MMIO_SIZE);
+
+    // SAFETY: Rejected before anything could access it.
+    let short = unsafe { E1000Regs::new(0x1000, E1000_MMIO_SIZE - 4) };
+    assert!(short.is_err());
+}
+
+#[test]
+fn errors_map_to_errnos() {
+    use kernel::error::{Error, code};
+    assert_eq!(Error::from(E1000Error::NoMemory), code::ENOMEM);
+    assert_eq!(Error::from(E1000Error::ResetTimeout), code::ETIMEDOUT);
+    assert_eq!(Error::from(E1000Error::InvalidRingSize(40)), code::EINVAL);
+    assert_eq!(Error::from(E1000Error::InvalidMtu(0)), code::EINVAL);
+}
+
+#[test]
+fn ring_size_for_clamps_and_rounds_up() {
+    assert_eq!(E1000Config::ring_size_for(0), 48);
+    assert_eq!(E1000Config::ring_size_for(50), 56);
+    assert_eq!(E1000Config::ring_size_for(256), 256);
+    assert_eq!(E1000Config::ring_size_for(100_000), 4096);
+}
+
+#[test]
+fn small_rings_wrap() {
+    let (mut nic, _kfn, mut dev) = setup(config(48, 48, 1024));
+
+    for seq in 0..200 {
+        let frame = test_frame(seq, 60 + seq as usize * 3);
+        assert_eq!(dev.tx.e1000_transmit(&frame), frame.len() as i32);
+        assert_eq!(nic.process_tx(), vec![frame.clone()]);
+
+        assert!(nic.inject_rx(&frame));
+        let mut received = Vec::new();
+        assert_eq!(dev.rx.e1000_recv(64, |f| received.push(frame_bytes(&f))), 1);
+        assert_eq!(received, vec![frame]);
+    }
+}
+
+#[test]
+fn large_buffers_carry_large_frames() {
+    let config = E1000Config {
+        mtu: 9000,
+        ..config(64, 64, 16384)
+    };
+    let (mut nic, _kfn, mut dev) = setup(config);
+    let frame = test_frame(1, 9018);
+
+    assert!(nic.inject_rx(&frame));
+    let mut received = Vec::new();
+    dev.rx.e1000_recv(64, |f| {
+        assert_eq!(f.segments().count(), 1);
+        received.push(frame_bytes(&f));
+    });
+    assert_eq!(received, vec![frame]);
+}
+
+#[test]
+fn dma_is_sized_by_config() {
+    let (_nic, kfn, dev) = setup(config(1024, 1024, 4096));
+    assert_eq!(kfn.dma.borrow().live(), 3);
+    assert_eq!(kfn.dma.borrow().live_bytes(), 2 * 1024 * 16 + 1024 * 4096);
+    // One receive buffer per RX descriptor.
+    assert_eq!(kfn.dma.borrow().rx_live, 1024);
+
+    drop(dev);
+    assert_eq!(kfn.dma.borrow().live(), 0);
+    assert_eq!(kfn.dma.borrow().rx_live, 0);
+}
+
+#[test]
+fn take_over_keeps_mac_and_stats() {
+    let (nic, _kfn, mut old) = setup(E1000Config::default());
+    let mac = [0x02, 0, 0, 0, 0, 0x42];
+    old.hw.set_mac_address(&mac);
+    nic.count(reg::GPRC, 9);
+    old.hw.e1000_update_stats();
+    nic.clear_stats();
+
+    let (_, mut new) = nic.device(config(512, 512, 2048));
+    new.take_over(&old.hw, &old.rx);
+    drop(old);
+
+    assert_eq!(new.hw.mac_address(), mac);
+    assert_eq!(new.stats().gprc, 9);
+    assert_eq!(nic.regs.read(reg::TDLEN), 512 * 16);
+}
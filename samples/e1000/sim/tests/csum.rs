@@ -0,0 +1,143 @@
+//! Transmit checksum offload.
+
+mod common;
+
+use common::setup;
+use e1000_sim::e1000::{E1000Config, TxChecksum, TxOffload};
+use e1000_sim::model::{
+    IPPROTO_TCP as TCP, IPPROTO_UDP as UDP, L4_START, ipv4_frame, l4_checksum_ok as checksum_ok,
+    reg, test_frame,
+};
+
+const TCP_CSUM: usize = L4_START + 16;
+const UDP_CSUM: usize = L4_START + 6;
+
+fn offload(csum: usize) -> TxOffload {
+    TxOffload {
+        csum: TxChecksum::new(L4_START, csum),
+        ..TxOffload::default()
+    }
+}
+
+#[test]
+fn tcp_checksum_is_inserted() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    let frame = ipv4_frame(1, 1514, TCP);
+    assert!(!checksum_ok(&frame));
+
+    assert_eq!(
+        dev.tx
+            .e1000_transmit_offload([&frame[..]], offload(TCP_CSUM)),
+        1514
+    );
+    // Context descriptor, then the data descriptor.
+    assert_eq!(nic.regs.read(reg::TDT), 2);
+
+    let sent = nic.process_tx();
+    assert_eq!(sent.len(), 1);
+    assert!(checksum_ok(&sent[0]));
+    assert_eq!(sent[0][..TCP_CSUM], frame[..TCP_CSUM]);
+    assert_eq!(sent[0][TCP_CSUM + 2..], frame[TCP_CSUM + 2..]);
+}
+
+#[test]
+fn udp_checksum_is_inserted_over_fragments() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    let frame = ipv4_frame(1, 777, UDP);
+    let (header, payload) = frame.split_at(L4_START + 8);
+    let (page0, page1) = payload.split_at(301);
+
+    assert_eq!(
+        dev.tx
+            .e1000_transmit_offload([header, page0, page1], offload(UDP_CSUM)),
+        777
+    );
+    let sent = nic.process_tx();
+    assert!(checksum_ok(&sent[0]));
+}
+
+#[test]
+fn context_is_loaded_only_when_offsets_change() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+
+    for seq in 0..3 {
+        let frame = ipv4_frame(seq, 200, TCP);
+        dev.tx
+            .e1000_transmit_offload([&frame[..]], offload(TCP_CSUM));
+        assert!(checksum_ok(&nic.process_tx()[0]));
+    }
+    assert_eq!(nic.tx_contexts, 1);
+    assert_eq!(nic.regs.read(reg::TDT), 4);
+
+    let frame = ipv4_frame(3, 200, UDP);
+    dev.tx
+        .e1000_transmit_offload([&frame[..]], offload(UDP_CSUM));
+    assert!(checksum_ok(&nic.process_tx()[0]));
+    assert_eq!(nic.tx_contexts, 2);
+}
+
+#[test]
+fn plain_frames_are_sent_as_is() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+
+    // Context descriptors land all over the ring, plain frames reuse
+    // their slots afterwards.
+    for seq in 0..600 {
+        let (frame, sent) = if seq % 3 == 0 {
+            let (proto, csum) = if seq % 2 == 0 {
+                (TCP, TCP_CSUM)
+            } else {
+                (UDP, UDP_CSUM)
+            };
+            let frame = ipv4_frame(seq, 100, proto);
+            (
+                frame.clone(),
+                dev.tx.e1000_transmit_offload([&frame[..]], offload(csum)),
+            )
+        } else {
+            let frame = ipv4_frame(seq, 100, TCP);
+            (frame.clone(), dev.tx.e1000_transmit(&frame))
+        };
+        assert_eq!(sent, 100);
+        let out = nic.process_tx();
+        assert_eq!(out.len(), 1);
+        assert_eq!(checksum_ok(&out[0]), seq % 3 == 0, "frame {seq}");
+        if seq % 3 != 0 {
+            assert_eq!(out[0], frame, "frame {seq}");
+        }
+    }
+}
+
+#[test]
+fn context_needs_a_free_descriptor() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    let frame = test_frame(1, 100);
+
+    // Leave exactly one free descriptor.
+    for _ in 0..255 {
+        assert_eq!(dev.tx.e1000_transmit(&frame), 100);
+    }
+    let frame = ipv4_frame(2, 100, TCP);
+    assert_eq!(
+        dev.tx
+            .e1000_transmit_offload([&frame[..]], offload(TCP_CSUM)),
+        -1
+    );
+    assert_eq!(nic.regs.read(reg::TDT), 255);
+
+    nic.process_tx();
+    assert_eq!(
+        dev.tx
+            .e1000_transmit_offload([&frame[..]], offload(TCP_CSUM)),
+        100
+    );
+    assert!(checksum_ok(nic.process_tx().last().unwrap()));
+}
+
+#[test]
+fn ineligible_offsets_are_refused() {
+    assert!(TxChecksum::new(L4_START, TCP_CSUM).is_some());
+    assert!(TxChecksum::new(256, 272).is_none());
+    assert!(TxChecksum::new(200, 256).is_none());
+    assert!(TxChecksum::new(TCP_CSUM, L4_START).is_none());
+}
@@ -0,0 +1,155 @@
+//! Unicast and multicast receive filtering.
+
+use e1000_sim::e1000::{E1000_RAR_ENTRIES, E1000Config, E1000Device, RxMode};
+use e1000_sim::model::{MockKernel, RAH_AV, SimNic, reg};
+
+const RCTL_UPE: u32 = 1 << 3;
+const RCTL_MPE: u32 = 1 << 4;
+const STATION: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+
+fn setup() -> (SimNic, E1000Device<MockKernel>) {
+    let nic = SimNic::new();
+    nic.set_ra0(STATION);
+    let (_, dev) = nic.device(E1000Config::default());
+    (nic, dev)
+}
+
+fn addr(kind: u8, i: usize) -> [u8; 6] {
+    [kind, 0, 0x5e, 0, (i >> 8) as u8, i as u8]
+}
+
+fn unicast(n: usize) -> Vec<[u8; 6]> {
+    (0..n).map(|i| addr(0x02, i)).collect()
+}
+
+fn multicast(n: usize) -> Vec<[u8; 6]> {
+    (0..n).map(|i| addr(0x01, i + 0x100)).collect()
+}
+
+/// The valid exact-match entries in RA[0..16]
+fn rar(nic: &SimNic) -> Vec<Option<[u8; 6]>> {
+    (0..E1000_RAR_ENTRIES)
+        .map(|i| {
+            let ral = nic.regs.read(reg::RAL0 + 8 * i).to_le_bytes();
+            let rah = nic.regs.read(reg::RAH0 + 8 * i);
+            let [a4, a5, ..] = rah.to_le_bytes();
+            (rah & RAH_AV != 0).then_some([ral[0], ral[1], ral[2], ral[3], a4, a5])
+        })
+        .collect()
+}
+
+/// The MTA bit for `addr` with RCTL.MO = 0, i.e. address bits 47:36
+fn mta_bit(nic: &SimNic, addr: [u8; 6]) -> bool {
+    let hash = ((addr[4] as usize >> 4) | ((addr[5] as usize) << 4)) & 0xfff;
+    nic.regs.read(reg::MTA + 4 * (hash >> 5)) & (1 << (hash & 31)) != 0
+}
+
+fn mta_bits(nic: &SimNic) -> u32 {
+    (0..128)
+        .map(|i| nic.regs.read(reg::MTA + 4 * i).count_ones())
+        .sum()
+}
+
+#[test]
+fn addresses_take_exact_match_entries() {
+    let (nic, mut dev) = setup();
+    let uc = unicast(3);
+    let mc = multicast(4);
+
+    dev.hw.e1000_set_rx_mode(RxMode::default(), &uc, &mc);
+
+    let mut expected = vec![Some(STATION)];
+    expected.extend(uc.iter().chain(&mc).map(|&a| Some(a)));
+    expected.resize(E1000_RAR_ENTRIES, None);
+    assert_eq!(rar(&nic), expected);
+    assert_eq!(mta_bits(&nic), 0);
+    assert_eq!(nic.regs.read(reg::RCTL) & (RCTL_UPE | RCTL_MPE), 0);
+}
+
+#[test]
+fn extra_multicasts_are_hashed() {
+    let (nic, mut dev) = setup();
+    let uc = unicast(5);
+    let mc = multicast(40);
+
+    dev.hw.e1000_set_rx_mode(RxMode::default(), &uc, &mc);
+
+    let in_rar = E1000_RAR_ENTRIES - 1 - uc.len();
+    assert_eq!(rar(&nic)[E1000_RAR_ENTRIES - 1], Some(mc[in_rar - 1]));
+    for &a in &mc[in_rar..] {
+        assert!(mta_bit(&nic, a), "{a:02x?}");
+    }
+    assert!(!mta_bit(&nic, mc[0]));
+    assert_eq!(nic.regs.read(reg::RCTL) & (RCTL_UPE | RCTL_MPE), 0);
+}
+
+#[test]
+fn too_many_unicasts_turn_on_unicast_promiscuous() {
+    let (nic, mut dev) = setup();
+    let uc = unicast(E1000_RAR_ENTRIES);
+    let mc = multicast(2);
+
+    dev.hw.e1000_set_rx_mode(RxMode::default(), &uc, &mc);
+
+    assert_eq!(nic.regs.read(reg::RCTL) & (RCTL_UPE | RCTL_MPE), RCTL_UPE);
+    // The multicasts still get exact matches.
+    assert_eq!(rar(&nic)[1..3], [Some(mc[0]), Some(mc[1])]);
+    assert_eq!(rar(&nic)[3], None);
+
+    dev.hw.e1000_set_rx_mode(RxMode::default(), &uc[..15], &[]);
+    assert_eq!(nic.regs.read(reg::RCTL) & (RCTL_UPE | RCTL_MPE), 0);
+}
+
+#[test]
+fn promiscuous_and_allmulti() {
+    let (nic, mut dev) = setup();
+    let rctl = nic.regs.read(reg::RCTL);
+    let mode = |promisc, allmulti| RxMode { promisc, allmulti };
+
+    dev.hw.e1000_set_rx_mode(mode(true, false), &[], &[]);
+    assert_eq!(nic.regs.read(reg::RCTL), rctl | RCTL_UPE | RCTL_MPE);
+    dev.hw.e1000_set_rx_mode(mode(false, true), &[], &[]);
+    assert_eq!(nic.regs.read(reg::RCTL), rctl | RCTL_MPE);
+    dev.hw.e1000_set_rx_mode(mode(false, false), &[], &[]);
+    assert_eq!(nic.regs.read(reg::RCTL), rctl);
+}
+
+#[test]
+fn shrinking_lists_clear_stale_entries() {
+    let (nic, mut dev) = setup();
+    dev.hw
+        .e1000_set_rx_mode(RxMode::default(), &unicast(4), &multicast(100));
+    dev.hw
+        .e1000_set_rx_mode(RxMode::default(), &unicast(1), &[]);
+
+    let rar = rar(&nic);
+    assert_eq!(rar[..2], [Some(STATION), Some(unicast(1)[0])]);
+    assert!(rar[2..].iter().all(Option::is_none));
+    assert_eq!(mta_bits(&nic), 0);
+}
+
+#[test]
+fn filters_survive_reconfiguration() {
+    let (nic, mut dev) = setup();
+    let mc = multicast(30);
+    let mode = RxMode {
+        promisc: false,
+        allmulti: true,
+    };
+    dev.hw.e1000_set_rx_mode(mode, &unicast(2), &mc);
+    let before = (rar(&nic), mta_bits(&nic), nic.regs.read(reg::RCTL));
+
+    dev.hw.e1000_update_stats();
+    let config = E1000Config {
+        rx_ring_size: 512,
+        ..E1000Config::default()
+    };
+    let (_, mut new) = nic.device(config);
+    assert_eq!(mta_bits(&nic), 0);
+    new.take_over(&dev.hw, &dev.rx);
+
+    assert_eq!(
+        (rar(&nic), mta_bits(&nic), nic.regs.read(reg::RCTL)),
+        before
+    );
+}
@@ -0,0 +1,69 @@
+//! Scatter-gather transmit.
+
+mod common;
+
+use common::{mtu, setup};
+use e1000_sim::model::{reg, test_frame};
+
+#[test]
+fn fragments_map_to_consecutive_descriptors() {
+    let (mut nic, _, mut dev) = setup(mtu(1500));
+    let frame = test_frame(1, 1514);
+    let (header, payload) = frame.split_at(54);
+    let (page0, page1) = payload.split_at(1000);
+
+    assert_eq!(dev.tx.e1000_transmit_sg([header, page0, page1]), 1514);
+    assert_eq!(nic.regs.read(reg::TDT), 3);
+    assert_eq!(nic.process_tx(), vec![frame]);
+}
+
+#[test]
+fn large_fragments_are_split() {
+    let (mut nic, _, mut dev) = setup(mtu(9000));
+    let frame = test_frame(1, 9014);
+    let (header, payload) = frame.split_at(66);
+
+    // 66 bytes, then 8948 bytes in 2048-byte buffers.
+    assert_eq!(dev.tx.e1000_transmit_sg([header, payload]), 9014);
+    assert_eq!(nic.regs.read(reg::TDT), 1 + 5);
+    assert_eq!(nic.process_tx(), vec![frame]);
+}
+
+#[test]
+fn empty_fragments_use_no_descriptor() {
+    let (mut nic, _, mut dev) = setup(mtu(1500));
+    let frame = test_frame(1, 200);
+
+    assert_eq!(
+        dev.tx.e1000_transmit_sg([&[][..], &frame[..], &[][..]]),
+        200
+    );
+    assert_eq!(nic.regs.read(reg::TDT), 1);
+    assert_eq!(nic.process_tx(), vec![frame]);
+}
+
+#[test]
+fn gathered_frames_wrap_the_ring() {
+    let (mut nic, _, mut dev) = setup(mtu(1500));
+
+    for seq in 0..300 {
+        let frame = test_frame(seq, 100 + seq as usize);
+        let frags: Vec<&[u8]> = frame.chunks(1 + seq as usize % 64).collect();
+        if frags.len() >= 256 {
+            continue;
+        }
+        assert_eq!(dev.tx.e1000_transmit_sg(frags), frame.len() as i32);
+        assert_eq!(nic.process_tx(), vec![frame], "frame {seq}");
+    }
+}
+
+#[test]
+fn too_many_fragments_are_rejected() {
+    let (mut nic, _, mut dev) = setup(mtu(1500));
+    let frame = test_frame(1, 256);
+    let frags: Vec<&[u8]> = frame.chunks(1).collect();
+
+    assert_eq!(dev.tx.e1000_transmit_sg(frags), -1);
+    assert_eq!(nic.regs.read(reg::TDT), 0);
+    assert!(nic.process_tx().is_empty());
+}
@@ -0,0 +1,128 @@
+//! The TX, RX and hardware halves of `E1000Device` used apart, as the
+//! kernel glue does with a lock for each.
+
+mod common;
+
+use common::setup;
+use e1000_sim::e1000::{E1000Coalesce, E1000Config, E1000Device};
+use e1000_sim::model::{frame_bytes, reg, test_frame};
+
+const RING_SIZE: usize = 256;
+const TXD_CMD_IDE: u8 = 0x80;
+
+#[test]
+fn rings_only_touch_their_own_tail() {
+    let (mut nic, _kfn, dev) = setup(E1000Config::default());
+    let E1000Device {
+        hw: _hw,
+        mut tx,
+        mut rx,
+    } = dev;
+
+    let out = test_frame(1, 60);
+    assert_eq!(tx.e1000_transmit(&out), 60);
+    assert_eq!(nic.regs.read(reg::TDT), 1);
+    assert_eq!(nic.regs.read(reg::RDT) as usize, RING_SIZE - 1);
+
+    let received = test_frame(2, 100);
+    assert!(nic.inject_rx(&received));
+    let mut frames = Vec::new();
+    assert_eq!(rx.e1000_recv(16, |f| frames.push(frame_bytes(&f))), 1);
+    assert_eq!(frames, vec![received]);
+    assert_eq!(nic.regs.read(reg::RDT), 0);
+    assert_eq!(nic.regs.read(reg::TDT), 1);
+
+    assert_eq!(nic.process_tx(), vec![out]);
+    assert_eq!(tx.e1000_clean_tx(), 1);
+}
+
+#[test]
+fn reinit_rebuilds_both_rings() {
+    let (mut nic, _kfn, dev) = setup(E1000Config::default());
+    let E1000Device {
+        mut hw,
+        mut tx,
+        mut rx,
+    } = dev;
+    assert_eq!(tx.e1000_transmit(&test_frame(1, 60)), 60);
+    assert!(nic.inject_rx(&test_frame(2, 60)));
+    assert_eq!(rx.e1000_recv(16, |_| {}), 1);
+
+    // As the kernel glue does: the reset sleeps, so it runs apart from
+    // the halves.
+    hw.e1000_update_stats();
+    hw.reset_handle().e1000_reset().unwrap();
+    hw.reinit(&mut tx, &mut rx).unwrap();
+    assert_eq!(nic.regs.read(reg::TDT), 0);
+    assert_eq!(nic.regs.read(reg::RDT) as usize, RING_SIZE - 1);
+
+    let frame = test_frame(3, 60);
+    assert_eq!(tx.e1000_transmit(&frame), 60);
+    assert_eq!(nic.process_tx(), vec![frame]);
+}
+
+#[test]
+fn adaptive_itr_sees_the_traffic_of_both_rings() {
+    let (mut nic, _kfn, dev) = setup(E1000Config::default());
+    let E1000Device {
+        mut hw,
+        mut tx,
+        mut rx,
+    } = dev;
+    let adaptive = E1000Coalesce {
+        adaptive: true,
+        ..E1000Coalesce::default()
+    };
+    hw.set_coalesce(&mut tx, adaptive).unwrap();
+
+    // Bulk in one direction is enough to pick the bulk rate.
+    for seq in 0..40 {
+        assert_eq!(tx.e1000_transmit(&test_frame(seq, 1514)), 1514);
+    }
+    nic.process_tx();
+    hw.e1000_update_itr(&mut tx, &mut rx);
+    assert_eq!(nic.regs.read(reg::ITR), 1_000_000_000 / (4_000 * 256));
+
+    // The samples were taken: without traffic nothing changes.
+    hw.e1000_update_itr(&mut tx, &mut rx);
+    assert_eq!(nic.regs.read(reg::ITR), 1_000_000_000 / (4_000 * 256));
+}
+
+#[test]
+fn stats_include_the_receive_path_counters() {
+    let (mut nic, kfn, mut dev) = setup(E1000Config::default());
+    kfn.dma.borrow_mut().fail_rx_alloc_from = Some(RING_SIZE);
+    assert!(nic.inject_rx(&test_frame(1, 1000)));
+    let taken = dev
+        .rx
+        .e1000_recv(16, |mut f| f.take_buffers().for_each(drop));
+    assert_eq!(taken, 1);
+
+    assert_eq!(dev.hw.stats(&dev.rx).alloc_rx_buff_failed, 1);
+    assert_eq!(dev.stats().alloc_rx_buff_failed, 1);
+
+    // And survive a reconfiguration.
+    kfn.dma.borrow_mut().fail_rx_alloc_from = None;
+    let mut new = E1000Device::new(kfn, nic.regs.base(), E1000Config::default()).unwrap();
+    new.take_over(&dev.hw, &dev.rx);
+    assert_eq!(new.stats().alloc_rx_buff_failed, 1);
+}
+
+#[test]
+fn tx_interrupt_delay_follows_the_coalescing() {
+    let (mut nic, kfn, mut dev) = setup(E1000Config::default());
+    let no_delay = E1000Coalesce {
+        tx_delay_us: 0,
+        ..E1000Coalesce::default()
+    };
+    dev.hw.set_coalesce(&mut dev.tx, no_delay).unwrap();
+    assert_eq!(dev.tx.e1000_transmit(&test_frame(1, 60)), 60);
+    assert_eq!(nic.tx_cmd(0) & TXD_CMD_IDE, 0);
+    nic.process_tx();
+
+    // A new device starts with the default delay, until it takes over.
+    let mut new = E1000Device::new(kfn, nic.regs.base(), E1000Config::default()).unwrap();
+    new.take_over(&dev.hw, &dev.rx);
+    assert_eq!(new.tx.e1000_transmit(&test_frame(2, 60)), 60);
+    assert_eq!(nic.tx_cmd(0) & TXD_CMD_IDE, 0);
+}
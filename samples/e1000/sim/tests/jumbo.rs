@@ -0,0 +1,159 @@
+//! Jumbo frames: multi-descriptor TX and RX and RCTL.LPE.
+
+mod common;
+
+use common::{mtu, setup};
+use e1000_sim::e1000::{E1000_MAX_MTU, E1000Config, E1000Device, E1000Error};
+use e1000_sim::model::{MockKernel, SimNic, frame_bytes, reg, test_frame};
+
+const RCTL_LPE: u32 = 1 << 5;
+const RXD_STAT_DD: u8 = 0x01;
+
+fn recv(dev: &mut E1000Device<MockKernel>) -> Vec<Vec<u8>> {
+    let mut frames = Vec::new();
+    dev.rx
+        .e1000_recv(64, |frame| frames.push(frame_bytes(&frame)));
+    frames
+}
+
+#[test]
+fn lpe_follows_the_mtu() {
+    let (nic, _, _dev) = setup(mtu(1500));
+    assert_eq!(nic.regs.read(reg::RCTL) & RCTL_LPE, 0);
+
+    let (nic, _, _dev) = setup(mtu(9000));
+    assert_ne!(nic.regs.read(reg::RCTL) & RCTL_LPE, 0);
+}
+
+#[test]
+fn invalid_mtu_is_rejected() {
+    for mtu in [0, 67, E1000_MAX_MTU + 1] {
+        let nic = SimNic::new();
+        let config = E1000Config {
+            mtu,
+            ..E1000Config::default()
+        };
+        let err = E1000Device::new(MockKernel::new(&nic), nic.regs.base(), config).err();
+        assert_eq!(err, Some(E1000Error::InvalidMtu(mtu)), "mtu {mtu}");
+    }
+}
+
+#[test]
+fn long_frames_are_dropped_without_lpe() {
+    let (mut nic, _, mut dev) = setup(mtu(1500));
+
+    assert!(!nic.inject_rx(&test_frame(1, 1600)));
+    assert!(recv(&mut dev).is_empty());
+    assert_eq!(dev.stats().roc, 1);
+}
+
+#[test]
+fn transmit_chains_descriptors() {
+    let (mut nic, _, mut dev) = setup(mtu(9000));
+    let frame = test_frame(1, 9014);
+
+    assert_eq!(dev.tx.e1000_transmit(&frame), 9014);
+    // 9014 bytes in 2048-byte buffers
+    assert_eq!(nic.regs.read(reg::TDT), 5);
+    assert_eq!(nic.process_tx(), vec![frame]);
+}
+
+#[test]
+fn transmit_chains_across_the_ring_end() {
+    let (mut nic, _, mut dev) = setup(mtu(9000));
+
+    for seq in 0..200 {
+        let frame = test_frame(seq, 64 + seq as usize * 45);
+        assert_eq!(dev.tx.e1000_transmit(&frame), frame.len() as i32);
+        assert_eq!(nic.process_tx(), vec![frame], "frame {seq}");
+    }
+}
+
+#[test]
+fn transmit_needs_all_descriptors_free() {
+    let (mut nic, _, mut dev) = setup(mtu(9000));
+    let pending: Vec<_> = (0..252).map(|seq| test_frame(seq, 60)).collect();
+    for frame in &pending {
+        assert!(dev.tx.e1000_transmit(frame) > 0);
+    }
+
+    // Descriptors 252..=255 are free, but the fifth one (0) is still queued.
+    assert_eq!(dev.tx.e1000_transmit(&test_frame(999, 9014)), -1);
+    assert_eq!(nic.regs.read(reg::TDT), 252);
+    assert_eq!(nic.process_tx(), pending);
+    assert_eq!(dev.tx.e1000_transmit(&test_frame(999, 9014)), 9014);
+}
+
+#[test]
+fn receive_reassembles_descriptors() {
+    let (mut nic, _, mut dev) = setup(mtu(9000));
+    let frame = test_frame(1, 9014);
+
+    assert!(nic.inject_rx(&frame));
+    let mut segments = 0;
+    let n = dev.rx.e1000_recv(64, |received| {
+        segments = received.segments().count();
+        assert_eq!(received.len(), 9014);
+        assert_eq!(frame_bytes(&received), frame);
+    });
+    assert_eq!(n, 1);
+    assert_eq!(segments, 5);
+    // All five descriptors are handed back.
+    assert_eq!(nic.regs.read(reg::RDT), 4);
+}
+
+#[test]
+fn receive_mixes_sizes_and_wraps() {
+    let (mut nic, _, mut dev) = setup(mtu(9000));
+    let mut seq = 0;
+
+    for _ in 0..20 {
+        let batch: Vec<_> = [60, 9014, 2048, 2049, 4000, 1514]
+            .iter()
+            .map(|&len| {
+                seq += 1;
+                test_frame(seq, len)
+            })
+            .collect();
+        for frame in &batch {
+            assert!(nic.inject_rx(frame));
+        }
+        assert_eq!(recv(&mut dev), batch);
+    }
+}
+
+#[test]
+fn receive_waits_for_eop() {
+    let (mut nic, _, mut dev) = setup(mtu(9000));
+    assert!(nic.inject_rx(&test_frame(1, 60)));
+    assert!(nic.inject_rx(&test_frame(2, 5000)));
+
+    // The hardware is still writing the last of the three buffers of the
+    // second frame.
+    let status = nic.rx_status(3);
+    nic.set_rx_status(3, 0);
+    assert_eq!(recv(&mut dev), vec![test_frame(1, 60)]);
+
+    nic.set_rx_status(3, status);
+    assert_eq!(recv(&mut dev), vec![test_frame(2, 5000)]);
+}
+
+#[test]
+fn receive_drops_frames_longer_than_the_ring() {
+    let (mut nic, _, mut dev) = setup(mtu(9000));
+    // The NIC has filled every descriptor it was given without reaching
+    // the end of the frame, and waits for more.
+    for seq in 0..51 {
+        assert!(nic.inject_rx(&test_frame(seq, 9014)));
+    }
+    for index in 0..255 {
+        nic.set_rx_status(index, RXD_STAT_DD);
+    }
+    assert!(recv(&mut dev).is_empty());
+    assert_eq!(nic.regs.read(reg::RDT), 254);
+
+    // The rest of the frame is dropped too, and the ring carries on.
+    assert!(nic.inject_rx(&test_frame(100, 3000)));
+    assert!(nic.inject_rx(&test_frame(101, 60)));
+    assert_eq!(recv(&mut dev), vec![test_frame(101, 60)]);
+}
@@ -0,0 +1,81 @@
+//! Link state detection.
+
+mod common;
+
+use common::setup;
+use e1000_sim::e1000::{Duplex, E1000Config, LinkSpeed, LinkState};
+use e1000_sim::model::reg;
+
+#[test]
+fn init_sets_link_up() {
+    let (nic, _, dev) = setup(E1000Config::default());
+
+    let ctrl = nic.regs.read(reg::CTRL);
+    assert_ne!(ctrl & 0x40, 0, "SLU");
+    assert_ne!(ctrl & 0x20, 0, "ASDE");
+    assert_eq!(ctrl & (1 << 26), 0, "RST is not written back");
+    assert_eq!(dev.hw.link_state(), None);
+}
+
+#[test]
+fn link_up_is_reported_by_lsc() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+
+    nic.set_link(Some((1000, true)));
+    let cause = dev.hw.e1000_intr();
+    assert!(cause.link_changed());
+    assert!(!cause.rx());
+    assert_eq!(
+        dev.hw.link_state(),
+        Some(LinkState {
+            speed: LinkSpeed::Speed1000,
+            duplex: Duplex::Full,
+        })
+    );
+}
+
+#[test]
+fn link_speed_and_duplex() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+
+    for (mbps, full, speed) in [
+        (10, false, LinkSpeed::Speed10),
+        (100, true, LinkSpeed::Speed100),
+        (1000, false, LinkSpeed::Speed1000),
+    ] {
+        nic.set_link(Some((mbps, full)));
+        let link = dev.hw.e1000_check_link().unwrap();
+        assert_eq!(link.speed, speed);
+        assert_eq!(link.speed.mbps(), mbps);
+        let duplex = if full { Duplex::Full } else { Duplex::Half };
+        assert_eq!(link.duplex, duplex);
+    }
+}
+
+#[test]
+fn link_down_is_reported_by_lsc() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    nic.set_link(Some((100, true)));
+    assert!(dev.hw.e1000_check_link().is_some());
+
+    nic.set_link(None);
+    assert!(dev.hw.e1000_intr().link_changed());
+    assert_eq!(dev.hw.link_state(), None);
+}
+
+#[test]
+fn link_state_is_only_refreshed_on_lsc() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+
+    // STATUS changes, but no interrupt has been handled yet.
+    nic.set_link(Some((100, true)));
+    assert_eq!(dev.hw.link_state(), None);
+    dev.hw.e1000_intr();
+    assert!(dev.hw.link_state().is_some());
+}
+
+#[test]
+fn no_cause_means_not_our_interrupt() {
+    let (_nic, _, mut dev) = setup(E1000Config::default());
+    assert!(dev.hw.e1000_intr().is_empty());
+}
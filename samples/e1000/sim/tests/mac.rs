@@ -0,0 +1,79 @@
+//! Station address discovery and programming.
+
+use e1000_sim::e1000::{E1000Config, E1000Eerd};
+use e1000_sim::model::{RAH_AV, SimNic, reg};
+
+const EEPROM_MAC: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+const RA_MAC: [u8; 6] = [0x90, 0xe2, 0xfc, 0xb5, 0x36, 0x95];
+
+#[test]
+fn mac_from_loaded_receive_address() {
+    let nic = SimNic::new();
+    nic.set_eeprom_mac(EEPROM_MAC);
+    nic.set_ra0(RA_MAC);
+
+    let (_, dev) = nic.device(E1000Config::default());
+    assert_eq!(dev.hw.mac_address(), RA_MAC);
+}
+
+#[test]
+fn mac_from_eeprom_when_ra_not_valid() {
+    let nic = SimNic::new();
+    nic.set_eeprom_mac(EEPROM_MAC);
+
+    let (_, dev) = nic.device(E1000Config::default());
+    assert_eq!(dev.hw.mac_address(), EEPROM_MAC);
+    // RA[0] is programmed with the address and marked valid.
+    assert_eq!(nic.regs.read(reg::RAL0), 0x12005452);
+    assert_eq!(nic.regs.read(reg::RAH0), 0x5634 | RAH_AV);
+}
+
+#[test]
+fn mac_from_eeprom_with_82574_eerd() {
+    let nic = SimNic::new();
+    nic.set_eerd_82574();
+    nic.set_eeprom_mac(EEPROM_MAC);
+
+    let config = E1000Config {
+        eerd: E1000Eerd::I8257x,
+        ..E1000Config::default()
+    };
+    let (_, dev) = nic.device(config);
+    assert_eq!(dev.hw.mac_address(), EEPROM_MAC);
+}
+
+#[test]
+fn no_eerd_gives_zero_mac() {
+    let nic = SimNic::new();
+    nic.set_eeprom_mac(EEPROM_MAC);
+
+    let config = E1000Config {
+        eerd: E1000Eerd::None,
+        ..E1000Config::default()
+    };
+    let (kfn, dev) = nic.device(config);
+    assert_eq!(dev.hw.mac_address(), [0; 6]);
+    // No time is spent waiting for a register the part does not have.
+    assert_eq!(kfn.delayed_us.get(), 0);
+}
+
+#[test]
+fn eeprom_timeout_gives_zero_mac() {
+    let nic = SimNic::new();
+    nic.remove_eeprom();
+    let (_, dev) = nic.device(E1000Config::default());
+
+    assert_eq!(dev.hw.mac_address(), [0; 6]);
+}
+
+#[test]
+fn set_mac_address_programs_ra0() {
+    let nic = SimNic::new();
+    nic.set_eeprom_mac(EEPROM_MAC);
+    let (_, mut dev) = nic.device(E1000Config::default());
+
+    dev.hw.set_mac_address(&RA_MAC);
+    assert_eq!(dev.hw.mac_address(), RA_MAC);
+    assert_eq!(nic.regs.read(reg::RAL0), 0xb5fce290);
+    assert_eq!(nic.regs.read(reg::RAH0), 0x9536 | RAH_AV);
+}
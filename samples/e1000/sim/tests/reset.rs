@@ -0,0 +1,99 @@
+//! Device reset and recovery of a hung transmitter.
+
+mod common;
+
+use common::setup;
+use e1000_sim::e1000::{E1000Coalesce, E1000Config, E1000Device, E1000Error};
+use e1000_sim::model::{MockKernel, RAH_AV, SimNic, reg, test_frame};
+
+const CTRL_RST: u32 = 1 << 26;
+const CTRL_VME: u32 = 1 << 30;
+const TXD_STAT_DD: u8 = 0x01;
+
+#[test]
+fn probe_waits_for_the_reset() {
+    let (nic, _, _dev) = setup(E1000Config::default());
+    assert_eq!(nic.resets(), 1);
+    assert_eq!(nic.regs.read(reg::CTRL) & CTRL_RST, 0);
+}
+
+#[test]
+fn stuck_reset_fails_probe_without_leaking() {
+    let nic = SimNic::new();
+    nic.set_stuck_in_reset(true);
+    let kfn = MockKernel::new(&nic);
+    let dma = kfn.dma.clone();
+
+    let result = E1000Device::new(kfn, nic.regs.base(), E1000Config::default());
+    assert_eq!(result.err(), Some(E1000Error::ResetTimeout));
+    assert_eq!(dma.borrow().live(), 0);
+}
+
+#[test]
+fn reinit_rebuilds_a_hung_tx_ring() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    for seq in 0..5 {
+        dev.tx.e1000_transmit(&test_frame(seq, 100));
+    }
+    // The transmitter hangs: nothing is ever sent.
+    assert_eq!(nic.regs.read(reg::TDT), 5);
+
+    dev.reinit().unwrap();
+    assert_eq!(nic.resets(), 2);
+    assert_eq!(nic.regs.read(reg::TDH), 0);
+    assert_eq!(nic.regs.read(reg::TDT), 0);
+    assert_eq!(nic.regs.read(reg::RDT), 255);
+    assert!((0..256).all(|i| nic.tx_status(i) & TXD_STAT_DD != 0));
+    assert_eq!(dev.tx.e1000_clean_tx(), 0);
+
+    // The stale frames are gone, new ones go out.
+    let frame = test_frame(9, 100);
+    assert_eq!(dev.tx.e1000_transmit(&frame), 100);
+    assert_eq!(nic.process_tx(), [frame]);
+    assert_eq!(dev.tx.e1000_clean_tx(), 1);
+}
+
+#[test]
+fn reinit_restores_settings_and_counters() {
+    let (nic, _, mut dev) = setup(E1000Config::default());
+    let mac = [0x02, 0x00, 0x00, 0xaa, 0xbb, 0xcc];
+    dev.hw.set_mac_address(&mac);
+    dev.hw.e1000_set_vlan_offload(true);
+    let coalesce = E1000Coalesce {
+        rx_delay_us: 100,
+        ..E1000Coalesce::default()
+    };
+    dev.set_coalesce(coalesce).unwrap();
+    let rdtr = nic.regs.read(reg::RDTR);
+    nic.count(reg::GPRC, 7);
+
+    dev.reinit().unwrap();
+    assert_eq!(dev.hw.mac_address(), mac);
+    assert_eq!(nic.regs.read(reg::RAL0), 0xaa000002);
+    assert_eq!(nic.regs.read(reg::RAH0), 0xccbb | RAH_AV);
+    assert_ne!(nic.regs.read(reg::CTRL) & CTRL_VME, 0);
+    assert_eq!(dev.hw.coalesce(), coalesce);
+    assert_eq!(nic.regs.read(reg::RDTR), rdtr);
+    assert_eq!(dev.stats().gprc, 7);
+}
+
+#[test]
+fn reinit_reports_a_stuck_reset() {
+    let (nic, _, mut dev) = setup(E1000Config::default());
+    nic.set_stuck_in_reset(true);
+    assert_eq!(dev.reinit(), Err(E1000Error::ResetTimeout));
+
+    nic.set_stuck_in_reset(false);
+    assert_eq!(dev.reinit(), Ok(()));
+}
+
+#[test]
+fn reset_sleeps_rather_than_busy_waits() {
+    let (_nic, kfn, mut dev) = setup(E1000Config::default());
+    kfn.delayed_us.set(0);
+
+    dev.reinit().unwrap();
+    dev.e1000_shutdown();
+    assert_eq!(kfn.delayed_us.get(), 0);
+    assert!(kfn.slept_us.get() >= 2 * 10_000);
+}
@@ -0,0 +1,160 @@
+//! TX/RX ring handling of `E1000Device` against the simulated NIC.
+
+mod common;
+
+use common::setup;
+use e1000_sim::e1000::{E1000Config, E1000Device};
+use e1000_sim::model::{MockKernel, frame_bytes, reg, test_frame};
+
+const RING_SIZE: usize = 256;
+
+/// Receive up to `budget` frames, copying each out of the lent buffer.
+fn recv(dev: &mut E1000Device<MockKernel>, budget: usize) -> Vec<Vec<u8>> {
+    let mut packets = Vec::new();
+    let n = dev
+        .rx
+        .e1000_recv(budget, |frame| packets.push(frame_bytes(&frame)));
+    assert_eq!(n, packets.len());
+    packets
+}
+
+#[test]
+fn init_programs_rings() {
+    let (nic, _kfn, _dev) = setup(E1000Config::default());
+
+    assert_eq!(nic.regs.read(reg::TDLEN) as usize, RING_SIZE * 16);
+    assert_eq!(nic.regs.read(reg::RDLEN) as usize, RING_SIZE * 16);
+    assert_ne!(nic.regs.read(reg::TDBAL), 0);
+    assert_ne!(nic.regs.read(reg::RDBAL), 0);
+    assert_eq!(nic.regs.read(reg::TDH), 0);
+    assert_eq!(nic.regs.read(reg::TDT), 0);
+    assert_eq!(nic.regs.read(reg::RDH), 0);
+    // All but one RX descriptor are handed to the hardware.
+    assert_eq!(nic.regs.read(reg::RDT) as usize, RING_SIZE - 1);
+}
+
+#[test]
+fn transmit_single_frame() {
+    let (mut nic, _kfn, mut dev) = setup(E1000Config::default());
+    let frame = test_frame(1, 60);
+
+    assert_eq!(dev.tx.e1000_transmit(&frame), 60);
+    assert_eq!(nic.regs.read(reg::TDT), 1);
+
+    let sent = nic.process_tx();
+    assert_eq!(sent, vec![frame]);
+    assert_eq!(nic.regs.read(reg::TDH), 1);
+}
+
+#[test]
+fn transmit_wraps_around_the_ring() {
+    let (mut nic, _kfn, mut dev) = setup(E1000Config::default());
+
+    for seq in 0..(RING_SIZE as u32 * 2 + 10) {
+        let frame = test_frame(seq, 64 + (seq as usize % 512));
+        assert_eq!(dev.tx.e1000_transmit(&frame), frame.len() as i32);
+        assert_eq!(nic.process_tx(), vec![frame], "frame {seq}");
+    }
+    assert_eq!(nic.regs.read(reg::TDT) as usize, 10);
+    assert_eq!(nic.regs.read(reg::TDH) as usize, 10);
+}
+
+#[test]
+fn transmit_batches_before_the_hardware_catches_up() {
+    let (mut nic, _kfn, mut dev) = setup(E1000Config::default());
+    let frames: Vec<_> = (0..100).map(|seq| test_frame(seq, 128)).collect();
+
+    for frame in &frames {
+        assert!(dev.tx.e1000_transmit(frame) > 0);
+    }
+    assert_eq!(nic.process_tx(), frames);
+}
+
+#[test]
+fn receive_single_frame() {
+    let (mut nic, _kfn, mut dev) = setup(E1000Config::default());
+    let frame = test_frame(7, 98);
+
+    assert_eq!(dev.rx.e1000_recv(64, |_| panic!("nothing was received")), 0);
+    assert!(nic.inject_rx(&frame));
+
+    assert_eq!(recv(&mut dev, 64), vec![frame]);
+    // The consumed descriptor is given back to the hardware.
+    assert_eq!(nic.regs.read(reg::RDT), 0);
+    assert!(recv(&mut dev, 64).is_empty());
+}
+
+#[test]
+fn receive_respects_budget() {
+    let (mut nic, _kfn, mut dev) = setup(E1000Config::default());
+    let frames: Vec<_> = (0..5).map(|seq| test_frame(seq, 60)).collect();
+    for frame in &frames {
+        assert!(nic.inject_rx(frame));
+    }
+
+    let first = recv(&mut dev, 2);
+    let rest = recv(&mut dev, 64);
+    assert_eq!(first, frames[..2]);
+    assert_eq!(rest, frames[2..]);
+}
+
+#[test]
+fn receive_wraps_around_the_ring() {
+    let (mut nic, _kfn, mut dev) = setup(E1000Config::default());
+    let mut seq = 0;
+
+    for _ in 0..10 {
+        let batch: Vec<_> = (0..100)
+            .map(|_| {
+                seq += 1;
+                test_frame(seq, 60 + seq as usize % 1400)
+            })
+            .collect();
+        for frame in &batch {
+            assert!(nic.inject_rx(frame));
+        }
+        assert_eq!(recv(&mut dev, usize::MAX), batch);
+    }
+}
+
+#[test]
+fn receive_ring_full_drops_until_drained() {
+    let (mut nic, _kfn, mut dev) = setup(E1000Config::default());
+
+    // One descriptor is always owned by software, so RDH stops one short.
+    for seq in 0..(RING_SIZE as u32 - 1) {
+        assert!(nic.inject_rx(&test_frame(seq, 60)), "frame {seq}");
+    }
+    assert!(!nic.inject_rx(&test_frame(999, 60)));
+
+    assert_eq!(recv(&mut dev, usize::MAX).len(), RING_SIZE - 1);
+    assert!(nic.inject_rx(&test_frame(1000, 60)));
+}
+
+#[test]
+fn receive_lends_the_dma_buffer() {
+    let (mut nic, kfn, mut dev) = setup(E1000Config::default());
+    let frame = test_frame(3, 200);
+    assert!(nic.inject_rx(&frame));
+
+    let buffer = nic.rx_addr(0);
+    let n = dev.rx.e1000_recv(64, |received| {
+        let segments: Vec<&[u8]> = received.segments().collect();
+        assert_eq!(segments, vec![&frame[..]]);
+        // No copy: the slice points into the buffer the NIC received into.
+        assert_eq!(segments[0].as_ptr() as usize, buffer);
+    });
+    assert_eq!(n, 1);
+    assert_eq!(kfn.dma.borrow().rx_live, RING_SIZE);
+}
+
+#[test]
+fn dma_memory_is_freed_on_drop() {
+    let (nic, kfn, dev) = setup(E1000Config::default());
+    assert!(kfn.dma.borrow().live() > 0);
+
+    drop(dev);
+    assert_eq!(kfn.dma.borrow().live(), 0);
+    assert_eq!(kfn.dma.borrow().allocs, kfn.dma.borrow().frees);
+    drop(nic);
+}
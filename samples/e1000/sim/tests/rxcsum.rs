@@ -0,0 +1,131 @@
+//! Receive checksum offload and receive errors.
+
+mod common;
+
+use common::setup;
+use e1000_sim::e1000::{E1000Config, E1000Device, RxChecksum};
+use e1000_sim::model::{
+    IP_START, IPPROTO_TCP, IPPROTO_UDP, MockKernel, SimNic, finish_l4_checksum, frame_bytes,
+    ipv4_frame, ones_complement_sum, reg, rxd_err, test_frame,
+};
+
+fn recv_all(dev: &mut E1000Device<MockKernel>) -> Vec<(Vec<u8>, RxChecksum)> {
+    let mut frames = Vec::new();
+    dev.rx.e1000_recv(64, |frame| {
+        frames.push((frame_bytes(&frame), frame.checksum()))
+    });
+    frames
+}
+
+fn good_frame(seq: u32, len: usize, proto: u8) -> Vec<u8> {
+    let mut frame = ipv4_frame(seq, len, proto);
+    finish_l4_checksum(&mut frame);
+    frame
+}
+
+#[test]
+fn checksum_offload_is_enabled() {
+    let (nic, _, _dev) = setup(E1000Config::default());
+    assert_eq!(nic.regs.read(reg::RXCSUM) & 0x300, 0x300);
+}
+
+#[test]
+fn good_checksums_are_verified() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    let tcp = good_frame(1, 1514, IPPROTO_TCP);
+    let udp = good_frame(2, 300, IPPROTO_UDP);
+    assert!(nic.inject_rx(&tcp));
+    assert!(nic.inject_rx(&udp));
+
+    assert_eq!(
+        recv_all(&mut dev),
+        vec![(tcp, RxChecksum::Verified), (udp, RxChecksum::Verified)]
+    );
+    let stats = dev.stats();
+    assert_eq!(stats.hw_csum_good, 2);
+    assert_eq!(stats.hw_csum_err, 0);
+}
+
+#[test]
+fn verified_frames_span_buffers() {
+    let nic = SimNic::new();
+    let config = E1000Config {
+        mtu: 9000,
+        ..E1000Config::default()
+    };
+    let (_, mut dev) = nic.device(config);
+    let mut nic = nic;
+    let frame = good_frame(1, 9014, IPPROTO_TCP);
+    assert!(nic.inject_rx(&frame));
+
+    assert_eq!(recv_all(&mut dev), vec![(frame, RxChecksum::Verified)]);
+}
+
+#[test]
+fn bad_checksums_are_reported() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    // The L4 checksum still holds the pseudo-header sum.
+    let bad_tcp = ipv4_frame(1, 200, IPPROTO_TCP);
+    let mut bad_ip = good_frame(2, 200, IPPROTO_UDP);
+    bad_ip[IP_START + 8] ^= 0xff;
+    assert!(nic.inject_rx(&bad_tcp));
+    assert!(nic.inject_rx(&bad_ip));
+
+    assert_eq!(
+        recv_all(&mut dev),
+        vec![(bad_tcp, RxChecksum::Bad), (bad_ip, RxChecksum::Bad)]
+    );
+    let stats = dev.stats();
+    assert_eq!(stats.hw_csum_good, 0);
+    assert_eq!(stats.hw_csum_err, 2);
+}
+
+#[test]
+fn other_frames_are_not_checked() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    let raw = test_frame(1, 100);
+    let mut icmp = good_frame(2, 100, IPPROTO_TCP);
+    icmp[IP_START + 9] = 1;
+    icmp[IP_START + 10..IP_START + 12].fill(0);
+    let ip_csum = !ones_complement_sum(&icmp[IP_START..IP_START + 20]);
+    icmp[IP_START + 10..IP_START + 12].copy_from_slice(&ip_csum.to_be_bytes());
+    assert!(nic.inject_rx(&raw));
+    assert!(nic.inject_rx(&icmp));
+
+    let frames = recv_all(&mut dev);
+    assert_eq!(frames.len(), 2);
+    assert!(frames.iter().all(|(_, csum)| *csum == RxChecksum::None));
+    assert_eq!(dev.stats().hw_csum_err, 0);
+}
+
+#[test]
+fn damaged_frames_are_dropped() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    let good = good_frame(4, 200, IPPROTO_TCP);
+    for (seq, err) in [rxd_err::CE, rxd_err::SE, rxd_err::RXE]
+        .into_iter()
+        .enumerate()
+    {
+        assert!(nic.inject_rx_error(&good_frame(seq as u32, 200, IPPROTO_TCP), err));
+    }
+    assert!(nic.inject_rx(&good));
+
+    let mut frames = Vec::new();
+    assert_eq!(dev.rx.e1000_recv(64, |f| frames.push(frame_bytes(&f))), 4);
+    assert_eq!(frames, vec![good]);
+    // The dropped frames' descriptors went back to the hardware too.
+    assert_eq!(nic.regs.read(reg::RDT), 3);
+}
+
+#[test]
+fn checksum_errors_alone_do_not_drop() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    let frame = good_frame(1, 200, IPPROTO_TCP);
+    assert!(nic.inject_rx_error(&frame, rxd_err::TCPE));
+    assert!(nic.inject_rx_error(&frame, rxd_err::IPE));
+
+    assert_eq!(
+        recv_all(&mut dev),
+        vec![(frame.clone(), RxChecksum::Bad), (frame, RxChecksum::Bad)]
+    );
+}
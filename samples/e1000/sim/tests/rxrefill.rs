@@ -0,0 +1,163 @@
+//! RX buffers handed up the stack and replaced, or copied out and reused.
+
+mod common;
+
+use common::{mtu, setup};
+use e1000_sim::e1000::{E1000_RX_COPYBREAK, E1000Config, E1000Device, RxBuffer};
+use e1000_sim::model::{MockKernel, MockRxBuffer, reg, test_frame};
+
+const RING_SIZE: usize = 256;
+
+/// Receive up to 64 frames, taking the buffers of those above the
+/// copybreak. Returns the frames, and the buffers taken with the length of
+/// the frame in each.
+fn recv_taking(dev: &mut E1000Device<MockKernel>) -> (Vec<Vec<u8>>, Vec<(MockRxBuffer, usize)>) {
+    let (mut frames, mut taken) = (Vec::new(), Vec::new());
+    dev.rx.e1000_recv(64, |mut frame| {
+        if frame.is_copybreak() {
+            frames.push(frame.segments().flatten().copied().collect());
+            return;
+        }
+        let bufs: Vec<_> = frame.take_buffers().collect();
+        frames.push(
+            bufs.iter()
+                .flat_map(|(buf, len)| &buf.as_slice()[..*len])
+                .copied()
+                .collect(),
+        );
+        taken.extend(bufs);
+    });
+    (frames, taken)
+}
+
+#[test]
+fn short_frames_are_copied_and_the_buffer_reused() {
+    let (mut nic, kfn, mut dev) = setup(mtu(1500));
+    let buffer = nic.rx_addr(0);
+    let frame = test_frame(1, E1000_RX_COPYBREAK);
+    assert!(nic.inject_rx(&frame));
+
+    let (frames, taken) = recv_taking(&mut dev);
+    assert_eq!(frames, vec![frame]);
+    assert!(taken.is_empty());
+    assert_eq!(nic.rx_addr(0), buffer);
+    let dma = kfn.dma.borrow();
+    assert_eq!((dma.rx_allocs, dma.rx_recycles), (RING_SIZE, 1));
+}
+
+#[test]
+fn long_frames_take_their_buffer_which_is_replaced() {
+    let (mut nic, kfn, mut dev) = setup(mtu(1500));
+    let buffer = nic.rx_addr(0);
+    let frame = test_frame(1, E1000_RX_COPYBREAK + 1);
+    assert!(nic.inject_rx(&frame));
+
+    let (frames, taken) = recv_taking(&mut dev);
+    assert_eq!(frames, vec![frame]);
+    assert_eq!(taken.len(), 1);
+    assert_eq!(taken[0].0.dma_handle(), buffer);
+    assert_ne!(nic.rx_addr(0), buffer);
+    assert_eq!(kfn.dma.borrow().rx_allocs, RING_SIZE + 1);
+    assert_eq!(kfn.dma.borrow().rx_live, RING_SIZE + 1);
+
+    // The stack frees it once it is done with the frame.
+    drop(taken);
+    assert_eq!(kfn.dma.borrow().rx_live, RING_SIZE);
+}
+
+#[test]
+fn buffers_left_in_place_are_reused() {
+    let (mut nic, kfn, mut dev) = setup(mtu(1500));
+    let buffer = nic.rx_addr(0);
+    assert!(nic.inject_rx(&test_frame(1, 1000)));
+
+    // e.g. no skb could be built around it
+    assert_eq!(
+        dev.rx
+            .e1000_recv(64, |frame| assert!(!frame.is_copybreak())),
+        1
+    );
+    assert_eq!(nic.rx_addr(0), buffer);
+    assert_eq!(kfn.dma.borrow().rx_allocs, RING_SIZE);
+}
+
+#[test]
+fn multi_buffer_frames_are_taken_whole() {
+    let (mut nic, kfn, mut dev) = setup(mtu(9000));
+    dev.rx.set_rx_copybreak(9014);
+    let frame = test_frame(1, 5000);
+    assert!(nic.inject_rx(&frame));
+
+    // Only single buffer frames are copied.
+    let (frames, taken) = recv_taking(&mut dev);
+    assert_eq!(frames, vec![frame]);
+    let lens: Vec<usize> = taken.iter().map(|&(_, len)| len).collect();
+    assert_eq!(lens, vec![2048, 2048, 904]);
+    assert_eq!(kfn.dma.borrow().rx_allocs, RING_SIZE + 3);
+}
+
+#[test]
+fn copybreak_is_configurable() {
+    let (mut nic, _kfn, mut dev) = setup(mtu(1500));
+    assert_eq!(dev.rx.rx_copybreak(), E1000_RX_COPYBREAK);
+
+    dev.rx.set_rx_copybreak(1514);
+    assert!(nic.inject_rx(&test_frame(1, 1514)));
+    assert!(recv_taking(&mut dev).1.is_empty());
+
+    dev.rx.set_rx_copybreak(0);
+    assert!(nic.inject_rx(&test_frame(2, 60)));
+    assert_eq!(recv_taking(&mut dev).1.len(), 1);
+}
+
+#[test]
+fn descriptors_wait_for_a_replacement_buffer() {
+    let (mut nic, kfn, mut dev) = setup(mtu(1500));
+    kfn.dma.borrow_mut().fail_rx_alloc_from = Some(RING_SIZE);
+    for seq in 0..3 {
+        assert!(nic.inject_rx(&test_frame(seq, 1000)));
+    }
+
+    // The frames still go up, but only the descriptor that kept its
+    // buffer goes back to the NIC.
+    assert_eq!(recv_taking(&mut dev).0.len(), 3);
+    assert_eq!(nic.regs.read(reg::RDT), 0);
+    assert_eq!(dev.stats().alloc_rx_buff_failed, 3);
+
+    // The next frame refills them.
+    kfn.dma.borrow_mut().fail_rx_alloc_from = None;
+    assert!(nic.inject_rx(&test_frame(3, 100)));
+    assert_eq!(recv_taking(&mut dev).0.len(), 1);
+    assert_eq!(nic.regs.read(reg::RDT), 3);
+    assert_eq!(kfn.dma.borrow().rx_live, RING_SIZE);
+}
+
+#[test]
+fn reinit_refills_the_ring() {
+    let (mut nic, kfn, mut dev) = setup(mtu(1500));
+    kfn.dma.borrow_mut().fail_rx_alloc_from = Some(RING_SIZE);
+    for seq in 0..3 {
+        assert!(nic.inject_rx(&test_frame(seq, 1000)));
+    }
+    drop(recv_taking(&mut dev));
+    assert_eq!(kfn.dma.borrow().rx_live, RING_SIZE - 3);
+
+    kfn.dma.borrow_mut().fail_rx_alloc_from = None;
+    dev.reinit().unwrap();
+    assert_eq!(kfn.dma.borrow().rx_live, RING_SIZE);
+    assert_eq!(nic.regs.read(reg::RDT) as usize, RING_SIZE - 1);
+
+    let frame = test_frame(4, 1000);
+    assert!(nic.inject_rx(&frame));
+    assert_eq!(recv_taking(&mut dev).0, vec![frame]);
+}
+
+#[test]
+fn copybreak_survives_reconfiguration() {
+    let (nic, kfn, mut old) = setup(mtu(1500));
+    old.rx.set_rx_copybreak(1000);
+
+    let mut new = E1000Device::new(kfn, nic.regs.base(), E1000Config::default()).unwrap();
+    new.take_over(&old.hw, &old.rx);
+    assert_eq!(new.rx.rx_copybreak(), 1000);
+}
@@ -0,0 +1,126 @@
+//! Quiescing the device before its memory is freed.
+
+use e1000_sim::e1000::{E1000Config, E1000Device, E1000Error};
+use e1000_sim::model::{MockKernel, SimNic, reg, test_frame};
+
+const CTRL_GIO_MASTER_DISABLE: u32 = 1 << 2;
+const STATUS_GIO_MASTER_EN: u32 = 1 << 19;
+const RCTL_EN: u32 = 1 << 1;
+const TCTL_EN: u32 = 1 << 1;
+
+#[test]
+fn shutdown_stops_rx_tx_and_interrupts() {
+    let nic = SimNic::new();
+    let (kfn, mut dev) = nic.device(E1000Config::default());
+    dev.hw.e1000_irq_enable();
+    assert_ne!(nic.regs.read(reg::RCTL) & RCTL_EN, 0);
+    assert_ne!(nic.regs.read(reg::TCTL) & TCTL_EN, 0);
+    nic.regs.write(reg::IMC, 0);
+
+    dev.e1000_shutdown();
+    assert_eq!(nic.regs.read(reg::RCTL) & RCTL_EN, 0);
+    assert_eq!(nic.regs.read(reg::TCTL) & TCTL_EN, 0);
+    assert_eq!(nic.regs.read(reg::IMC), !0);
+    // Conventional PCI: a fixed wait, no master disable and no reset.
+    assert_eq!(nic.regs.read(reg::CTRL) & CTRL_GIO_MASTER_DISABLE, 0);
+    assert_eq!(nic.resets(), 1);
+
+    drop(dev);
+    assert_eq!(kfn.dma.borrow().live(), 0);
+}
+
+#[test]
+fn pcie_parts_drain_bus_mastering() {
+    let nic = SimNic::new();
+    nic.set_pcie();
+    let (_kfn, dev) = nic.device(E1000Config::default());
+    assert_ne!(nic.regs.read(reg::STATUS) & STATUS_GIO_MASTER_EN, 0);
+
+    dev.e1000_shutdown();
+    assert_ne!(nic.regs.read(reg::CTRL) & CTRL_GIO_MASTER_DISABLE, 0);
+    assert_eq!(nic.regs.read(reg::STATUS) & STATUS_GIO_MASTER_EN, 0);
+    assert_eq!(nic.resets(), 1);
+}
+
+#[test]
+fn dma_that_does_not_drain_is_stopped_by_a_reset() {
+    let nic = SimNic::new();
+    nic.set_pcie();
+    let (_kfn, mut dev) = nic.device(E1000Config::default());
+    dev.tx.e1000_transmit(&test_frame(1, 100));
+    nic.set_dma_stuck();
+
+    dev.e1000_shutdown();
+    assert_eq!(nic.resets(), 2);
+    assert_eq!(nic.regs.read(reg::IMC), !0);
+}
+
+#[test]
+fn reinit_after_shutdown_brings_the_device_back() {
+    let mut nic = SimNic::new();
+    nic.set_pcie();
+    let (_kfn, mut dev) = nic.device(E1000Config::default());
+    dev.e1000_shutdown();
+
+    dev.reinit().unwrap();
+    assert_eq!(nic.regs.read(reg::CTRL) & CTRL_GIO_MASTER_DISABLE, 0);
+    assert_ne!(nic.regs.read(reg::STATUS) & STATUS_GIO_MASTER_EN, 0);
+    let frame = test_frame(2, 100);
+    assert_eq!(dev.tx.e1000_transmit(&frame), 100);
+    assert_eq!(nic.process_tx(), [frame]);
+}
+
+#[test]
+fn dropping_the_halves_quiesces_the_device() {
+    let nic = SimNic::new();
+    let (kfn, mut dev) = nic.device(E1000Config::default());
+    dev.hw.e1000_irq_enable();
+    kfn.slept_us.set(0);
+
+    // As a probe that fails after the device was split up drops it: the
+    // receive ring goes first.
+    let E1000Device { hw, tx, rx } = dev;
+    drop(rx);
+    assert_eq!(nic.regs.read(reg::RCTL) & RCTL_EN, 0);
+    assert_eq!(nic.regs.read(reg::TCTL) & TCTL_EN, 0);
+    assert_eq!(nic.regs.read(reg::IMC), !0);
+    let slept = kfn.slept_us.get();
+    assert_ne!(slept, 0);
+
+    // Once is enough.
+    drop(tx);
+    drop(hw);
+    assert_eq!(kfn.slept_us.get(), slept);
+    assert_eq!(kfn.dma.borrow().live(), 0);
+}
+
+#[test]
+fn dropping_a_taken_over_device_leaves_the_new_one_running() {
+    let mut nic = SimNic::new();
+    let (_, mut old) = nic.device(E1000Config::default());
+    old.hw.e1000_update_stats();
+    let (_, mut new) = nic.device(E1000Config::default());
+    new.take_over(&old.hw, &old.rx);
+
+    drop(old);
+    assert_ne!(nic.regs.read(reg::RCTL) & RCTL_EN, 0);
+    let frame = test_frame(1, 100);
+    assert_eq!(new.tx.e1000_transmit(&frame), 100);
+    assert_eq!(nic.process_tx(), [frame]);
+}
+
+#[test]
+fn failed_probe_leaves_the_running_device_alone() {
+    let mut nic = SimNic::new();
+    let (_, mut dev) = nic.device(E1000Config::default());
+    let kfn = MockKernel::new(&nic);
+    // The TX ring and its buffers are allocated, the RX ring is not.
+    kfn.dma.borrow_mut().fail_at = Some(2);
+
+    let result = E1000Device::new(kfn.clone(), nic.regs.base(), E1000Config::default());
+    assert_eq!(result.err(), Some(E1000Error::NoMemory));
+    assert_eq!(kfn.dma.borrow().live(), 0);
+    let frame = test_frame(1, 100);
+    assert_eq!(dev.tx.e1000_transmit(&frame), 100);
+    assert_eq!(nic.process_tx(), [frame]);
+}
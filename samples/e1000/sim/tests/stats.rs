@@ -0,0 +1,83 @@
+//! Accumulation of the clear-on-read statistics registers.
+
+mod common;
+
+use common::setup;
+use e1000_sim::e1000::{E1000_STATS_LEN, E1000Config, E1000Device, E1000Stats};
+use e1000_sim::model::{MockKernel, SimNic, reg};
+
+/// Read the statistics the way the hardware sees it: reading clears.
+fn snapshot(nic: &SimNic, dev: &mut E1000Device<MockKernel>) -> E1000Stats {
+    let stats = dev.stats();
+    nic.clear_stats();
+    stats
+}
+
+#[test]
+fn stats_start_at_zero() {
+    let (nic, _, mut dev) = setup(E1000Config::default());
+    assert_eq!(snapshot(&nic, &mut dev), E1000Stats::default());
+}
+
+#[test]
+fn stats_accumulate_across_reads() {
+    let (nic, _, mut dev) = setup(E1000Config::default());
+
+    nic.count(reg::GPRC, 10);
+    nic.count(reg::CRCERRS, 2);
+    let first = snapshot(&nic, &mut dev);
+    assert_eq!(first.gprc, 10);
+    assert_eq!(first.crcerrs, 2);
+
+    nic.count(reg::GPRC, 5);
+    nic.count(reg::MPC, 1);
+    let second = snapshot(&nic, &mut dev);
+    assert_eq!(second.gprc, 15);
+    assert_eq!(second.crcerrs, 2);
+    assert_eq!(second.mpc, 1);
+}
+
+#[test]
+fn stats_combine_64bit_octet_counters() {
+    let (nic, _, mut dev) = setup(E1000Config::default());
+
+    nic.count(reg::GORCL, 0x8000_0000);
+    nic.count(reg::GORCH, 1);
+    assert_eq!(snapshot(&nic, &mut dev).gorc, 0x1_8000_0000);
+
+    nic.count(reg::GORCL, 0x8000_0000);
+    assert_eq!(snapshot(&nic, &mut dev).gorc, 0x2_0000_0000);
+}
+
+#[test]
+fn stats_survive_32bit_wrap() {
+    let (nic, _, mut dev) = setup(E1000Config::default());
+
+    for _ in 0..3 {
+        nic.count(reg::GPTC, u32::MAX);
+        snapshot(&nic, &mut dev);
+    }
+    assert_eq!(snapshot(&nic, &mut dev).gptc, 3 * u32::MAX as u64);
+}
+
+#[test]
+fn ethtool_names_are_unique_and_fit() {
+    let names = E1000Stats::default().ethtool_stats().map(|(name, _)| name);
+    assert_eq!(names.len(), E1000_STATS_LEN);
+    for (i, name) in names.iter().enumerate() {
+        // ETH_GSTRING_LEN, including the terminating NUL
+        assert!(name.len() < 32, "{name}");
+        assert!(!names[..i].contains(name), "duplicate {name}");
+    }
+}
+
+#[test]
+fn ethtool_values_follow_the_counters() {
+    let (nic, _, mut dev) = setup(E1000Config::default());
+    nic.count(reg::GPRC, 7);
+    nic.count(reg::GPTC, 3);
+
+    let stats = snapshot(&nic, &mut dev).ethtool_stats();
+    assert!(stats.contains(&("rx_packets", 7)));
+    assert!(stats.contains(&("tx_packets", 3)));
+}
@@ -0,0 +1,155 @@
+//! TCP segmentation offload.
+
+mod common;
+
+use common::setup;
+use e1000_sim::e1000::{E1000Config, TxChecksum, TxOffload, TxTso};
+use e1000_sim::model::{
+    IP_START, IPPROTO_TCP, L4_START, ipv4_frame, l4_checksum_ok, l4_csum_offset,
+    ones_complement_sum, reg,
+};
+
+const HDR_LEN: usize = L4_START + 20;
+const FIN: u8 = 0x01;
+const PSH: u8 = 0x08;
+const ACK: u8 = 0x10;
+
+/// A TCP super-packet carrying `payload` bytes, with the headers prepared
+/// the way the Linux glue hands them to the NIC.
+fn tso_frame(payload: usize, flags: u8) -> Vec<u8> {
+    let mut frame = ipv4_frame(7, HDR_LEN + payload, IPPROTO_TCP);
+    let ip = IP_START;
+    frame[ip + 2..ip + 4].fill(0);
+    frame[ip + 10..ip + 12].fill(0);
+    frame[L4_START + 12] = 5 << 4;
+    frame[L4_START + 13] = flags;
+    // Pseudo-header sum without the TCP length
+    let mut ph = frame[ip + 12..ip + 20].to_vec();
+    ph.extend_from_slice(&[0, IPPROTO_TCP]);
+    let seed = ones_complement_sum(&ph);
+    let csum = l4_csum_offset(IPPROTO_TCP);
+    frame[csum..csum + 2].copy_from_slice(&seed.to_be_bytes());
+    frame
+}
+
+fn tso(mss: usize) -> TxOffload {
+    TxOffload {
+        tso: TxTso::new(IP_START, L4_START, HDR_LEN, mss),
+        ..TxOffload::default()
+    }
+}
+
+fn seq_of(frame: &[u8]) -> u32 {
+    u32::from_be_bytes(frame[L4_START + 4..L4_START + 8].try_into().unwrap())
+}
+
+/// Check `segs` are a valid segmentation of `frame` at `mss`.
+fn check_segments(frame: &[u8], segs: &[Vec<u8>], mss: usize) {
+    let payload = &frame[HDR_LEN..];
+    assert_eq!(segs.len(), payload.len().div_ceil(mss));
+    let id = u16::from_be_bytes([frame[IP_START + 4], frame[IP_START + 5]]);
+
+    for (i, (seg, chunk)) in segs.iter().zip(payload.chunks(mss)).enumerate() {
+        assert_eq!(seg.len(), HDR_LEN + chunk.len());
+        assert_eq!(&seg[HDR_LEN..], chunk, "segment {i}");
+        assert_eq!(seg[..IP_START], frame[..IP_START]);
+        let ip = &seg[IP_START..L4_START];
+        assert_eq!(ones_complement_sum(ip), 0xffff, "segment {i}");
+        assert_eq!(
+            u16::from_be_bytes([ip[2], ip[3]]) as usize,
+            seg.len() - IP_START
+        );
+        assert_eq!(
+            u16::from_be_bytes([ip[4], ip[5]]),
+            id.wrapping_add(i as u16)
+        );
+        assert_eq!(seq_of(seg), seq_of(frame).wrapping_add((i * mss) as u32));
+        assert!(l4_checksum_ok(seg), "segment {i}");
+    }
+}
+
+#[test]
+fn super_packet_is_segmented() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    let frame = tso_frame(4000, ACK);
+
+    assert_eq!(dev.tx.e1000_transmit_offload([&frame[..]], tso(1448)), 4054);
+    // Context descriptor, then 4054 bytes in two buffers.
+    assert_eq!(nic.regs.read(reg::TDT), 3);
+
+    let segs = nic.process_tx();
+    check_segments(&frame, &segs, 1448);
+    assert_eq!(
+        segs.iter().map(Vec::len).collect::<Vec<_>>(),
+        vec![HDR_LEN + 1448, HDR_LEN + 1448, HDR_LEN + 1104]
+    );
+    assert_eq!(dev.stats().tsctc, 1);
+}
+
+#[test]
+fn fin_and_psh_go_on_the_last_segment() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    let frame = tso_frame(3000, ACK | PSH | FIN);
+
+    dev.tx.e1000_transmit_offload([&frame[..]], tso(1000));
+    let segs = nic.process_tx();
+    check_segments(&frame, &segs, 1000);
+    let flags: Vec<u8> = segs.iter().map(|s| s[L4_START + 13]).collect();
+    assert_eq!(flags, vec![ACK, ACK, ACK | PSH | FIN]);
+}
+
+#[test]
+fn largest_super_packet_over_fragments() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    let frame = tso_frame(65535 - 40, ACK);
+    let (header, payload) = frame.split_at(HDR_LEN);
+    let frags: Vec<&[u8]> = payload.chunks(4096).collect();
+    let frags = [&[header][..], &frags[..]].concat();
+
+    assert_eq!(
+        dev.tx.e1000_transmit_offload(frags, tso(1460)),
+        frame.len() as i32
+    );
+    check_segments(&frame, &nic.process_tx(), 1460);
+}
+
+#[test]
+fn every_super_packet_loads_a_context() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    for payload in [2000, 2000] {
+        dev.tx
+            .e1000_transmit_offload([&tso_frame(payload, ACK)[..]], tso(1448));
+        assert_eq!(nic.process_tx().len(), 2);
+    }
+    assert_eq!(nic.tx_contexts, 2);
+
+    // The checksum context is reloaded after segmentation.
+    let csum = TxOffload {
+        csum: TxChecksum::new(L4_START, l4_csum_offset(IPPROTO_TCP)),
+        ..TxOffload::default()
+    };
+    let frame = ipv4_frame(1, 500, IPPROTO_TCP);
+    dev.tx.e1000_transmit_offload([&frame[..]], csum);
+    assert!(l4_checksum_ok(&nic.process_tx()[0]));
+    assert_eq!(nic.tx_contexts, 3);
+}
+
+#[test]
+fn super_packet_without_payload_is_rejected() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    let frame = tso_frame(0, ACK);
+
+    assert_eq!(dev.tx.e1000_transmit_offload([&frame[..]], tso(1448)), -1);
+    assert_eq!(nic.regs.read(reg::TDT), 0);
+    assert!(nic.process_tx().is_empty());
+}
+
+#[test]
+fn invalid_segmentation_is_refused() {
+    assert!(TxTso::new(IP_START, L4_START, HDR_LEN, 1448).is_some());
+    assert!(TxTso::new(IP_START, L4_START, HDR_LEN, 0).is_none());
+    assert!(TxTso::new(IP_START, L4_START, HDR_LEN, 1 << 16).is_none());
+    assert!(TxTso::new(IP_START, IP_START + 10, HDR_LEN, 1448).is_none());
+    assert!(TxTso::new(IP_START, L4_START, L4_START + 10, 1448).is_none());
+    assert!(TxTso::new(IP_START, 240, 260, 1448).is_none());
+}
@@ -0,0 +1,118 @@
+//! TX descriptor reclamation and queue flow control.
+
+mod common;
+
+use common::setup;
+use e1000_sim::e1000::{E1000Config, TxChecksum, TxOffload};
+use e1000_sim::model::{IPPROTO_TCP, L4_START, SimNic, ipv4_frame, reg, test_frame};
+
+/// A context descriptor, then 18 fragments each ending with a short chunk
+/// of a 64K frame over 2048 byte buffers
+const NEEDED: usize = 1 + 18 + 32;
+
+#[test]
+fn completed_frames_are_counted_once() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    assert_eq!(dev.tx.e1000_clean_tx(), 0);
+
+    for seq in 0..3 {
+        dev.tx.e1000_transmit(&test_frame(seq, 100));
+    }
+    // Not sent yet: TDH has not moved.
+    assert_eq!(dev.tx.e1000_clean_tx(), 0);
+
+    nic.process_tx();
+    assert_eq!(dev.tx.e1000_clean_tx(), 3);
+    assert_eq!(dev.tx.e1000_clean_tx(), 0);
+}
+
+#[test]
+fn multi_descriptor_frames_count_as_one() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    let frame = ipv4_frame(1, 300, IPPROTO_TCP);
+    let (head, tail) = frame.split_at(100);
+    let offload = TxOffload {
+        csum: TxChecksum::new(L4_START, L4_START + 16),
+        ..TxOffload::default()
+    };
+
+    // Context descriptor plus two data descriptors, then three fragments.
+    dev.tx.e1000_transmit_offload([head, tail], offload);
+    dev.tx.e1000_transmit_sg([head, &tail[..100], &tail[100..]]);
+    assert_eq!(nic.regs.read(reg::TDT), 6);
+
+    assert_eq!(nic.process_tx().len(), 2);
+    assert_eq!(dev.tx.e1000_clean_tx(), 2);
+}
+
+#[test]
+fn fetched_but_unfinished_descriptors_are_left_alone() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    for seq in 0..2 {
+        dev.tx.e1000_transmit(&test_frame(seq, 100));
+    }
+    nic.process_tx();
+
+    // The NIC fetched a third descriptor but has not written it back.
+    dev.tx.e1000_transmit(&test_frame(2, 100));
+    nic.regs.write(reg::TDH, 3);
+    assert_eq!(dev.tx.e1000_clean_tx(), 2);
+
+    nic.regs.write(reg::TDH, 2);
+    nic.process_tx();
+    assert_eq!(dev.tx.e1000_clean_tx(), 1);
+}
+
+#[test]
+fn ring_full_stops_and_cleaning_restarts_the_queue() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+
+    let mut sent = 0;
+    while !dev.tx.e1000_tx_ring_full() {
+        assert_eq!(dev.tx.e1000_transmit(&test_frame(sent, 100)), 100);
+        sent += 1;
+    }
+    // 255 usable descriptors, stopped with fewer than NEEDED left.
+    assert_eq!(sent as usize, 255 - (NEEDED - 1));
+
+    // Frames still go out until the ring really is full.
+    for seq in sent..255 {
+        assert_eq!(dev.tx.e1000_transmit(&test_frame(seq, 100)), 100);
+    }
+    assert_eq!(dev.tx.e1000_transmit(&test_frame(255, 100)), -1);
+
+    assert_eq!(nic.process_tx().len(), 255);
+    assert!(dev.tx.e1000_tx_ring_full());
+    assert_eq!(dev.tx.e1000_clean_tx(), 255);
+    assert!(!dev.tx.e1000_tx_ring_full());
+}
+
+#[test]
+fn transmit_reclaims_by_itself_when_short() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    for seq in 0..255 {
+        dev.tx.e1000_transmit(&test_frame(seq, 100));
+    }
+    nic.process_tx();
+
+    // No clean in between: transmit does it.
+    let frame = test_frame(255, 100);
+    assert_eq!(dev.tx.e1000_transmit(&frame), 100);
+    assert_eq!(nic.process_tx(), [frame]);
+    assert_eq!(dev.tx.e1000_clean_tx(), 1);
+}
+
+#[test]
+fn small_rings_stop_until_empty() {
+    let nic = SimNic::new();
+    let config = E1000Config {
+        tx_ring_size: 48,
+        ..E1000Config::default()
+    };
+    let (_, mut dev) = nic.device(config);
+
+    // Fewer descriptors than the worst case frame: any frame in flight stops the queue.
+    assert!(!dev.tx.e1000_tx_ring_full());
+    dev.tx.e1000_transmit(&test_frame(1, 100));
+    assert!(dev.tx.e1000_tx_ring_full());
+}
@@ -0,0 +1,149 @@
+//! Transmit from streaming DMA mappings of the frame.
+
+mod common;
+
+use common::setup;
+use e1000_sim::e1000::{E1000_TX_COPYBREAK, E1000Config, TxOffload};
+use e1000_sim::model::{FrameFate, MockFrame, reg, test_frame};
+
+#[test]
+fn fragments_are_read_in_place() {
+    let (mut nic, kfn, mut dev) = setup(E1000Config::default());
+    let frame = test_frame(1, 1514);
+    let (header, payload) = frame.split_at(54);
+    let (tx, fate) = MockFrame::new(&[header, payload]);
+
+    assert!(
+        dev.tx
+            .e1000_transmit_frame(tx, TxOffload::default())
+            .is_ok()
+    );
+    assert_eq!(nic.regs.read(reg::TDT), 2);
+    assert_eq!(kfn.dma.borrow().mapped(), 2);
+    // Neither descriptor points into the copy buffers.
+    assert!(!kfn.dma.borrow().contains(nic.tx_addr(0) as *const u8));
+    assert!(!kfn.dma.borrow().contains(nic.tx_addr(1) as *const u8));
+    assert_eq!(fate.get(), FrameFate::Held);
+
+    assert_eq!(nic.process_tx(), vec![frame]);
+    assert_eq!(dev.tx.e1000_clean_tx(), 1);
+    assert_eq!(kfn.dma.borrow().mapped(), 0);
+    assert_eq!(fate.get(), FrameFate::Consumed);
+}
+
+#[test]
+fn tiny_frames_are_copied() {
+    let (mut nic, kfn, mut dev) = setup(E1000Config::default());
+    let frame = test_frame(1, E1000_TX_COPYBREAK - 1);
+    let (tx, fate) = MockFrame::new(&[&frame]);
+
+    assert_eq!(
+        dev.tx.e1000_transmit_frame(tx, TxOffload::default()).ok(),
+        Some(frame.len())
+    );
+    assert_eq!(kfn.dma.borrow().maps, 0);
+    assert!(kfn.dma.borrow().contains(nic.tx_addr(0) as *const u8));
+    // The copy is all the NIC needs.
+    assert_eq!(fate.get(), FrameFate::Consumed);
+    assert_eq!(nic.process_tx(), vec![frame]);
+}
+
+#[test]
+fn frames_that_cannot_be_mapped_are_copied() {
+    let (mut nic, kfn, mut dev) = setup(E1000Config::default());
+    let frame = test_frame(1, 1000);
+    let (head, tail) = frame.split_at(500);
+    kfn.dma.borrow_mut().fail_map_at = Some(1);
+    let (tx, fate) = MockFrame::new(&[head, tail]);
+
+    assert!(
+        dev.tx
+            .e1000_transmit_frame(tx, TxOffload::default())
+            .is_ok()
+    );
+    // The first fragment was mapped, then unmapped again.
+    assert_eq!(kfn.dma.borrow().maps, 1);
+    assert_eq!(kfn.dma.borrow().mapped(), 0);
+    assert_eq!(fate.get(), FrameFate::Consumed);
+    assert_eq!(nic.process_tx(), vec![frame]);
+}
+
+#[test]
+fn large_fragments_share_one_mapping() {
+    let (mut nic, kfn, mut dev) = setup(E1000Config::default());
+    let frame = test_frame(1, 9014);
+    let (tx, _) = MockFrame::new(&[&frame]);
+
+    assert!(
+        dev.tx
+            .e1000_transmit_frame(tx, TxOffload::default())
+            .is_ok()
+    );
+    // 4096 bytes per descriptor.
+    assert_eq!(nic.regs.read(reg::TDT), 3);
+    assert_eq!(nic.tx_addr(1), nic.tx_addr(0) + 4096);
+    assert_eq!(kfn.dma.borrow().mapped(), 1);
+    assert_eq!(nic.process_tx(), vec![frame]);
+}
+
+#[test]
+fn frames_stay_mapped_until_their_last_descriptor_is_done() {
+    let (mut nic, kfn, mut dev) = setup(E1000Config::default());
+    let frame = test_frame(1, 1000);
+    let (head, tail) = frame.split_at(500);
+    let (tx, fate) = MockFrame::new(&[head, tail]);
+    dev.tx.e1000_transmit_frame(tx, TxOffload::default()).ok();
+
+    // The NIC has fetched both descriptors but only finished the first.
+    nic.process_tx();
+    nic.regs.write(reg::TDH, 2);
+    let status = nic.tx_status(1);
+    nic.set_tx_status(1, 0);
+    assert_eq!(dev.tx.e1000_clean_tx(), 0);
+    assert_eq!(kfn.dma.borrow().mapped(), 2);
+    assert_eq!(fate.get(), FrameFate::Held);
+
+    nic.set_tx_status(1, status);
+    assert_eq!(dev.tx.e1000_clean_tx(), 1);
+    assert_eq!(kfn.dma.borrow().mapped(), 0);
+}
+
+#[test]
+fn busy_ring_hands_the_frame_back_unmapped() {
+    let (_nic, kfn, mut dev) = setup(E1000Config::default());
+    let ring = E1000Config::default().tx_ring_size;
+    let frame = test_frame(1, 1000);
+
+    for _ in 0..ring - 1 {
+        assert_eq!(dev.tx.e1000_transmit(&frame), 1000);
+    }
+    let (tx, fate) = MockFrame::new(&[&frame]);
+    let tx = dev.tx.e1000_transmit_frame(tx, TxOffload::default()).err();
+    assert!(tx.is_some());
+    assert_eq!(kfn.dma.borrow().mapped(), 0);
+    assert_eq!(fate.get(), FrameFate::Held);
+}
+
+#[test]
+fn reinit_drops_frames_in_flight() {
+    let (_nic, kfn, mut dev) = setup(E1000Config::default());
+    let frame = test_frame(1, 1000);
+    let (tx, fate) = MockFrame::new(&[&frame]);
+    dev.tx.e1000_transmit_frame(tx, TxOffload::default()).ok();
+
+    dev.reinit().unwrap();
+    assert_eq!(kfn.dma.borrow().mapped(), 0);
+    assert_eq!(fate.get(), FrameFate::Dropped);
+}
+
+#[test]
+fn dropping_the_device_unmaps_frames_in_flight() {
+    let (_nic, kfn, mut dev) = setup(E1000Config::default());
+    let frame = test_frame(1, 1000);
+    let (tx, fate) = MockFrame::new(&[&frame]);
+    dev.tx.e1000_transmit_frame(tx, TxOffload::default()).ok();
+
+    drop(dev);
+    assert_eq!(kfn.dma.borrow().mapped(), 0);
+    assert_eq!(fate.get(), FrameFate::Dropped);
+}
@@ -0,0 +1,146 @@
+//! VLAN tag insertion, stripping and filtering.
+
+mod common;
+
+use common::setup;
+use e1000_sim::e1000::{E1000Config, E1000Device, RxMode, TxChecksum, TxOffload};
+use e1000_sim::model::{
+    IPPROTO_UDP, L4_START, MockKernel, frame_bytes, ipv4_frame, l4_checksum_ok, l4_csum_offset,
+    reg, test_frame, vlan_insert,
+};
+
+const RCTL_VFE: u32 = 1 << 18;
+const CTRL_VME: u32 = 1 << 30;
+
+fn recv_all(dev: &mut E1000Device<MockKernel>) -> Vec<(Vec<u8>, Option<u16>)> {
+    let mut frames = Vec::new();
+    dev.rx.e1000_recv(64, |frame| {
+        frames.push((frame_bytes(&frame), frame.vlan_tag()))
+    });
+    frames
+}
+
+fn tagged(vid: u16) -> TxOffload {
+    TxOffload {
+        vlan: Some(vid),
+        ..TxOffload::default()
+    }
+}
+
+#[test]
+fn offload_is_off_after_init() {
+    let (nic, _, _dev) = setup(E1000Config::default());
+    assert_eq!(nic.regs.read(reg::VET), 0x8100);
+    assert_eq!(nic.regs.read(reg::CTRL) & CTRL_VME, 0);
+    assert_eq!(nic.regs.read(reg::RCTL) & RCTL_VFE, 0);
+}
+
+#[test]
+fn tags_are_inserted() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    dev.hw.e1000_set_vlan_offload(true);
+    let frame = test_frame(1, 3000);
+    let (a, b) = frame.split_at(100);
+    let tci = 0x6000 | 42;
+
+    assert_eq!(dev.tx.e1000_transmit_offload([a, b], tagged(tci)), 3000);
+    assert_eq!(nic.process_tx(), vec![vlan_insert(&frame, tci)]);
+
+    // Untagged frames that follow use the same descriptors.
+    for _ in 0..2 {
+        dev.tx.e1000_transmit(&frame);
+        assert_eq!(nic.process_tx(), vec![frame.clone()]);
+    }
+}
+
+#[test]
+fn tags_combine_with_checksum_offload() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    dev.hw.e1000_set_vlan_offload(true);
+    let frame = ipv4_frame(1, 300, IPPROTO_UDP);
+    let offload = TxOffload {
+        csum: TxChecksum::new(L4_START, l4_csum_offset(IPPROTO_UDP)),
+        vlan: Some(7),
+        ..TxOffload::default()
+    };
+
+    dev.tx.e1000_transmit_offload([&frame[..]], offload);
+    let sent = nic.process_tx().remove(0);
+    assert_eq!(sent[12..16], [0x81, 0x00, 0, 7]);
+    let untagged = [&sent[..12], &sent[16..]].concat();
+    assert!(l4_checksum_ok(&untagged));
+}
+
+#[test]
+fn tags_are_stripped() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    let frame = test_frame(1, 200);
+    let tci = 0xa000 | 100;
+    nic.inject_rx(&vlan_insert(&frame, tci));
+    assert_eq!(recv_all(&mut dev), vec![(vlan_insert(&frame, tci), None)]);
+
+    dev.hw.e1000_set_vlan_offload(true);
+    nic.inject_rx(&vlan_insert(&frame, tci));
+    nic.inject_rx(&frame);
+    assert_eq!(
+        recv_all(&mut dev),
+        vec![(frame.clone(), Some(tci)), (frame, None)]
+    );
+}
+
+#[test]
+fn filtering_drops_unknown_vlans() {
+    let (mut nic, _, mut dev) = setup(E1000Config::default());
+    let frame = test_frame(1, 200);
+    dev.hw.e1000_set_vlan_offload(true);
+    dev.hw.e1000_set_vlan_filtering(true);
+    dev.hw.e1000_vlan_rx_add_vid(5);
+    dev.hw.e1000_vlan_rx_add_vid(4095);
+    assert_ne!(nic.regs.read(reg::RCTL) & RCTL_VFE, 0);
+
+    assert!(nic.inject_rx(&vlan_insert(&frame, 5)));
+    assert!(!nic.inject_rx(&vlan_insert(&frame, 6)));
+    assert!(nic.inject_rx(&vlan_insert(&frame, 0x2000 | 4095)));
+    assert!(nic.inject_rx(&frame));
+
+    dev.hw.e1000_vlan_rx_kill_vid(5);
+    assert!(!nic.inject_rx(&vlan_insert(&frame, 5)));
+    assert_eq!(recv_all(&mut dev).len(), 3);
+}
+
+#[test]
+fn promiscuous_mode_suspends_filtering() {
+    let (nic, _, mut dev) = setup(E1000Config::default());
+    dev.hw.e1000_set_vlan_filtering(true);
+    let promisc = RxMode {
+        promisc: true,
+        allmulti: false,
+    };
+
+    dev.hw.e1000_set_rx_mode(promisc, &[], &[]);
+    assert_eq!(nic.regs.read(reg::RCTL) & RCTL_VFE, 0);
+    dev.hw.e1000_set_rx_mode(RxMode::default(), &[], &[]);
+    assert_ne!(nic.regs.read(reg::RCTL) & RCTL_VFE, 0);
+}
+
+#[test]
+fn vlan_setup_survives_reconfiguration() {
+    let (nic, _, mut dev) = setup(E1000Config::default());
+    dev.hw.e1000_set_vlan_offload(true);
+    dev.hw.e1000_set_vlan_filtering(true);
+    dev.hw.e1000_vlan_rx_add_vid(100);
+    dev.hw
+        .e1000_set_rx_mode(RxMode::default(), &[], &[[1, 0, 0x5e, 0, 0, 1]]);
+    let vfta = nic.regs.read(reg::VFTA + 4 * (100 >> 5));
+
+    dev.hw.e1000_update_stats();
+    let (_, mut new) = nic.device(E1000Config::default());
+    assert_eq!(nic.regs.read(reg::CTRL) & CTRL_VME, 0);
+    assert_eq!(nic.regs.read(reg::VFTA + 4 * (100 >> 5)), 0);
+    new.take_over(&dev.hw, &dev.rx);
+
+    assert_ne!(nic.regs.read(reg::CTRL) & CTRL_VME, 0);
+    assert_ne!(nic.regs.read(reg::RCTL) & RCTL_VFE, 0);
+    assert_eq!(nic.regs.read(reg::VFTA + 4 * (100 >> 5)), vfta);
+    assert_eq!(vfta, 1 << (100 & 31));
+}
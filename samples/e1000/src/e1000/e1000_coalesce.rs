@@ -0,0 +1,175 @@
+// Interrupt moderation settings of an E1000Device.
+
+use kernel::prelude::*;
+
+/// Lowest interrupt rate ITR may be throttled to, per second
+pub const E1000_MIN_ITR: u32 = 100;
+/// Highest interrupt rate ITR may be set to, per second
+pub const E1000_MAX_ITR: u32 = 100_000;
+/// Longest delay the 16-bit RDTR/RADV/TIDV/TADV timers hold, in microseconds
+pub const E1000_MAX_DELAY_US: u32 = 0xffff * 1024 / 1000;
+
+/// Interrupt moderation, adjustable while the device runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct E1000Coalesce {
+    /// Most interrupts per second (ITR); 0 for no limit
+    pub itr: u32,
+    /// Pick the ITR rate from the observed traffic instead of `itr`
+    pub adaptive: bool,
+    /// Delay of the RX interrupt after each packet (RDTR), in microseconds
+    pub rx_delay_us: u32,
+    /// Longest delay of the RX interrupt after the first packet (RADV)
+    pub rx_abs_delay_us: u32,
+    /// Delay of the TX interrupt after each packet (TIDV), in microseconds
+    pub tx_delay_us: u32,
+    /// Longest delay of the TX interrupt after the first packet (TADV)
+    pub tx_abs_delay_us: u32,
+}
+
+impl Default for E1000Coalesce {
+    /// The defaults of the C e1000 driver
+    fn default() -> Self {
+        Self {
+            itr: ItrLatency::Low.rate(),
+            adaptive: false,
+            rx_delay_us: 0,
+            rx_abs_delay_us: 8,
+            tx_delay_us: 8,
+            tx_abs_delay_us: 32,
+        }
+    }
+}
+
+impl E1000Coalesce {
+    /// Check the values against what the registers can hold.
+    pub fn validate(&self) -> Result {
+        if self.itr != 0 && !(E1000_MIN_ITR..=E1000_MAX_ITR).contains(&self.itr) {
+            pr_err!("e1000: invalid interrupt rate {}", self.itr);
+            return Err(EINVAL);
+        }
+        for delay in [
+            self.rx_delay_us,
+            self.rx_abs_delay_us,
+            self.tx_delay_us,
+            self.tx_abs_delay_us,
+        ] {
+            if delay > E1000_MAX_DELAY_US {
+                pr_err!("e1000: invalid interrupt delay {}us", delay);
+                return Err(EINVAL);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// ITR value for `rate` interrupts per second, in 256ns units [E1000 13.4.18]
+pub(crate) fn itr_reg(rate: u32) -> u32 {
+    if rate == 0 {
+        0
+    } else {
+        1_000_000_000 / (rate * 256)
+    }
+}
+
+/// Delay timer value for `us` microseconds, in 1.024us units [E1000 13.4.30]
+pub(crate) fn delay_reg(us: u32) -> u32 {
+    (us * 1000 + 512) / 1024
+}
+
+/// Traffic classes of the adaptive mode, as in the C driver's e1000_update_itr()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ItrLatency {
+    Lowest,
+    Low,
+    Bulk,
+}
+
+impl ItrLatency {
+    fn rate(self) -> u32 {
+        match self {
+            ItrLatency::Lowest => 70_000,
+            ItrLatency::Low => 20_000,
+            ItrLatency::Bulk => 4_000,
+        }
+    }
+
+    /// The class for one interrupt's worth of traffic in one direction
+    fn next(self, packets: u32, bytes: u64) -> Self {
+        use ItrLatency::*;
+        if packets == 0 {
+            return self;
+        }
+        let per_packet = bytes / packets as u64;
+        match self {
+            Lowest if per_packet > 8000 => Bulk,
+            Lowest if packets < 5 && bytes > 512 => Low,
+            Low if bytes > 10000 => {
+                if per_packet > 8000 || packets < 10 || per_packet > 1200 {
+                    Bulk
+                } else if packets > 35 {
+                    Lowest
+                } else {
+                    Low
+                }
+            }
+            Low if per_packet > 2000 => Bulk,
+            Low if packets <= 2 && bytes < 512 => Lowest,
+            Bulk if bytes > 25000 && packets > 35 => Low,
+            Bulk if bytes < 6000 => Lowest,
+            _ => self,
+        }
+    }
+}
+
+/// Packets and bytes moved in one direction since the last interrupt
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct ItrSample {
+    packets: u32,
+    bytes: u64,
+}
+
+impl ItrSample {
+    pub(crate) fn count(&mut self, packets: usize, bytes: usize) {
+        self.packets = self.packets.saturating_add(packets as u32);
+        self.bytes += bytes as u64;
+    }
+}
+
+/// State of the adaptive mode
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AdaptiveItr {
+    rx_latency: ItrLatency,
+    tx_latency: ItrLatency,
+    /// Interrupts per second currently programmed
+    pub(crate) rate: u32,
+}
+
+impl AdaptiveItr {
+    pub(crate) fn new() -> Self {
+        Self {
+            rx_latency: ItrLatency::Low,
+            tx_latency: ItrLatency::Low,
+            rate: ItrLatency::Low.rate(),
+        }
+    }
+
+    /// Classify the traffic since the last call and return the new rate
+    /// if it changed. Called once per interrupt.
+    pub(crate) fn update(&mut self, rx: ItrSample, tx: ItrSample) -> Option<u32> {
+        self.rx_latency = self.rx_latency.next(rx.packets, rx.bytes);
+        self.tx_latency = self.tx_latency.next(tx.packets, tx.bytes);
+
+        // The direction that wants the fewest interrupts decides.
+        let target = self.rx_latency.rate().min(self.tx_latency.rate());
+        // Ramp up gradually, drop at once.
+        let rate = if target > self.rate {
+            (self.rate + target / 4).min(target)
+        } else {
+            target
+        };
+        (rate != self.rate).then(|| {
+            self.rate = rate;
+            rate
+        })
+    }
+}
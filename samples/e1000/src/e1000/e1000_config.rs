@@ -0,0 +1,119 @@
+// Ring and buffer dimensions of an E1000Device.
+
+use super::e1000_const::*;
+use super::e1000_error::E1000Error;
+use kernel::prelude::*;
+
+/// Fewest descriptors a ring may have
+pub const E1000_MIN_RING_SIZE: usize = 48;
+/// Most descriptors a ring may have
+pub const E1000_MAX_RING_SIZE: usize = 4096;
+/// TDLEN/RDLEN must be 128-byte aligned: a multiple of 8 descriptors
+pub const E1000_RING_SIZE_MULTIPLE: usize = 8;
+
+/// Ethernet header length
+pub const ETH_HLEN: usize = 14;
+/// Ethernet frame check sequence length
+pub const ETH_FCS_LEN: usize = 4;
+/// Standard ethernet MTU; anything larger needs RCTL.LPE
+pub const ETH_DATA_LEN: usize = 1500;
+/// Smallest MTU (the IPv4 minimum)
+pub const E1000_MIN_MTU: usize = 68;
+/// Largest frame the MAC accepts with RCTL.LPE set, FCS included
+pub const E1000_MAX_JUMBO_FRAME_SIZE: usize = 16128;
+/// Largest MTU
+pub const E1000_MAX_MTU: usize = E1000_MAX_JUMBO_FRAME_SIZE - ETH_HLEN - ETH_FCS_LEN;
+
+/// Layout of the EEPROM Read register, which differs between parts
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum E1000Eerd {
+    /// 8254x: DONE is bit 4, the word address bits 15:8
+    #[default]
+    I8254x,
+    /// 82571 and later, like the 82574L: DONE is bit 1, the address bits 15:2
+    I8257x,
+    /// None at all; ICH/PCH parts like the I219 keep the NVM in flash
+    None,
+}
+
+/// Ring and buffer sizes, fixed for the lifetime of an `E1000Device`,
+/// and the EERD layout of the part they are used on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct E1000Config {
+    /// Number of TX descriptors
+    pub tx_ring_size: usize,
+    /// Number of RX descriptors
+    pub rx_ring_size: usize,
+    /// Size of each packet buffer; one of the sizes RCTL.BSIZE can encode.
+    /// Frames larger than a buffer span several descriptors.
+    pub buffer_size: usize,
+    /// Largest payload of a frame
+    pub mtu: usize,
+    /// How to read the station address when RA[0] was not loaded
+    pub eerd: E1000Eerd,
+}
+
+impl Default for E1000Config {
+    fn default() -> Self {
+        Self {
+            tx_ring_size: 256,
+            rx_ring_size: 256,
+            buffer_size: 2048,
+            mtu: ETH_DATA_LEN,
+            eerd: E1000Eerd::default(),
+        }
+    }
+}
+
+impl E1000Config {
+    /// The closest valid ring size to `n`: clamped to the supported range
+    /// and rounded up to a multiple of [`E1000_RING_SIZE_MULTIPLE`].
+    pub fn ring_size_for(n: usize) -> usize {
+        n.clamp(E1000_MIN_RING_SIZE, E1000_MAX_RING_SIZE)
+            .next_multiple_of(E1000_RING_SIZE_MULTIPLE)
+    }
+
+    /// Check the sizes against what the hardware can be programmed with.
+    pub fn validate(&self) -> Result<(), E1000Error> {
+        for size in [self.tx_ring_size, self.rx_ring_size] {
+            if !(E1000_MIN_RING_SIZE..=E1000_MAX_RING_SIZE).contains(&size)
+                || !size.is_multiple_of(E1000_RING_SIZE_MULTIPLE)
+            {
+                pr_err!("e1000: invalid ring size {}", size);
+                return Err(E1000Error::InvalidRingSize(size));
+            }
+        }
+        if !(E1000_MIN_MTU..=E1000_MAX_MTU).contains(&self.mtu) {
+            pr_err!("e1000: invalid MTU {}", self.mtu);
+            return Err(E1000Error::InvalidMtu(self.mtu));
+        }
+        if self.rctl_bsize().is_none() {
+            pr_err!("e1000: invalid buffer size {}", self.buffer_size);
+            return Err(E1000Error::InvalidBufferSize(self.buffer_size));
+        }
+        Ok(())
+    }
+
+    /// RCTL.LPE if the MTU needs frames longer than the standard 1522 bytes
+    pub(crate) fn rctl_lpe(&self) -> u32 {
+        if self.mtu > ETH_DATA_LEN {
+            E1000_RCTL_LPE
+        } else {
+            0
+        }
+    }
+
+    /// RCTL.BSIZE and RCTL.BSEX bits for the buffer size [E1000 13.4.22]
+    pub(crate) fn rctl_bsize(&self) -> Option<u32> {
+        match self.buffer_size {
+            256 => Some(E1000_RCTL_SZ_256),
+            512 => Some(E1000_RCTL_SZ_512),
+            1024 => Some(E1000_RCTL_SZ_1024),
+            2048 => Some(E1000_RCTL_SZ_2048),
+            4096 => Some(E1000_RCTL_SZ_4096 | E1000_RCTL_BSEX),
+            8192 => Some(E1000_RCTL_SZ_8192 | E1000_RCTL_BSEX),
+            16384 => Some(E1000_RCTL_SZ_16384 | E1000_RCTL_BSEX),
+            _ => None,
+        }
+    }
+}
@@ -1,36 +1,101 @@
 // E1000 hardware definitions: registers and DMA ring format.
 // from the Intel 82540EP/EM &c manual.
 
+use super::e1000_regs::{Reg, RegArray};
+
 /* Registers */
-pub(crate) const E1000_CTL: usize = 0x00000; /* Device Control Register - RW */
-pub(crate) const E1000_STAT: usize = 0x00008 / 4; /* Device Status Register - R */
-pub(crate) const E1000_ICR: usize = 0x000C0 / 4; /* Interrupt Cause Read - R */
-pub(crate) const E1000_ITR: usize = 0x000C4 / 4; /* Interrupt Throttling Rate - RW */
-pub(crate) const E1000_ICS: usize = 0x000C8 / 4; /* Interrupt Cause Set - WO */
-pub(crate) const E1000_IMS: usize = 0x000D0 / 4; /* Interrupt Mask Set - RW */
-pub(crate) const E1000_IMC: usize = 0x000D8 / 4; /* Interrupt Mask Clear - WO */
-pub(crate) const E1000_RCTL: usize = 0x00100 / 4; /* RX Control - RW */
-pub(crate) const E1000_TCTL: usize = 0x00400 / 4; /* TX Control - RW */
-pub(crate) const E1000_TIPG: usize = 0x00410 / 4; /* TX Inter-packet gap -RW */
-pub(crate) const E1000_RDBAL: usize = 0x02800 / 4; /* RX Descriptor Base Address Low - RW */
-pub(crate) const E1000_RDBAH: usize = 0x02804 / 4; /* RX Descriptor Base Address High - RW */
-pub(crate) const E1000_RDTR: usize = 0x02820 / 4; /* RX Delay Timer */
-pub(crate) const E1000_RADV: usize = 0x0282C / 4; /* RX Interrupt Absolute Delay Timer */
-pub(crate) const E1000_RDH: usize = 0x02810 / 4; /* RX Descriptor Head - RW */
-pub(crate) const E1000_RDT: usize = 0x02818 / 4; /* RX Descriptor Tail - RW */
-pub(crate) const E1000_RDLEN: usize = 0x02808 / 4; /* RX Descriptor Length - RW */
-pub(crate) const E1000_RSRPD: usize = 0x02C00 / 4; /* RX Small Packet Detect Interrupt */
-pub(crate) const E1000_TDBAL: usize = 0x03800 / 4; /* TX Descriptor Base Address Low - RW */
-pub(crate) const E1000_TDBAH: usize = 0x03804 / 4; /* TX Descriptor Base Address High - RW */
-pub(crate) const E1000_TDLEN: usize = 0x03808 / 4; /* TX Descriptor Length - RW */
-pub(crate) const E1000_TDH: usize = 0x03810 / 4; /* TX Descriptor Head - RW */
-pub(crate) const E1000_TDT: usize = 0x03818 / 4; /* TX Descripotr Tail - RW */
-pub(crate) const E1000_TIDV: usize = 0x03820 / 4; /* TX Interrupt Delay Value - RW */
-pub(crate) const E1000_TADV: usize = 0x0382C / 4; /* TX Interrupt Absolute Delay Val - RW */
-pub(crate) const E1000_MTA: usize = 0x05200 / 4; /* Multicast Table Array - RW Array */
-pub(crate) const E1000_RA: usize = 0x05400 / 4; /* Receive Address Low are used for unicast/multicast address filtering. - RW Array */
-
-pub(crate) const E1000_RFCTL: usize = 0x05008 / 4; /* e1000e: RFCTL */
+pub(crate) const E1000_CTL: Reg = Reg::new(0x00000); /* Device Control Register - RW */
+pub(crate) const E1000_STAT: Reg = Reg::new(0x00008); /* Device Status Register - R */
+pub(crate) const E1000_EERD: Reg = Reg::new(0x00014); /* EEPROM Read - RW */
+pub(crate) const E1000_CTRL_EXT: Reg = Reg::new(0x00018); /* Extended Device Control - RW */
+pub(crate) const E1000_VET: Reg = Reg::new(0x00038); /* VLAN Ether Type - RW */
+pub(crate) const E1000_ICR: Reg = Reg::new(0x000C0); /* Interrupt Cause Read - R */
+pub(crate) const E1000_ITR: Reg = Reg::new(0x000C4); /* Interrupt Throttling Rate - RW */
+pub(crate) const E1000_ICS: Reg = Reg::new(0x000C8); /* Interrupt Cause Set - WO */
+pub(crate) const E1000_IMS: Reg = Reg::new(0x000D0); /* Interrupt Mask Set - RW */
+pub(crate) const E1000_IMC: Reg = Reg::new(0x000D8); /* Interrupt Mask Clear - WO */
+pub(crate) const E1000_RCTL: Reg = Reg::new(0x00100); /* RX Control - RW */
+pub(crate) const E1000_TCTL: Reg = Reg::new(0x00400); /* TX Control - RW */
+pub(crate) const E1000_TIPG: Reg = Reg::new(0x00410); /* TX Inter-packet gap -RW */
+pub(crate) const E1000_RDBAL: Reg = Reg::new(0x02800); /* RX Descriptor Base Address Low - RW */
+pub(crate) const E1000_RDBAH: Reg = Reg::new(0x02804); /* RX Descriptor Base Address High - RW */
+pub(crate) const E1000_RDTR: Reg = Reg::new(0x02820); /* RX Delay Timer */
+pub(crate) const E1000_RADV: Reg = Reg::new(0x0282C); /* RX Interrupt Absolute Delay Timer */
+pub(crate) const E1000_RDH: Reg = Reg::new(0x02810); /* RX Descriptor Head - RW */
+pub(crate) const E1000_RDT: Reg = Reg::new(0x02818); /* RX Descriptor Tail - RW */
+pub(crate) const E1000_RDLEN: Reg = Reg::new(0x02808); /* RX Descriptor Length - RW */
+pub(crate) const E1000_RSRPD: Reg = Reg::new(0x02C00); /* RX Small Packet Detect Interrupt */
+pub(crate) const E1000_TDBAL: Reg = Reg::new(0x03800); /* TX Descriptor Base Address Low - RW */
+pub(crate) const E1000_TDBAH: Reg = Reg::new(0x03804); /* TX Descriptor Base Address High - RW */
+pub(crate) const E1000_TDLEN: Reg = Reg::new(0x03808); /* TX Descriptor Length - RW */
+pub(crate) const E1000_TDH: Reg = Reg::new(0x03810); /* TX Descriptor Head - RW */
+pub(crate) const E1000_TDT: Reg = Reg::new(0x03818); /* TX Descripotr Tail - RW */
+pub(crate) const E1000_TIDV: Reg = Reg::new(0x03820); /* TX Interrupt Delay Value - RW */
+pub(crate) const E1000_TADV: Reg = Reg::new(0x0382C); /* TX Interrupt Absolute Delay Val - RW */
+pub(crate) const E1000_MTA: RegArray<128> = RegArray::new(0x05200); /* Multicast Table Array - RW Array */
+pub(crate) const E1000_RA: RegArray<32> = RegArray::new(0x05400); /* Receive Address Low are used for unicast/multicast address filtering. - RW Array */
+pub(crate) const E1000_VFTA: RegArray<128> = RegArray::new(0x05600); /* VLAN Filter Table Array - RW Array */
+
+pub(crate) const E1000_RXCSUM: Reg = Reg::new(0x05000); /* RX Checksum Control - RW */
+pub(crate) const E1000_RFCTL: Reg = Reg::new(0x05008); /* e1000e: RFCTL */
+
+/* Statistics, clear on read [E1000 13.7] */
+pub(crate) const E1000_CRCERRS: Reg = Reg::new(0x04000); /* CRC Error Count - R/clr */
+pub(crate) const E1000_ALGNERRC: Reg = Reg::new(0x04004); /* Alignment Error Count - R/clr */
+pub(crate) const E1000_SYMERRS: Reg = Reg::new(0x04008); /* Symbol Error Count - R/clr */
+pub(crate) const E1000_RXERRC: Reg = Reg::new(0x0400C); /* Receive Error Count - R/clr */
+pub(crate) const E1000_MPC: Reg = Reg::new(0x04010); /* Missed Packet Count - R/clr */
+pub(crate) const E1000_SCC: Reg = Reg::new(0x04014); /* Single Collision Count - R/clr */
+pub(crate) const E1000_ECOL: Reg = Reg::new(0x04018); /* Excessive Collision Count - R/clr */
+pub(crate) const E1000_MCC: Reg = Reg::new(0x0401C); /* Multiple Collision Count - R/clr */
+pub(crate) const E1000_LATECOL: Reg = Reg::new(0x04020); /* Late Collision Count - R/clr */
+pub(crate) const E1000_COLC: Reg = Reg::new(0x04028); /* Collision Count - R/clr */
+pub(crate) const E1000_DC: Reg = Reg::new(0x04030); /* Defer Count - R/clr */
+pub(crate) const E1000_TNCRS: Reg = Reg::new(0x04034); /* TX-No CRS - R/clr */
+pub(crate) const E1000_SEC: Reg = Reg::new(0x04038); /* Sequence Error Count - R/clr */
+pub(crate) const E1000_CEXTERR: Reg = Reg::new(0x0403C); /* Carrier Extension Error Count - R/clr */
+pub(crate) const E1000_RLEC: Reg = Reg::new(0x04040); /* Receive Length Error Count - R/clr */
+pub(crate) const E1000_XONRXC: Reg = Reg::new(0x04048); /* XON RX Count - R/clr */
+pub(crate) const E1000_XONTXC: Reg = Reg::new(0x0404C); /* XON TX Count - R/clr */
+pub(crate) const E1000_XOFFRXC: Reg = Reg::new(0x04050); /* XOFF RX Count - R/clr */
+pub(crate) const E1000_XOFFTXC: Reg = Reg::new(0x04054); /* XOFF TX Count - R/clr */
+pub(crate) const E1000_FCRUC: Reg = Reg::new(0x04058); /* Flow Control RX Unsupported Count- R/clr */
+pub(crate) const E1000_GPRC: Reg = Reg::new(0x04074); /* Good Packets RX Count - R/clr */
+pub(crate) const E1000_BPRC: Reg = Reg::new(0x04078); /* Broadcast Packets RX Count - R/clr */
+pub(crate) const E1000_MPRC: Reg = Reg::new(0x0407C); /* Multicast Packets RX Count - R/clr */
+pub(crate) const E1000_GPTC: Reg = Reg::new(0x04080); /* Good Packets TX Count - R/clr */
+pub(crate) const E1000_GORCL: Reg = Reg::new(0x04088); /* Good Octets RX Count Low - R/clr */
+pub(crate) const E1000_GORCH: Reg = Reg::new(0x0408C); /* Good Octets RX Count High - R/clr */
+pub(crate) const E1000_GOTCL: Reg = Reg::new(0x04090); /* Good Octets TX Count Low - R/clr */
+pub(crate) const E1000_GOTCH: Reg = Reg::new(0x04094); /* Good Octets TX Count High - R/clr */
+pub(crate) const E1000_RNBC: Reg = Reg::new(0x040A0); /* RX No Buffers Count - R/clr */
+pub(crate) const E1000_RUC: Reg = Reg::new(0x040A4); /* RX Undersize Count - R/clr */
+pub(crate) const E1000_RFC: Reg = Reg::new(0x040A8); /* RX Fragment Count - R/clr */
+pub(crate) const E1000_ROC: Reg = Reg::new(0x040AC); /* RX Oversize Count - R/clr */
+pub(crate) const E1000_RJC: Reg = Reg::new(0x040B0); /* RX Jabber Count - R/clr */
+pub(crate) const E1000_TORL: Reg = Reg::new(0x040C0); /* Total Octets RX Low - R/clr */
+pub(crate) const E1000_TORH: Reg = Reg::new(0x040C4); /* Total Octets RX High - R/clr */
+pub(crate) const E1000_TOTL: Reg = Reg::new(0x040C8); /* Total Octets TX Low - R/clr */
+pub(crate) const E1000_TOTH: Reg = Reg::new(0x040CC); /* Total Octets TX High - R/clr */
+pub(crate) const E1000_TPR: Reg = Reg::new(0x040D0); /* Total Packets RX - R/clr */
+pub(crate) const E1000_TPT: Reg = Reg::new(0x040D4); /* Total Packets TX - R/clr */
+pub(crate) const E1000_MPTC: Reg = Reg::new(0x040F0); /* Multicast Packets TX Count - R/clr */
+pub(crate) const E1000_BPTC: Reg = Reg::new(0x040F4); /* Broadcast Packets TX Count - R/clr */
+pub(crate) const E1000_TSCTC: Reg = Reg::new(0x040F8); /* TCP Segmentation Context TX - R/clr */
+pub(crate) const E1000_TSCTFC: Reg = Reg::new(0x040FC); /* TCP Segmentation Context TX Fail - R/clr */
+
+/* EEPROM Read [E1000 13.4.4] */
+pub(crate) const E1000_EERD_START: u32 = 0x00000001; /* Start Read */
+pub(crate) const E1000_EERD_DONE: u32 = 0x00000010; /* Read Done */
+pub(crate) const E1000_EERD_ADDR_SHIFT: u32 = 8;
+pub(crate) const E1000_EERD_DATA_SHIFT: u32 = 16;
+/* The 82571 and later moved DONE and widened the address [82574 10.2.2.8] */
+pub(crate) const E1000_NVM_RW_REG_DONE: u32 = 0x00000002; /* Read Done */
+pub(crate) const E1000_NVM_RW_ADDR_SHIFT: u32 = 2;
+pub(crate) const E1000_EEPROM_MAC_ADDR: u16 = 0x00; /* Ethernet address, words 0-2 */
+
+/* Receive Address High [E1000 13.5.3] */
+pub(crate) const E1000_RAH_AV: u32 = 0x80000000; /* Address Valid */
 
 /* This defines the bits that are set in the Interrupt Mask
  * Set/Read Register.  Each bit is documented below:
@@ -41,7 +106,7 @@ pub(crate) const E1000_RFCTL: usize = 0x05008 / 4; /* e1000e: RFCTL */
  *   o LSC    = Link Status Change
  */
 pub(crate) const IMS_ENABLE_MASK: u32 = E1000_IMS_RXT0 /* | E1000_IMS_RXDMT0 | E1000_IMS_RXSEQ */
-    | E1000_IMS_LSC /* | E1000_IMS_TXQE | E1000_IMS_TXDW */;
+    | E1000_IMS_LSC /* | E1000_IMS_TXQE */ | E1000_IMS_TXDW;
 
 pub(crate) const E1000_IMS_TXDW: u32 = 0x00000001;
 pub(crate) const E1000_IMS_TXQE: u32 = 0x00000002;
@@ -51,12 +116,28 @@ pub(crate) const E1000_IMS_RXDMT0: u32 = 0x00000010;
 pub(crate) const E1000_IMS_RXT0: u32 = 0x00000080;
 
 pub(crate) const E1000_ICR_LSC: u32 = 0x00000004; /* Link Status Change */
+pub(crate) const E1000_ICR_RXT0: u32 = 0x00000080; /* rx timer intr (ring 0) */
+
+/* Device Status */
+pub(crate) const E1000_STATUS_FD: u32 = 0x00000001; /* Full duplex.0=half,1=full */
+pub(crate) const E1000_STATUS_LU: u32 = 0x00000002; /* Link up.0=no,1=link */
+pub(crate) const E1000_STATUS_SPEED_MASK: u32 = 0x000000C0;
+pub(crate) const E1000_STATUS_SPEED_10: u32 = 0x00000000; /* Speed 10Mb/s */
+pub(crate) const E1000_STATUS_SPEED_100: u32 = 0x00000040; /* Speed 100Mb/s */
+pub(crate) const E1000_STATUS_SPEED_1000: u32 = 0x00000080; /* Speed 1000Mb/s */
+pub(crate) const E1000_STATUS_GIO_MASTER_EN: u32 = 0x00080000; /* PCIe: master requests enabled */
 
 /* Device Control */
+pub(crate) const E1000_CTL_GIO_MASTER_DISABLE: u32 = 0x00000004; /* PCIe: block new master requests */
+pub(crate) const E1000_CTL_ASDE: u32 = 0x00000020; /* Auto-speed detect enable */
 pub(crate) const E1000_CTL_SLU: u32 = 0x00000040; /* set link up */
 pub(crate) const E1000_CTL_FRCSPD: u32 = 0x00000800; /* force speed */
 pub(crate) const E1000_CTL_FRCDPLX: u32 = 0x00001000; /* force duplex */
-pub(crate) const E1000_CTL_RST: u32 = (1 << 26); /* Device Reset */
+pub(crate) const E1000_CTL_RST: u32 = 1 << 26; /* Device Reset */
+pub(crate) const E1000_CTL_VME: u32 = 0x40000000; /* IEEE VLAN mode enable */
+
+/* Extended Device Control */
+pub(crate) const E1000_CTRL_EXT_EE_RST: u32 = 0x00002000; /* Reinitialize from EEPROM */
 
 /* Transmit Control */
 pub(crate) const E1000_TCTL_RST: u32 = 0x00000001; /* software reset */
@@ -116,10 +197,21 @@ pub(crate) const E1000_RCTL_FLXBUF_MASK: u32 = 0x78000000; /* Flexible buffer si
 pub(crate) const E1000_RCTL_FLXBUF_SHIFT: u32 = 27; /* Flexible buffer shift */
 
 pub(crate) const DATA_MAX: u32 = 1518;
+pub(crate) const ETH_P_8021Q: u32 = 0x8100; /* 802.1Q VLAN Extended Header */
 
 /* Transmit Descriptor command definitions [E1000 3.3.3.1] */
 pub(crate) const E1000_TXD_CMD_EOP: u32 = 0x01; /* End of Packet */
 pub(crate) const E1000_TXD_CMD_RS: u32 = 0x08; /* Report Status */
+pub(crate) const E1000_TXD_CMD_DEXT: u32 = 0x20; /* Descriptor extension (0 = legacy) */
+pub(crate) const E1000_TXD_CMD_VLE: u32 = 0x40; /* Add VLAN tag */
+pub(crate) const E1000_TXD_CMD_IDE: u32 = 0x80; /* Enable Tidv register */
+pub(crate) const E1000_TXD_CMD_TCP: u32 = 0x01; /* TCP packet (context) */
+pub(crate) const E1000_TXD_CMD_IP: u32 = 0x02; /* IP packet (context) */
+pub(crate) const E1000_TXD_CMD_TSE: u32 = 0x04; /* TCP Seg enable */
+pub(crate) const E1000_TXD_DTYP_D: u32 = 0x10; /* Data Descriptor (in the DTYP byte) */
+pub(crate) const E1000_TXD_DTYP_C: u32 = 0x00; /* Context Descriptor (in the DTYP byte) */
+pub(crate) const E1000_TXD_POPTS_IXSM: u32 = 0x01; /* Insert IP checksum */
+pub(crate) const E1000_TXD_POPTS_TXSM: u32 = 0x02; /* Insert TCP/UDP checksum */
 
 /* Transmit Descriptor status definitions [E1000 3.3.3.2] */
 pub(crate) const E1000_TXD_STAT_DD: u32 = 0x00000001; /* Descriptor Done */
@@ -127,3 +219,20 @@ pub(crate) const E1000_TXD_STAT_DD: u32 = 0x00000001; /* Descriptor Done */
 /* Receive Descriptor bit definitions [E1000 3.2.3.1] */
 pub(crate) const E1000_RXD_STAT_DD: u32 = 0x01; /* Descriptor Done */
 pub(crate) const E1000_RXD_STAT_EOP: u32 = 0x02; /* End of Packet */
+pub(crate) const E1000_RXD_STAT_IXSM: u32 = 0x04; /* Ignore checksum */
+pub(crate) const E1000_RXD_STAT_VP: u32 = 0x08; /* IEEE VLAN Packet */
+pub(crate) const E1000_RXD_STAT_TCPCS: u32 = 0x20; /* TCP/UDP xsum calculated */
+pub(crate) const E1000_RXD_STAT_IPCS: u32 = 0x40; /* IP xsum calculated */
+pub(crate) const E1000_RXD_ERR_CE: u32 = 0x01; /* CRC Error */
+pub(crate) const E1000_RXD_ERR_SE: u32 = 0x02; /* Symbol Error */
+pub(crate) const E1000_RXD_ERR_SEQ: u32 = 0x04; /* Sequence Error */
+pub(crate) const E1000_RXD_ERR_CXE: u32 = 0x10; /* Carrier Extension Error */
+pub(crate) const E1000_RXD_ERR_TCPE: u32 = 0x20; /* TCP/UDP Checksum Error */
+pub(crate) const E1000_RXD_ERR_IPE: u32 = 0x40; /* IP Checksum Error */
+pub(crate) const E1000_RXD_ERR_RXE: u32 = 0x80; /* Rx Data Error */
+pub(crate) const E1000_RXD_ERR_FRAME_ERR_MASK: u32 =
+    E1000_RXD_ERR_CE | E1000_RXD_ERR_SE | E1000_RXD_ERR_SEQ | E1000_RXD_ERR_CXE | E1000_RXD_ERR_RXE;
+
+/* Receive Checksum Control [E1000 13.4.25] */
+pub(crate) const E1000_RXCSUM_IPOFL: u32 = 0x00000100; /* IPv4 checksum offload */
+pub(crate) const E1000_RXCSUM_TUOFL: u32 = 0x00000200; /* TCP / UDP checksum offload */
@@ -0,0 +1,37 @@
+// Errors of the e1000 driver core.
+
+use kernel::alloc::AllocError;
+use kernel::error::{Error, code};
+
+/// Why an `E1000Device` could not be created or brought back up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum E1000Error {
+    /// A DMA or heap allocation failed
+    NoMemory,
+    /// The device did not come out of reset in time
+    ResetTimeout,
+    /// A ring size outside the supported range or not a multiple of 8
+    InvalidRingSize(usize),
+    /// A buffer size RCTL.BSIZE cannot encode
+    InvalidBufferSize(usize),
+    /// An MTU outside the supported range
+    InvalidMtu(usize),
+}
+
+impl From<AllocError> for E1000Error {
+    fn from(_: AllocError) -> Self {
+        E1000Error::NoMemory
+    }
+}
+
+impl From<E1000Error> for Error {
+    fn from(e: E1000Error) -> Self {
+        match e {
+            E1000Error::NoMemory => code::ENOMEM,
+            E1000Error::ResetTimeout => code::ETIMEDOUT,
+            E1000Error::InvalidRingSize(_)
+            | E1000Error::InvalidBufferSize(_)
+            | E1000Error::InvalidMtu(_) => code::EINVAL,
+        }
+    }
+}
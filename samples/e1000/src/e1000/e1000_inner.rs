@@ -1,57 +1,537 @@
 // e1000 Driver for Intel 82540EP/EM
 // use super::super::Ext;
-use super::super::linux::volatile::Volatile;
+use super::e1000_coalesce::{AdaptiveItr, E1000Coalesce, ItrSample, delay_reg, itr_reg};
+use super::e1000_config::{E1000Config, E1000Eerd};
 use super::e1000_const::*;
+use super::e1000_error::E1000Error;
+use super::e1000_regs::{E1000Regs, Reg};
+use super::e1000_stats::E1000Stats;
 use crate::utils::*;
-use core::{cmp::min, mem::size_of, slice::from_raw_parts_mut};
+use core::cmp::min;
 
-type Vec<T> = kernel::prelude::KVec<T>;
 use kernel::prelude::*;
+use kernel::sync::Arc;
+use kernel::transmute::{AsBytes, FromBytes};
 
-const PAGE_SIZE: usize = 4096;
-const TX_RING_SIZE: usize = 256;
-const RX_RING_SIZE: usize = 256;
-const MBUF_SIZE: usize = 2048;
+/// Memory shared with the NIC, from [`KernelFunc::dma_alloc_coherent`]
+///
+/// Owns the allocation and frees it on drop.
+pub trait DmaBuffer<T> {
+    /// The address the NIC uses for the first element
+    fn dma_handle(&self) -> usize;
 
-const ALLOC_TX_RING_PAGES: usize = (TX_RING_SIZE * size_of::<TxDesc>()).div_ceil(PAGE_SIZE);
-const ALLOC_RX_RING_PAGES: usize = (RX_RING_SIZE * size_of::<RxDesc>()).div_ceil(PAGE_SIZE);
+    /// The elements, as the CPU sees them
+    fn as_slice(&self) -> &[T];
 
-const ALLOC_TX_BUFFER_PAGES: usize = (TX_RING_SIZE * MBUF_SIZE).div_ceil(PAGE_SIZE);
-const ALLOC_RX_BUFFER_PAGES: usize = (RX_RING_SIZE * MBUF_SIZE).div_ceil(PAGE_SIZE);
+    /// The elements, as the CPU sees them
+    fn as_mut_slice(&mut self) -> &mut [T];
+}
+
+/// A streaming DMA mapping from [`KernelFunc::dma_map`]
+///
+/// Unmaps the memory on drop.
+pub trait DmaMapping {
+    /// The address the NIC uses for the first byte
+    fn dma_handle(&self) -> usize;
+}
+
+/// A frame the NIC reads in place, e.g. an skb, see
+/// [`E1000TxRing::e1000_transmit_frame`]
+///
+/// Dropping it releases the frame as not sent.
+pub trait TxFrame {
+    /// The frame data: the linear part followed by the page fragments
+    fn fragments(&self) -> impl Iterator<Item = &[u8]> + Clone;
+
+    /// Release the frame once it has been sent
+    fn consume(self);
+}
+
+/// A buffer from an [`RxPool`] for the NIC to receive into, e.g. a page
+/// pool fragment
+///
+/// Mapped for the NIC to write; dropping it unmaps and frees it.
+pub trait RxBuffer {
+    /// The address the NIC writes the frame to
+    fn dma_handle(&self) -> usize;
+
+    /// The buffer, as the CPU sees it after [`Self::sync_for_cpu`]
+    fn as_slice(&self) -> &[u8];
+
+    /// Make the first `len` bytes the NIC wrote visible to the CPU
+    fn sync_for_cpu(&mut self, len: usize);
+
+    /// Hand the buffer back to the NIC, to be received into again
+    fn sync_for_device(&mut self);
+}
+
+/// Where an RX ring gets its buffers from, e.g. a page pool
+pub trait RxPool {
+    /// The buffers handed out
+    type Buffer: RxBuffer;
+
+    /// Allocate a buffer of the size the pool was created for. Called from
+    /// the receive path, so it must not sleep.
+    fn alloc(&mut self) -> Result<Self::Buffer>;
+}
 
 /// Kernel functions that drivers must use
-pub trait KernelFunc {
-    /// Page size (usually 4K)
-    const PAGE_SIZE: usize = PAGE_SIZE;
+///
+/// Each half of an [`E1000Device`] gets a clone.
+pub trait KernelFunc: Clone {
+    /// A coherent DMA allocation of `T`s
+    type DmaBuffer<T: AsBytes + FromBytes>: DmaBuffer<T>;
+
+    /// A streaming DMA mapping of transmit data
+    type DmaMapping: DmaMapping;
+
+    /// A frame to transmit without copying it
+    type TxFrame: TxFrame;
+
+    /// A buffer to receive a frame into
+    type RxBuffer: RxBuffer;
+
+    /// The buffers of one RX ring
+    type RxPool: RxPool<Buffer = Self::RxBuffer>;
 
     // 或请求分配irq
 
-    /// Allocate 'contiguous physical memory' for DMA;
-    /// Return (cpu virtual address, dma physical address) which is page aligned.
-    //fn dma_alloc_coherent(pages: usize) -> usize;
-    fn dma_alloc_coherent(&mut self, pages: usize) -> (usize, usize);
+    /// Allocate zeroed, page aligned coherent DMA memory for `count` `T`s
+    fn dma_alloc_coherent<T: AsBytes + FromBytes>(
+        &mut self,
+        count: usize,
+    ) -> Result<Self::DmaBuffer<T>>;
+
+    /// Map `data`, a fragment of a [`Self::TxFrame`], for the NIC to read
+    fn dma_map(&mut self, data: &[u8]) -> Result<Self::DmaMapping>;
+
+    /// Create a pool of `size` byte buffers for the NIC to receive into,
+    /// for a ring of `count` descriptors
+    fn rx_pool(&mut self, count: usize, size: usize) -> Result<Self::RxPool>;
+
+    /// Busy-wait for `us` microseconds
+    fn udelay(&self, us: usize);
+
+    /// Sleep for between `min_us` and `max_us` microseconds
+    fn usleep_range(&self, min_us: usize, max_us: usize);
+}
+
+/// Length of an ethernet hardware address
+pub const ETH_ALEN: usize = 6;
+
+/// Number of receive address registers; RA[0] holds the station address
+pub const E1000_RAR_ENTRIES: usize = 16;
+
+/// Number of 32-bit registers in the multicast table array
+const E1000_MTA_ENTRIES: usize = 128;
+
+/// Number of 32-bit registers in the VLAN filter table array
+const E1000_VFTA_ENTRIES: usize = 128;
+
+/// Most fragments a frame is handed over in: the linear part plus
+/// MAX_SKB_FRAGS page fragments
+pub const E1000_TX_MAX_FRAGS: usize = 18;
+
+/// Largest frame handed over for transmission (a 64K TSO frame)
+pub const E1000_TX_MAX_LEN: usize = 65536;
+
+/// Frames shorter than this are copied rather than mapped for transmit
+pub const E1000_TX_COPYBREAK: usize = 256;
+
+/// Default for [`E1000RxRing::set_rx_copybreak`]
+pub const E1000_RX_COPYBREAK: usize = 256;
+
+/// Most data a mapped fragment puts on one TX descriptor
+const E1000_TX_MAX_DATA_PER_TXD: usize = 4096;
+
+/// How long to wait for an EEPROM word, in microseconds
+const EEPROM_READ_TIMEOUT_US: usize = 10_000;
+
+/// How long to wait for a reset or EEPROM reload to finish, in microseconds
+const RESET_TIMEOUT_US: usize = 10_000;
+
+/// How long in-flight DMA is given to finish before a reset, in microseconds
+const RESET_QUIESCE_US: usize = 10_000;
+
+/// How long to wait for a PCIe part to stop mastering the bus, in microseconds
+const DMA_DRAIN_TIMEOUT_US: usize = 10_000;
+
+/// How often a reset is polled for completion, in microseconds
+const RESET_POLL_US: usize = 10;
+
+/// Link speed as resolved by the MAC/PHY
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkSpeed {
+    /// 10 Mb/s
+    Speed10,
+    /// 100 Mb/s
+    Speed100,
+    /// 1000 Mb/s
+    Speed1000,
+}
+
+impl LinkSpeed {
+    /// Speed in Mb/s
+    pub fn mbps(self) -> u32 {
+        match self {
+            LinkSpeed::Speed10 => 10,
+            LinkSpeed::Speed100 => 100,
+            LinkSpeed::Speed1000 => 1000,
+        }
+    }
+}
+
+/// Link duplex mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Duplex {
+    /// Half duplex
+    Half,
+    /// Full duplex
+    Full,
+}
+
+/// State of an established link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkState {
+    /// Negotiated speed
+    pub speed: LinkSpeed,
+    /// Negotiated duplex mode
+    pub duplex: Duplex,
+}
+
+/// Interrupt causes read from ICR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntrCause(u32);
+
+impl IntrCause {
+    /// No cause is set: the interrupt was not raised by this device.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The link went up or down.
+    pub fn link_changed(self) -> bool {
+        self.0 & E1000_ICR_LSC != 0
+    }
+
+    /// Frames were received.
+    pub fn rx(self) -> bool {
+        self.0 & E1000_ICR_RXT0 != 0
+    }
+
+    /// The raw ICR value
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+/// What the NIC found checking a received frame's checksums
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RxChecksum {
+    /// Not checked: not TCP/UDP over IPv4, or offload is off
+    None,
+    /// The IP and TCP/UDP checksums are correct
+    Verified,
+    /// A checksum is wrong; left to the stack to verify and drop
+    Bad,
+}
+
+/// A received frame, lent out of the RX buffers by
+/// [`E1000RxRing::e1000_recv`].
+///
+/// A frame longer than one buffer spans several consecutive descriptors.
+/// The buffers can be read in place, or taken along with the frame.
+pub struct RxFrame<'a, K: KernelFunc> {
+    ring: &'a [RxDesc],
+    bufs: &'a mut [Option<K::RxBuffer>],
+    first: usize,
+    count: usize,
+    copybreak: usize,
+}
+
+impl<K: KernelFunc> RxFrame<'_, K> {
+    /// The buffer of the `i`th segment and the frame length in it
+    fn buffer(&self, i: usize) -> Option<(&K::RxBuffer, usize)> {
+        let index = (self.first + i) % self.ring.len();
+        let buf = self.bufs[index].as_ref()?;
+        Some((
+            buf,
+            min(self.ring[index].length as usize, buf.as_slice().len()),
+        ))
+    }
+
+    /// The parts of the frame, one per buffer, in order
+    pub fn segments(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        (0..self.count)
+            .filter_map(|i| self.buffer(i))
+            .map(|(buf, len)| &buf.as_slice()[..len])
+    }
+
+    /// Whether the frame is short enough to be copied out rather than
+    /// taken, see [`E1000RxRing::set_rx_copybreak`]
+    pub fn is_copybreak(&self) -> bool {
+        self.count == 1 && self.len() <= self.copybreak
+    }
+
+    /// Take the buffers holding the frame, each with the length of the
+    /// frame in it. The ring gets new buffers in their place.
+    pub fn take_buffers(&mut self) -> impl Iterator<Item = (K::RxBuffer, usize)> + '_ {
+        let ring_len = self.ring.len();
+        (0..self.count).filter_map(move |i| {
+            let index = (self.first + i) % ring_len;
+            let buf = self.bufs[index].take()?;
+            let len = min(self.ring[index].length as usize, buf.as_slice().len());
+            Some((buf, len))
+        })
+    }
+
+    /// Length of the whole frame
+    pub fn len(&self) -> usize {
+        self.segments().map(|s| s.len()).sum()
+    }
+
+    /// Whether the frame has no data
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The 802.1Q tag control (priority and VLAN id) the NIC stripped
+    /// from the frame, if it was tagged
+    pub fn vlan_tag(&self) -> Option<u16> {
+        let last = &self.ring[(self.first + self.count - 1) % self.ring.len()];
+        (last.status as u32 & E1000_RXD_STAT_VP != 0).then_some(last.special)
+    }
+
+    /// The checksum status, which the NIC reports with the last descriptor
+    pub fn checksum(&self) -> RxChecksum {
+        let last = &self.ring[(self.first + self.count - 1) % self.ring.len()];
+        let (status, errors) = (last.status as u32, last.errors as u32);
+        if status & E1000_RXD_STAT_IXSM != 0 {
+            RxChecksum::None
+        } else if errors & (E1000_RXD_ERR_IPE | E1000_RXD_ERR_TCPE) != 0 {
+            RxChecksum::Bad
+        } else if status & E1000_RXD_STAT_TCPCS != 0 {
+            RxChecksum::Verified
+        } else {
+            RxChecksum::None
+        }
+    }
+
+    /// Whether the NIC flagged the frame as damaged on the wire
+    fn has_frame_error(&self) -> bool {
+        let last = &self.ring[(self.first + self.count - 1) % self.ring.len()];
+        last.errors as u32 & E1000_RXD_ERR_FRAME_ERR_MASK != 0
+    }
+}
+
+/// A TCP/UDP checksum for the NIC to fill in: the ones' complement sum
+/// of the frame from `start` to its end, stored at `offset`.
+///
+/// The checksum field must already hold the pseudo-header sum, as the
+/// Linux stack leaves it in a `CHECKSUM_PARTIAL` skb.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxChecksum {
+    start: u8,
+    offset: u8,
+}
+
+impl TxChecksum {
+    /// `None` if the frame is not eligible: a context descriptor only
+    /// holds offsets below 256, and the checksum must lie in the summed
+    /// range.
+    pub fn new(start: usize, offset: usize) -> Option<Self> {
+        if offset < start {
+            return None;
+        }
+        Some(TxChecksum {
+            start: u8::try_from(start).ok()?,
+            offset: u8::try_from(offset).ok()?,
+        })
+    }
+}
+
+/// Largest TCP payload of one TSO frame, PAYLEN is a 20-bit field
+const TSO_MAX_PAYLOAD: usize = (1 << 20) - 1;
+
+/// TCP segmentation of an IPv4 frame: the NIC sends the payload after
+/// `hdr_len` bytes of headers in segments of `mss` bytes, each behind a
+/// copy of the headers with the lengths, sequence number and checksums
+/// fixed up.
+///
+/// The IP total length and checksum must be zero, and the TCP checksum
+/// the pseudo-header sum without the length, as Linux drivers prepare a
+/// GSO skb.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxTso {
+    ip_start: u8,
+    tcp_start: u8,
+    hdr_len: u8,
+    mss: u16,
+}
+
+impl TxTso {
+    /// `None` if the headers do not fit a context descriptor (255 bytes)
+    /// or are out of order, or if `mss` is zero or above 64K.
+    pub fn new(ip_start: usize, tcp_start: usize, hdr_len: usize, mss: usize) -> Option<Self> {
+        // Minimal IPv4 and TCP headers
+        if tcp_start < ip_start + 20 || hdr_len < tcp_start + 20 || mss == 0 {
+            return None;
+        }
+        Some(TxTso {
+            ip_start: u8::try_from(ip_start).ok()?,
+            tcp_start: u8::try_from(tcp_start).ok()?,
+            hdr_len: u8::try_from(hdr_len).ok()?,
+            mss: u16::try_from(mss).ok()?,
+        })
+    }
+}
+
+/// Work the NIC does on a frame while transmitting it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TxOffload {
+    /// Insert the TCP/UDP checksum
+    pub csum: Option<TxChecksum>,
+    /// Segment a TCP frame; implies the IP and TCP checksums, `csum` is
+    /// not used
+    pub tso: Option<TxTso>,
+    /// Insert an 802.1Q tag with this tag control (priority and VLAN id);
+    /// needs [`E1000Hw::e1000_set_vlan_offload`]
+    pub vlan: Option<u16>,
+}
+
+/// What the receiver accepts beyond its address filters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RxMode {
+    /// Accept every frame
+    pub promisc: bool,
+    /// Accept every multicast frame
+    pub allmulti: bool,
+}
+
+/// The receive filters as last set, kept to program a reset device with
+#[derive(Clone, Copy)]
+struct RxFilter {
+    mode: RxMode,
+    /// The unicast list did not fit in RA[], so all unicasts are accepted
+    uc_overflow: bool,
+    /// Exact matches for RA[1..]
+    rar: [Option<[u8; ETH_ALEN]>; E1000_RAR_ENTRIES - 1],
+    mta: [u32; E1000_MTA_ENTRIES],
+    /// Drop frames tagged with a VLAN not in `vfta`
+    vlan_filter: bool,
+    vfta: [u32; E1000_VFTA_ENTRIES],
+}
 
-    /// Deallocate DMA memory by virtual address
-    fn dma_free_coherent(&mut self, vaddr: usize, pages: usize);
+impl RxFilter {
+    const EMPTY: Self = RxFilter {
+        mode: RxMode {
+            promisc: false,
+            allmulti: false,
+        },
+        uc_overflow: false,
+        rar: [None; E1000_RAR_ENTRIES - 1],
+        mta: [0; E1000_MTA_ENTRIES],
+        vlan_filter: false,
+        vfta: [0; E1000_VFTA_ENTRIES],
+    };
 }
 
 /// Main structure of the e1000 driver.
-/// Used to save members such as ring buffer.
-pub struct E1000Device<'a, K: KernelFunc> {
-    regs: &'static mut [Volatile<u32>],
-    rx_ring_dma: usize,
-    tx_ring_dma: usize,
-    rx_ring: &'a mut [RxDesc], //可以只为ring buffer加锁
-    tx_ring: &'a mut [TxDesc],
-    rx_mbufs: Vec<usize>,
-    tx_mbufs: Vec<usize>,
-    mbuf_size: usize,
+///
+/// Made of halves that can be locked independently, e.g. so that transmit
+/// and receive run on different CPUs: the TX and RX rings, each touching
+/// only its own registers through the shared register handle, and the
+/// rest of the hardware. Operations that need more than one half are
+/// available here, and on [`E1000Hw`] given the rings.
+pub struct E1000Device<K: KernelFunc> {
+    /// Registers, configuration, filters, link and interrupts
+    pub hw: E1000Hw<K>,
+    /// The transmit ring
+    pub tx: E1000TxRing<K>,
+    /// The receive ring
+    pub rx: E1000RxRing<K>,
+}
+
+/// The part of an [`E1000Device`] not tied to a ring
+pub struct E1000Hw<K: KernelFunc> {
+    regs: Arc<E1000Regs>,
+    config: E1000Config,
+    mac_addr: [u8; ETH_ALEN],
+    link: Option<LinkState>,
+    stats: E1000Stats,
+    rx_filter: RxFilter,
+    /// VLAN tags are inserted and stripped (CTRL.VME)
+    vlan_offload: bool,
+    coalesce: E1000Coalesce,
+    itr: AdaptiveItr,
     //phy_interface: PhyInterfaceMode,
     kfn: K,
 }
 
+/// Reset and quiesce of the hardware, see [`E1000Hw::reset_handle`]
+///
+/// These sleep, so unlike the halves of an [`E1000Device`] the handle is
+/// used without a lock, while nothing else drives the hardware.
+#[derive(Clone)]
+pub struct E1000Reset<K: KernelFunc> {
+    regs: Arc<E1000Regs>,
+    kfn: K,
+}
+
+/// The transmit half of an [`E1000Device`]
+pub struct E1000TxRing<K: KernelFunc> {
+    regs: Arc<E1000Regs>,
+    ring: K::DmaBuffer<TxDesc>,
+    /// One `mbuf_size` buffer per descriptor
+    mbufs: K::DmaBuffer<u8>,
+    /// What each descriptor keeps alive until the NIC is done with it
+    slots: KVec<TxSlot<K>>,
+    /// Next descriptor to reclaim, see e1000_clean_tx()
+    clean: usize,
+    mbuf_size: usize,
+    /// The checksum context last loaded into the NIC
+    context: Option<TxChecksum>,
+    /// Descriptors ask for the delayed interrupt (TIDV is not zero)
+    int_delay: bool,
+    /// Traffic since the last e1000_update_itr()
+    itr: ItrSample,
+    kfn: K,
+}
+
+/// The receive half of an [`E1000Device`]
+pub struct E1000RxRing<K: KernelFunc> {
+    regs: Arc<E1000Regs>,
+    ring: K::DmaBuffer<RxDesc>,
+    /// The buffer of each descriptor, `None` once it went up the stack
+    /// until e1000_rx_refill() replaces it
+    bufs: KVec<Option<K::RxBuffer>>,
+    /// Next descriptor to check for a received frame
+    clean: usize,
+    /// Frames up to this long are copied, see set_rx_copybreak()
+    copybreak: usize,
+    /// The start of a frame longer than the ring was dropped; so is the
+    /// rest, up to EOP
+    discarding: bool,
+    /// The counters of [`E1000Stats`] kept by the receive path
+    hw_csum_good: u64,
+    hw_csum_err: u64,
+    alloc_rx_buff_failed: u64,
+    /// Traffic since the last e1000_update_itr()
+    itr: ItrSample,
+    kfn: K,
+    /// Dropped after `bufs`, which came from it
+    pool: K::RxPool,
+}
+
 // struct spinlock e1000_lock;
 
+/// The mapping of a fragment and the frame that end at a TX descriptor,
+/// released as e1000_clean_tx() reclaims it
+struct TxSlot<K: KernelFunc> {
+    map: Option<K::DmaMapping>,
+    frame: Option<K::TxFrame>,
+}
+
 /// [E1000 3.3.3]
 /// The dma descriptor for transmitting
 #[derive(Debug, Clone)]
@@ -66,6 +546,60 @@ pub struct TxDesc {
     special: u16,
 }
 
+// SAFETY: Integers only, laid out without padding.
+unsafe impl AsBytes for TxDesc {}
+// SAFETY: Any bit pattern is a valid descriptor.
+unsafe impl FromBytes for TxDesc {}
+
+impl TxDesc {
+    /// [E1000 3.3.6]
+    /// A TCP/IP context descriptor, laid over the legacy fields: the
+    /// checksum offsets take the place of `addr`, DTYP (the high nibble of
+    /// `cso`) is zero and the command byte stays where it is.
+    fn context(csum: TxChecksum) -> Self {
+        TxDesc {
+            // IPCSS/IPCSO/IPCSE unused; TUCSE = 0 sums up to the end of the frame.
+            addr: ((csum.start as u64) << 32) | ((csum.offset as u64) << 40),
+            length: 0,
+            cso: E1000_TXD_DTYP_C as u8,
+            cmd: (E1000_TXD_CMD_DEXT | E1000_TXD_CMD_RS) as u8,
+            status: 0,
+            css: 0,
+            special: 0,
+        }
+    }
+
+    /// Whether this is a context descriptor, as opposed to a data one
+    fn is_context(&self) -> bool {
+        self.cmd & E1000_TXD_CMD_DEXT as u8 != 0 && self.cso & 0xf0 == E1000_TXD_DTYP_C as u8
+    }
+
+    /// [E1000 3.3.6]
+    /// A TCP/IP context descriptor setting up segmentation of a frame with
+    /// `paylen` bytes after the headers.
+    fn tso_context(tso: TxTso, paylen: usize) -> Self {
+        let (ipcss, tucss) = (tso.ip_start as u64, tso.tcp_start as u64);
+        TxDesc {
+            // IPCSE is the last byte of the IP header; TUCSE = 0 as above.
+            addr: ipcss
+                | ((ipcss + 10) << 8)
+                | ((tucss - 1) << 16)
+                | (tucss << 32)
+                | ((tucss + 16) << 40),
+            length: paylen as u16,
+            cso: (paylen >> 16) as u8 | E1000_TXD_DTYP_C as u8,
+            cmd: (E1000_TXD_CMD_DEXT
+                | E1000_TXD_CMD_TSE
+                | E1000_TXD_CMD_IP
+                | E1000_TXD_CMD_TCP
+                | E1000_TXD_CMD_RS) as u8,
+            status: 0,
+            css: tso.hdr_len,
+            special: tso.mss,
+        }
+    }
+}
+
 /// [E1000 3.2.3]
 /// The dma descriptor for receiving
 #[derive(Debug, Clone)]
@@ -79,366 +613,1292 @@ pub struct RxDesc {
     special: u16,
 }
 
-impl<K: KernelFunc> E1000Device<'_, K> {
-    /// New an e1000 device by Allocating memory
-    pub fn new(mut kfn: K, mapped_regs: usize) -> Result<Self, Error> {
-        pr_info!("New E1000 device @ {:#x}", mapped_regs);
-        // 分配的ring内存空间需要16字节对齐
-        let (tx_ring_vaddr, tx_ring_dma) = kfn.dma_alloc_coherent(ALLOC_TX_RING_PAGES);
-        let (rx_ring_vaddr, rx_ring_dma) = kfn.dma_alloc_coherent(ALLOC_RX_RING_PAGES);
+// SAFETY: Integers only, laid out without padding.
+unsafe impl AsBytes for RxDesc {}
+// SAFETY: Any bit pattern is a valid descriptor.
+unsafe impl FromBytes for RxDesc {}
 
-        let tx_ring = unsafe { from_raw_parts_mut(tx_ring_vaddr as *mut TxDesc, TX_RING_SIZE) };
-        let rx_ring = unsafe { from_raw_parts_mut(rx_ring_vaddr as *mut RxDesc, RX_RING_SIZE) };
+impl<K: KernelFunc> E1000Device<K> {
+    /// New an e1000 device by Allocating memory
+    ///
+    /// Nothing is left allocated if this fails.
+    pub fn new(mut kfn: K, regs: E1000Regs, config: E1000Config) -> Result<Self, E1000Error> {
+        pr_info!("New E1000 device, {:?}", config);
+        config.validate()?;
+
+        let regs = Arc::new(regs, GFP_KERNEL).map_err(|_| E1000Error::NoMemory)?;
+        // Each allocation is freed again if a later one fails.
+        let tx = E1000TxRing::new(kfn.clone(), regs.clone(), &config)?;
+        let rx = E1000RxRing::new(&mut kfn, regs.clone(), &config)?;
+        let hw = E1000Hw {
+            regs,
+            config,
+            mac_addr: [0; ETH_ALEN],
+            link: None,
+            stats: E1000Stats::default(),
+            rx_filter: RxFilter::EMPTY,
+            vlan_offload: false,
+            coalesce: E1000Coalesce::default(),
+            itr: AdaptiveItr::new(),
+            kfn,
+        };
 
-        tx_ring.fill(TxDesc {
-            addr: 0,
-            length: 0,
-            cso: 0,
-            cmd: 0,
-            status: 0,
-            css: 0,
-            special: 0,
-        });
-        rx_ring.fill(RxDesc {
-            addr: 0,
-            length: 0,
-            csum: 0,
-            status: 0,
-            errors: 0,
-            special: 0,
-        });
+        let mut e1000dev = E1000Device { hw, tx, rx };
+        // Dropping `e1000dev` frees the DMA memory if either fails.
+        e1000dev.tx.e1000_init_ring();
+        e1000dev.rx.e1000_init_ring()?;
+        e1000dev.hw.e1000_init(&e1000dev.tx, &e1000dev.rx)?;
 
-        let mut tx_mbufs = Vec::with_capacity(tx_ring.len(), GFP_KERNEL)?;
-        let mut rx_mbufs = Vec::with_capacity(rx_ring.len(), GFP_KERNEL)?;
+        Ok(e1000dev)
+    }
 
-        // 一起申请所有TX内存
-        let (mut tx_mbufs_vaddr, mut tx_mbufs_dma) = kfn.dma_alloc_coherent(ALLOC_TX_BUFFER_PAGES);
+    /// Carry the station address, filters, interrupt moderation, RX
+    /// copybreak and statistics over from `hw` and `rx`, the halves of the
+    /// device that drove the same hardware until this one was created (e.g.
+    /// with a different [`E1000Config`]). Call `hw.e1000_update_stats()`
+    /// before creating the new device: the reset clears the hardware
+    /// counters.
+    pub fn take_over(&mut self, hw: &E1000Hw<K>, rx: &E1000RxRing<K>) {
+        self.hw.take_over(hw);
+        self.tx.int_delay = self.hw.coalesce.tx_delay_us != 0;
+        self.rx.take_over(rx);
+    }
 
-        for item in tx_ring.iter_mut().take(TX_RING_SIZE) {
-            item.status = E1000_TXD_STAT_DD as u8;
-            item.addr = tx_mbufs_dma as u64;
-            tx_mbufs.push(tx_mbufs_vaddr, GFP_KERNEL);
-            tx_mbufs_dma += MBUF_SIZE;
-            tx_mbufs_vaddr += MBUF_SIZE;
-        }
+    /// Reset the device and rebuild both rings, see [`E1000Hw::reinit`]
+    pub fn reinit(&mut self) -> Result<(), E1000Error> {
+        // The reset clears the counters.
+        self.hw.e1000_update_stats();
+        self.hw.reset_handle().e1000_reset()?;
+        self.hw.reinit(&mut self.tx, &mut self.rx)
+    }
 
-        // 一起申请所有RX内存
-        //let mut rx_mbufs_dma: usize = K::dma_alloc_coherent(alloc_rx_buffer_pages);
-        let (mut rx_mbufs_vaddr, mut rx_mbufs_dma) = kfn.dma_alloc_coherent(ALLOC_RX_BUFFER_PAGES);
-        if rx_mbufs_vaddr == 0 {
-            panic!("e1000, alloc dma rx buffer failed");
-        }
+    /// See [`E1000Reset::e1000_shutdown`]
+    pub fn e1000_shutdown(&self) {
+        self.hw.reset_handle().e1000_shutdown();
+    }
 
-        for item in rx_ring.iter_mut().take(RX_RING_SIZE) {
-            item.addr = rx_mbufs_dma as u64;
-            rx_mbufs.push(rx_mbufs_vaddr, GFP_KERNEL);
-            rx_mbufs_dma += MBUF_SIZE;
-            rx_mbufs_vaddr += MBUF_SIZE;
-        }
-        fence_w();
+    /// See [`E1000Hw::stats`]
+    pub fn stats(&mut self) -> E1000Stats {
+        self.hw.stats(&self.rx)
+    }
 
-        // Slice切片，内存連續的動態大小的序列；
-        // array, 数组
-        // Vec, 内存連續的可增長數組類型
-
-        // 寄存器读写。写自己存器时，先写一遍，再读一遍，确保值写成功。
-        // ptr::write_volatile
-        // ptr::read_volatile
-
-        // 处理网络包的部分头字段
-        // impl<T: AsRef<[u8]> + AsMut<[u8]>> Packet<T>
-        // 或？ vcell::VolatileCell
-
-        /* volatile = "0.4.5"
-        let regs = unsafe{ from_raw_parts_mut(mapped_regs as *mut u32, len) };
-        let regs = Volatile::new(regs);
-        regs.index_mut(E1000_IMS).write(0);
-
-        #[repr(transparent)]
-        只能用于只有单个非零大小字段（可能还有其他零大小字段，如PhantomData<T>）的
-        struct或enum 中。使得整个结构的内存布局和ABI被保证与该非零字段相同。
-        */
-        // 0x00000 ~ 0x1FFFF, I/O-Mapped Internal Registers and Memories
-        let len = 0x1FFFF / size_of::<u32>();
-        // 处理网卡寄存器配置: 由一个指针和一个长度len形成一个slice切片。len是元素的个数，而非字节数。
-        let regs = unsafe { from_raw_parts_mut(mapped_regs as *mut Volatile<u32>, len) };
-
-        let mut e1000dev = E1000Device {
-            regs,
-            rx_ring_dma,
-            tx_ring_dma,
-            rx_ring,
-            tx_ring,
-            rx_mbufs,
-            tx_mbufs,
-            mbuf_size: MBUF_SIZE,
-            kfn,
-        };
-        e1000dev.e1000_init();
+    /// See [`E1000Hw::set_coalesce`]
+    pub fn set_coalesce(&mut self, coalesce: E1000Coalesce) -> Result {
+        self.hw.set_coalesce(&mut self.tx, coalesce)
+    }
 
-        Ok(e1000dev)
+    /// See [`E1000Hw::e1000_update_itr`]
+    pub fn e1000_update_itr(&mut self) {
+        self.hw.e1000_update_itr(&mut self.tx, &mut self.rx)
     }
+}
+
+/// Log a failed DMA allocation
+fn dma_no_memory(_: Error) -> E1000Error {
+    pr_err!("e1000, DMA allocation failed");
+    E1000Error::NoMemory
+}
 
-    /// Initialize e1000 driver  
-    /// mapped_regs is the memory address at which the e1000's registers are mapped.
-    fn e1000_init(&mut self) {
-        let stat = self.regs[E1000_STAT].read();
-        let ctl = self.regs[E1000_CTL].read();
+/// Make sure posted register writes have reached the NIC
+fn write_flush(regs: &E1000Regs) {
+    regs.read(E1000_STAT);
+}
+
+/// RAL and RAH of a receive address register holding `mac`, marked valid
+fn rar_value(mac: &[u8; ETH_ALEN]) -> [u32; 2] {
+    let ral = u32::from_le_bytes([mac[0], mac[1], mac[2], mac[3]]);
+    let rah = u32::from_le_bytes([mac[4], mac[5], 0, 0]) | E1000_RAH_AV;
+    [ral, rah]
+}
+
+impl<K: KernelFunc> E1000Hw<K> {
+    /// Initialize e1000 driver
+    fn e1000_init(&mut self, tx: &E1000TxRing<K>, rx: &E1000RxRing<K>) -> Result<(), E1000Error> {
+        let stat = self.regs.read(E1000_STAT);
+        let ctl = self.regs.read(E1000_CTL);
         pr_info!("e1000 CTL: {:#x}, Status: {:#x}", ctl, stat);
 
-        // Reset the device
-        self.regs[E1000_IMS].write(0); // disable interrupts
-        self.regs[E1000_CTL].write(ctl | E1000_CTL_RST);
-        self.regs[E1000_IMS].write(0); // redisable interrupts
+        self.reset_handle().e1000_reset()?;
 
-        // 内存壁垒 fence
-        //__sync_synchronize();
-        fence_w();
+        // The address the EEPROM reload put in RA[0]; RA[0] is
+        // reprogrammed with it below.
+        self.mac_addr = self.e1000_read_mac_addr();
+        pr_info!("e1000 MAC address: {:02x?}", self.mac_addr);
+
+        self.e1000_configure(tx, rx);
+        pr_info!("e1000_init has been completed");
+        Ok(())
+    }
+
+    /// A handle to reset and quiesce the hardware without `self`
+    pub fn reset_handle(&self) -> E1000Reset<K> {
+        E1000Reset {
+            regs: self.regs.clone(),
+            kfn: self.kfn.clone(),
+        }
+    }
+
+    /// Program a freshly reset device: MAC, rings, RA[0] and coalescing.
+    /// Filters and VLAN offload are left cleared.
+    fn e1000_configure(&mut self, tx: &E1000TxRing<K>, rx: &E1000RxRing<K>) {
+        let ctl = self.regs.read(E1000_CTL);
+
+        // Let the MAC follow the speed and duplex resolved by the PHY.
+        self.regs.write(
+            E1000_CTL,
+            (ctl | E1000_CTL_SLU | E1000_CTL_ASDE)
+                & !(E1000_CTL_RST | E1000_CTL_FRCSPD | E1000_CTL_FRCDPLX | E1000_CTL_VME),
+        );
+        self.regs.write(E1000_VET, ETH_P_8021Q);
 
         // [E1000 14.5] Transmit initialization
-        if !(core::mem::size_of_val(self.tx_ring)).is_multiple_of(128) {
+        let tx_ring_dma = tx.ring.dma_handle();
+        let tx_ring_len = core::mem::size_of_val(tx.ring.as_slice());
+        if !tx_ring_len.is_multiple_of(128) {
             //panic("e1000");
             pr_err!("e1000, size of tx_ring is invalid");
         }
 
         // transmitter control bits.
-        self.regs[E1000_TCTL].write(
+        self.regs.write(
+            E1000_TCTL,
             E1000_TCTL_EN |  // enable
             E1000_TCTL_PSP |  // pad short packets
             (0x10 << E1000_TCTL_CT_SHIFT) |  // collision stuff
             (0x40 << E1000_TCTL_COLD_SHIFT),
         );
-        self.regs[E1000_TIPG].write(10 | (8 << 10) | (6 << 20)); // inter-pkt gap
+        self.regs.write(E1000_TIPG, 10 | (8 << 10) | (6 << 20)); // inter-pkt gap
 
-        self.regs[E1000_TDBAL].write(self.tx_ring_dma as u32);
-        self.regs[E1000_TDBAH].write((self.tx_ring_dma >> 32) as u32);
-        self.regs[E1000_TDLEN].write((core::mem::size_of_val(self.tx_ring)) as u32);
+        self.regs.write(E1000_TDBAL, tx_ring_dma as u32);
+        self.regs.write(E1000_TDBAH, (tx_ring_dma >> 32) as u32);
+        self.regs.write(E1000_TDLEN, tx_ring_len as u32);
 
-        self.regs[E1000_TDT].write(0); // TX Desc Tail
-        self.regs[E1000_TDH].write(0); // TX Desc Head
+        self.regs.write(E1000_TDT, 0); // TX Desc Tail
+        self.regs.write(E1000_TDH, 0); // TX Desc Head
 
         // [E1000 14.4] Receive initialization
-        pr_info!("rx ring 0: {:x?}", self.rx_ring[0]);
-        if !(core::mem::size_of_val(self.rx_ring)).is_multiple_of(128) {
+        pr_debug!("rx ring 0: {:x?}", rx.ring.as_slice()[0]);
+        let rx_ring_dma = rx.ring.dma_handle();
+        let rx_ring = rx.ring.as_slice();
+        let rx_ring_len = core::mem::size_of_val(rx_ring);
+        let rx_ring_count = rx_ring.len();
+        if !rx_ring_len.is_multiple_of(128) {
             pr_err!("e1000, size of rx_ring is invalid");
         }
 
         // receiver control bits.
-        self.regs[E1000_RCTL].write(
+        self.regs.write(
+            E1000_RCTL,
             (
                 E1000_RCTL_EN |  // enable receiver
             E1000_RCTL_BAM |  // enable broadcast
-            E1000_RCTL_SZ_2048 |  // 2048-byte rx buffers
+            self.config.rctl_bsize().unwrap_or(E1000_RCTL_SZ_2048) |  // rx buffer size
+            self.config.rctl_lpe() |  // long packets for jumbo MTUs
             E1000_RCTL_SECRC
                 // strip CRC
             ) & !(0b11 << 10), // Just for e1000e DTYP bits[11:10]=00 : Legacy description type
         );
-        self.regs[E1000_RFCTL].write(0); //e1000e RFCTL.EXSTEN bits[15]=0 : Legacy Desc
-        pr_info!(
+        self.regs.write(E1000_RFCTL, 0); //e1000e RFCTL.EXSTEN bits[15]=0 : Legacy Desc
+        // Have IPv4 and TCP/UDP checksums verified, see RxFrame::checksum().
+        self.regs
+            .write(E1000_RXCSUM, E1000_RXCSUM_IPOFL | E1000_RXCSUM_TUOFL);
+        pr_debug!(
             "e1000 RCTL: {:#x}, RFCTL: {:#x}",
-            self.regs[E1000_RCTL].read(),
-            self.regs[E1000_RFCTL].read()
+            self.regs.read(E1000_RCTL),
+            self.regs.read(E1000_RFCTL)
         );
 
-        self.regs[E1000_RDBAL].write(self.rx_ring_dma as u32);
-        self.regs[E1000_RDBAH].write((self.rx_ring_dma >> 32) as u32);
-        self.regs[E1000_RDLEN].write(core::mem::size_of_val(self.rx_ring) as u32);
+        self.regs.write(E1000_RDBAL, rx_ring_dma as u32);
+        self.regs.write(E1000_RDBAH, (rx_ring_dma >> 32) as u32);
+        self.regs.write(E1000_RDLEN, rx_ring_len as u32);
 
-        self.regs[E1000_RDH].write(0);
-        self.regs[E1000_RDT].write((RX_RING_SIZE - 1) as u32);
+        self.regs.write(E1000_RDH, 0);
+        self.regs.write(E1000_RDT, (rx_ring_count - 1) as u32);
 
-        // filter by qemu's MAC address, 52:54:00:12:34:56
-        //self.regs[E1000_RA].write(0x6c005452);
-        //self.regs[E1000_RA + 1].write(0x88f8 | (1 << 31)); //52:54:00:6c:f8:88
+        // filter by our own MAC address
+        self.e1000_write_station_addr();
 
         // multicast table
-        for i in 0..(4096 / 32) {
-            self.regs[E1000_MTA + i].write(0);
-        }
+        self.regs.write_all(E1000_MTA, &[0; E1000_MTA_ENTRIES]);
+        self.regs.write_all(E1000_VFTA, &[0; E1000_VFTA_ENTRIES]);
 
-        self.regs[E1000_TIDV].write(0);
-        self.regs[E1000_TADV].write(0);
-        // ask e1000 for receive interrupts.
-        self.regs[E1000_RDTR].write(0); // interrupt after every received packet (no timer)
-        self.regs[E1000_RADV].write(0); // interrupt after every packet (no timer)
+        // ask e1000 for receive interrupts, moderated by the delay timers and ITR.
+        self.e1000_write_coalesce();
 
-        self.regs[E1000_ITR].write(0); //Interrupt Throttle interval has expired, and an interrupt will be generated
+        //self.regs.write(E1000_ICS, 1 << 7); //手动测试触发对应中断
 
-        //self.regs[E1000_ICS].write(1 << 7); //手动测试触发对应中断
+        // Interrupts stay masked until the driver is ready to handle them,
+        // see e1000_irq_enable().
+        self.regs.write(E1000_IMC, !0);
 
-        self.regs[E1000_IMS].write(1 << 7); // RXT0 - Receiver Timer Interrupt , RXDW -- Receiver Descriptor Write Back
-
-        self.regs[E1000_ICR].read(); // clear ints
+        self.regs.read(E1000_ICR); // clear ints
         self.e1000_write_flush();
-        pr_info!("e1000_init has been completed");
+        self.e1000_check_link();
     }
 
-    /// Transmitting network packets
-    pub fn e1000_transmit(&mut self, packet: &[u8]) -> i32 {
-        let tindex = self.regs[E1000_TDT].read() as usize;
-        pr_info!("Read E1000_TDT = {:#x}", tindex);
-        //info!("TX Desc = {:#x?}", self.tx_ring[tindex]);
-        if (self.tx_ring[tindex].status & E1000_TXD_STAT_DD as u8) == 0 {
-            pr_err!("E1000 hasn't finished the corresponding previous transmission request");
-            return -1;
+    /// Rebuild both rings after [`E1000Reset::e1000_reset`], for recovering
+    /// from a hung transmitter. Frames in the rings are dropped; the station
+    /// address, filters, VLAN offload and coalescing settings are restored.
+    /// Call `e1000_update_stats()` before the reset, which clears the
+    /// counters.
+    ///
+    /// Interrupts are left masked, see e1000_irq_enable().
+    pub fn reinit(
+        &mut self,
+        tx: &mut E1000TxRing<K>,
+        rx: &mut E1000RxRing<K>,
+    ) -> Result<(), E1000Error> {
+        tx.e1000_init_ring();
+        rx.e1000_init_ring()?;
+        self.e1000_configure(tx, rx);
+        self.e1000_write_rx_filter();
+        self.e1000_set_vlan_offload(self.vlan_offload);
+        Ok(())
+    }
+
+    /// Read one 16-bit word from the EEPROM through EERD
+    fn e1000_read_eeprom(&mut self, word: u16) -> Option<u16> {
+        let (addr_shift, done) = match self.config.eerd {
+            E1000Eerd::I8254x => (E1000_EERD_ADDR_SHIFT, E1000_EERD_DONE),
+            E1000Eerd::I8257x => (E1000_NVM_RW_ADDR_SHIFT, E1000_NVM_RW_REG_DONE),
+            E1000Eerd::None => return None,
+        };
+        self.regs
+            .write(E1000_EERD, ((word as u32) << addr_shift) | E1000_EERD_START);
+
+        for _ in 0..EEPROM_READ_TIMEOUT_US {
+            let eerd = self.regs.read(E1000_EERD);
+            if eerd & done != 0 {
+                return Some((eerd >> E1000_EERD_DATA_SHIFT) as u16);
+            }
+            self.kfn.udelay(1);
         }
+        pr_err!("e1000, EEPROM read of word {} timed out", word);
+        None
+    }
 
-        let mut length = packet.len();
-        if length > self.mbuf_size {
-            pr_err!("The packet: {} to be send is TOO LARGE", length);
-            length = min(length, self.mbuf_size);
+    /// Get the station address: RA[0] if the hardware loaded it, else the
+    /// first three EEPROM words. All zeroes if neither is available.
+    fn e1000_read_mac_addr(&mut self) -> [u8; ETH_ALEN] {
+        let ral = self.regs.read(E1000_RA.at::<0>());
+        let rah = self.regs.read(E1000_RA.at::<1>());
+        if rah & E1000_RAH_AV != 0 {
+            let [a0, a1, a2, a3] = ral.to_le_bytes();
+            let [a4, a5, _, _] = rah.to_le_bytes();
+            return [a0, a1, a2, a3, a4, a5];
         }
 
-        let mbuf = unsafe { from_raw_parts_mut(self.tx_mbufs[tindex] as *mut u8, length) };
-        mbuf.copy_from_slice(packet);
+        let mut mac = [0u8; ETH_ALEN];
+        for (i, bytes) in mac.chunks_exact_mut(2).enumerate() {
+            match self.e1000_read_eeprom(E1000_EEPROM_MAC_ADDR + i as u16) {
+                Some(word) => bytes.copy_from_slice(&word.to_le_bytes()),
+                None => return [0; ETH_ALEN],
+            }
+        }
+        mac
+    }
 
-        pr_info!(">>>>>>>>> TX PKT {}", length);
-        pr_info!("\n\r");
-        //print_hex_dump(tx_mbuf, 64);
+    /// Program RA[0] with the station address and mark it valid
+    fn e1000_write_station_addr(&mut self) {
+        let [ral, rah] = rar_value(&self.mac_addr);
+        self.regs.write(E1000_RA.at::<0>(), ral);
+        self.regs.write(E1000_RA.at::<1>(), rah);
+        self.e1000_write_flush();
+    }
 
-        self.tx_ring[tindex].length = length as u16;
-        self.tx_ring[tindex].status = 0;
-        self.tx_ring[tindex].cmd = (E1000_TXD_CMD_RS | E1000_TXD_CMD_EOP) as u8;
+    /// The current station address
+    pub fn mac_address(&self) -> [u8; ETH_ALEN] {
+        self.mac_addr
+    }
 
-        self.regs[E1000_TDT].write(((tindex + 1) % TX_RING_SIZE) as u32);
+    /// The ring and buffer sizes the device was created with
+    pub fn config(&self) -> E1000Config {
+        self.config
+    }
 
-        self.e1000_write_flush();
-        // sync
-        fence_w();
+    /// See [`E1000Device::take_over`]
+    fn take_over(&mut self, old: &Self) {
+        self.stats = old.stats;
+        self.set_mac_address(&old.mac_addr);
+        self.rx_filter = old.rx_filter;
+        self.e1000_write_rx_filter();
+        self.e1000_set_vlan_offload(old.vlan_offload);
+        self.coalesce = old.coalesce;
+        self.itr = old.itr;
+        self.e1000_write_coalesce();
+    }
 
-        length as i32
+    /// Change the station address and program it into RA[0]
+    pub fn set_mac_address(&mut self, mac: &[u8; ETH_ALEN]) {
+        self.mac_addr = *mac;
+        self.e1000_write_station_addr();
     }
 
-    // Todo: send and recv lock
-    /// Receiving network packets
-    pub fn e1000_recv(&mut self) -> Option<Vec<Vec<u8>>> {
-        // Check for packets that have arrived from the e1000
-        // Create and deliver an mbuf for each packet (using net_rx()).
-        //let mut recv_packets = VecDeque::new();
-        let mut recv_packets = Vec::new();
-        let mut rindex = (self.regs[E1000_RDT].read() as usize + 1) % RX_RING_SIZE;
+    /// Set the receive filters.
+    ///
+    /// The `unicast` and then the `multicast` addresses take RA[1..] as
+    /// exact matches. Multicast addresses beyond that are hashed into the
+    /// multicast table; unicast ones that do not all fit turn on unicast
+    /// promiscuous mode instead.
+    pub fn e1000_set_rx_mode(
+        &mut self,
+        mode: RxMode,
+        unicast: &[[u8; ETH_ALEN]],
+        multicast: &[[u8; ETH_ALEN]],
+    ) {
+        // The VLAN filters are set separately.
+        let mut filter = RxFilter {
+            mode,
+            vlan_filter: self.rx_filter.vlan_filter,
+            vfta: self.rx_filter.vfta,
+            ..RxFilter::EMPTY
+        };
+        filter.uc_overflow = unicast.len() > filter.rar.len();
+        let unicast = if filter.uc_overflow { &[] } else { unicast };
 
-        //pr_info!("RX Desc {} = {:#x?}", rindex, self.rx_ring[rindex]);
-        if self.rx_ring[rindex].addr == 0 {
-            pr_err!("E1000 RX Desc.addr is invalid");
-            return None;
+        let mut exact = unicast.iter().chain(multicast);
+        for entry in filter.rar.iter_mut() {
+            *entry = exact.next().copied();
+        }
+        for addr in exact {
+            let hash = self.e1000_mta_hash(addr);
+            filter.mta[(hash >> 5) as usize] |= 1 << (hash & 0x1f);
         }
 
-        // DD设为1时，内存中的接收包是完整的
-        while (self.rx_ring[rindex].status & E1000_RXD_STAT_DD as u8) != 0 {
-            pr_info!("Read E1000_RDT + 1 = {:#x}", rindex);
-            let mut len = self.rx_ring[rindex].length as usize;
-            if len > self.mbuf_size {
-                pr_err!("The packet: {} received is TOO LARGE", len);
-                len = self.mbuf_size;
-            }
+        self.rx_filter = filter;
+        self.e1000_write_rx_filter();
+    }
 
-            let mbuf = unsafe { from_raw_parts_mut(self.rx_mbufs[rindex] as *mut u8, len) };
-            pr_info!("RX PKT {} <<<<<<<<<", len);
-            //recv_packets.push_back(mbuf.to_vec());
-            let mut vec_mbuf = Vec::new();
-            vec_mbuf.extend_from_slice(mbuf, GFP_KERNEL).unwrap();
-            recv_packets.push(vec_mbuf, GFP_KERNEL).unwrap();
+    /// The 12 address bits RCTL.MO selects to index the multicast table
+    fn e1000_mta_hash(&self, addr: &[u8; ETH_ALEN]) -> u32 {
+        let (a4, a5) = (addr[4] as u32, addr[5] as u32);
+        let hash = match self.regs.read(E1000_RCTL) & E1000_RCTL_MO_3 {
+            E1000_RCTL_MO_0 => (a4 >> 4) | (a5 << 4),
+            E1000_RCTL_MO_1 => (a4 >> 3) | (a5 << 5),
+            E1000_RCTL_MO_2 => (a4 >> 2) | (a5 << 6),
+            _ => a4 | (a5 << 8),
+        };
+        hash & 0xfff
+    }
 
-            // Deliver the mbuf to the network stack
-            net_rx(mbuf);
+    /// Insert VLAN tags on transmit as asked for in [`TxOffload::vlan`],
+    /// and strip them on receive, see [`RxFrame::vlan_tag`]. The NIC
+    /// cannot do one without the other.
+    pub fn e1000_set_vlan_offload(&mut self, on: bool) {
+        self.vlan_offload = on;
+        let ctl = self.regs.read(E1000_CTL) & !E1000_CTL_VME;
+        self.regs
+            .write(E1000_CTL, if on { ctl | E1000_CTL_VME } else { ctl });
+        self.e1000_write_flush();
+    }
 
-            fence();
-            // Just need to clear 64 bits header
-            mbuf[..min(64, len)].fill(0);
+    /// Drop frames tagged with a VLAN not added with
+    /// [`Self::e1000_vlan_rx_add_vid`], except in promiscuous mode.
+    pub fn e1000_set_vlan_filtering(&mut self, on: bool) {
+        self.rx_filter.vlan_filter = on;
+        self.e1000_write_rx_filter();
+    }
 
-            self.rx_ring[rindex].status = 0;
-            self.regs[E1000_RDT].write(rindex as u32);
+    /// Accept frames tagged with `vid` while VLAN filtering
+    pub fn e1000_vlan_rx_add_vid(&mut self, vid: u16) {
+        self.e1000_write_vfta(vid, true);
+    }
 
-            self.e1000_write_flush();
-            // sync
-            fence_w();
+    /// Stop accepting frames tagged with `vid` while VLAN filtering
+    pub fn e1000_vlan_rx_kill_vid(&mut self, vid: u16) {
+        self.e1000_write_vfta(vid, false);
+    }
 
-            rindex = (rindex + 1) % RX_RING_SIZE;
+    /// The interrupt moderation in effect
+    pub fn coalesce(&self) -> E1000Coalesce {
+        self.coalesce
+    }
+
+    /// Change the interrupt moderation; takes effect with the next interrupt
+    /// and, for `tx`, the next frame
+    pub fn set_coalesce(&mut self, tx: &mut E1000TxRing<K>, coalesce: E1000Coalesce) -> Result {
+        coalesce.validate()?;
+        self.coalesce = coalesce;
+        self.itr = AdaptiveItr::new();
+        tx.int_delay = coalesce.tx_delay_us != 0;
+        self.e1000_write_coalesce();
+        Ok(())
+    }
+
+    /// In adaptive mode, pick the ITR rate for the traffic the rings saw
+    /// since the last call. Meant to be called once per interrupt.
+    pub fn e1000_update_itr(&mut self, tx: &mut E1000TxRing<K>, rx: &mut E1000RxRing<K>) {
+        let (rx, tx) = (core::mem::take(&mut rx.itr), core::mem::take(&mut tx.itr));
+        if !self.coalesce.adaptive {
+            return;
         }
-        pr_info!("e1000_recv\n\r");
+        if let Some(rate) = self.itr.update(rx, tx) {
+            self.regs.write(E1000_ITR, itr_reg(rate));
+        }
+    }
 
-        if recv_packets.is_empty() {
-            Some(recv_packets)
-        } else {
-            None
+    fn e1000_write_coalesce(&mut self) {
+        let c = self.coalesce;
+        let itr = if c.adaptive { self.itr.rate } else { c.itr };
+        self.regs.write(E1000_RDTR, delay_reg(c.rx_delay_us));
+        self.regs.write(E1000_RADV, delay_reg(c.rx_abs_delay_us));
+        self.regs.write(E1000_TIDV, delay_reg(c.tx_delay_us));
+        self.regs.write(E1000_TADV, delay_reg(c.tx_abs_delay_us));
+        self.regs.write(E1000_ITR, itr_reg(itr));
+    }
+
+    fn e1000_write_vfta(&mut self, vid: u16, on: bool) {
+        let (index, bit) = ((vid as usize >> 5) & 0x7f, 1 << (vid & 0x1f));
+        let vfta = &mut self.rx_filter.vfta[index];
+        *vfta = if on { *vfta | bit } else { *vfta & !bit };
+        if let Err(e) = self.regs.write_at(E1000_VFTA, index, *vfta) {
+            pr_err!("e1000, VFTA[{}] write failed: {:?}", index, e);
+        }
+        self.e1000_write_flush();
+    }
+
+    /// Program RA[1..], the MTA, the VFTA and the filter bits of RCTL from
+    /// `rx_filter`
+    fn e1000_write_rx_filter(&mut self) {
+        let filter = self.rx_filter;
+        // RA[0] keeps the station address.
+        let mut ra = [0; 2 * E1000_RAR_ENTRIES];
+        let entries = core::iter::once(Some(self.mac_addr)).chain(filter.rar);
+        for (regs, entry) in ra.chunks_exact_mut(2).zip(entries) {
+            if let Some(addr) = entry {
+                regs.copy_from_slice(&rar_value(&addr));
+            }
+        }
+        self.regs.write_all(E1000_RA, &ra);
+        self.regs.write_all(E1000_MTA, &filter.mta);
+        self.regs.write_all(E1000_VFTA, &filter.vfta);
+
+        let mut rctl =
+            self.regs.read(E1000_RCTL) & !(E1000_RCTL_UPE | E1000_RCTL_MPE | E1000_RCTL_VFE);
+        if filter.mode.promisc || filter.uc_overflow {
+            rctl |= E1000_RCTL_UPE;
         }
+        if filter.mode.promisc || filter.mode.allmulti {
+            rctl |= E1000_RCTL_MPE;
+        }
+        if filter.vlan_filter && !filter.mode.promisc {
+            rctl |= E1000_RCTL_VFE;
+        }
+        self.regs.write(E1000_RCTL, rctl);
+        self.e1000_write_flush();
     }
 
-    // 参考
-    // xv6_for_internet_os
-    // https://xiayingp.gitbook.io/build_a_os/labs/lab-10-networking-part-1
-    // https://blog.mky.moe/mit6828/10-lab10/
+    /// Add the clear-on-read statistics registers to the accumulated counters.
+    pub fn e1000_update_stats(&mut self) {
+        let regs = &self.regs;
+        let r = |reg: Reg| regs.read(reg) as u64;
+        // The low half must be read first, reading the high half clears both.
+        let r64 = |lo: Reg, hi: Reg| r(lo) | (r(hi) << 32);
+        let s = &mut self.stats;
+
+        s.crcerrs += r(E1000_CRCERRS);
+        s.algnerrc += r(E1000_ALGNERRC);
+        s.symerrs += r(E1000_SYMERRS);
+        s.rxerrc += r(E1000_RXERRC);
+        s.mpc += r(E1000_MPC);
+        s.scc += r(E1000_SCC);
+        s.ecol += r(E1000_ECOL);
+        s.mcc += r(E1000_MCC);
+        s.latecol += r(E1000_LATECOL);
+        s.colc += r(E1000_COLC);
+        s.dc += r(E1000_DC);
+        s.tncrs += r(E1000_TNCRS);
+        s.sec += r(E1000_SEC);
+        s.cexterr += r(E1000_CEXTERR);
+        s.rlec += r(E1000_RLEC);
+        s.xonrxc += r(E1000_XONRXC);
+        s.xontxc += r(E1000_XONTXC);
+        s.xoffrxc += r(E1000_XOFFRXC);
+        s.xofftxc += r(E1000_XOFFTXC);
+        s.fcruc += r(E1000_FCRUC);
+        s.gprc += r(E1000_GPRC);
+        s.bprc += r(E1000_BPRC);
+        s.mprc += r(E1000_MPRC);
+        s.gptc += r(E1000_GPTC);
+        s.gorc += r64(E1000_GORCL, E1000_GORCH);
+        s.gotc += r64(E1000_GOTCL, E1000_GOTCH);
+        s.rnbc += r(E1000_RNBC);
+        s.ruc += r(E1000_RUC);
+        s.rfc += r(E1000_RFC);
+        s.roc += r(E1000_ROC);
+        s.rjc += r(E1000_RJC);
+        s.tor += r64(E1000_TORL, E1000_TORH);
+        s.tot += r64(E1000_TOTL, E1000_TOTH);
+        s.tpr += r(E1000_TPR);
+        s.tpt += r(E1000_TPT);
+        s.mptc += r(E1000_MPTC);
+        s.bptc += r(E1000_BPTC);
+        s.tsctc += r(E1000_TSCTC);
+        s.tsctfc += r(E1000_TSCTFC);
+    }
+
+    /// Snapshot of the statistics, including everything counted so far
+    /// here and by `rx`.
+    pub fn stats(&mut self, rx: &E1000RxRing<K>) -> E1000Stats {
+        self.e1000_update_stats();
+        E1000Stats {
+            hw_csum_good: rx.hw_csum_good,
+            hw_csum_err: rx.hw_csum_err,
+            alloc_rx_buff_failed: rx.alloc_rx_buff_failed,
+            ..self.stats
+        }
+    }
 
     /// Clear Interrupt
     pub fn e1000_irq_disable(&mut self) {
-        self.regs[E1000_IMC].write(!0); // 只有在对应位写1才能清中断Mask，以屏蔽对应中断
+        self.regs.write(E1000_IMC, !0); // 只有在对应位写1才能清中断Mask，以屏蔽对应中断
         self.e1000_write_flush();
     }
 
     /// Enable Interrupts
     pub fn e1000_irq_enable(&mut self) {
-        self.regs[E1000_IMS].write(IMS_ENABLE_MASK);
+        self.regs.write(E1000_IMS, IMS_ENABLE_MASK);
         self.e1000_write_flush();
     }
 
     /// flush e1000 status
     pub fn e1000_write_flush(&mut self) {
-        self.regs[E1000_STAT].read();
+        write_flush(&self.regs);
     }
 
     /// Cause a link status change interrupt
     pub fn e1000_cause_lsc_int(&mut self) {
-        self.regs[E1000_ICS].write(E1000_ICR_LSC);
+        self.regs.write(E1000_ICS, E1000_ICR_LSC);
     }
 
     /// To handle e1000 interrupt
-    pub fn e1000_intr(&mut self) -> u32 {
+    ///
+    /// On a link status change the cached link state is refreshed, see
+    /// [`Self::link_state`].
+    pub fn e1000_intr(&mut self) -> IntrCause {
         //self.e1000_recv();
 
         // tell the e1000 we've seen this interrupt;
         // without this the e1000 won't raise any
         // further interrupts.
-        let icr = self.regs[E1000_ICR].read();
-        self.regs[E1000_ICR].write(icr); //Writing a 1b to ICR any bit also clears that bit.
-        icr
+        let icr = self.regs.read(E1000_ICR);
+        self.regs.write(E1000_ICR, icr); //Writing a 1b to ICR any bit also clears that bit.
+
+        let cause = IntrCause(icr);
+        if cause.link_changed() {
+            self.e1000_check_link();
+        }
+        cause
+    }
+
+    /// Read the link state from STATUS, cache it and return it.
+    /// `None` means the link is down.
+    pub fn e1000_check_link(&mut self) -> Option<LinkState> {
+        let status = self.regs.read(E1000_STAT);
+        self.link = if status & E1000_STATUS_LU == 0 {
+            None
+        } else {
+            let speed = match status & E1000_STATUS_SPEED_MASK {
+                E1000_STATUS_SPEED_10 => LinkSpeed::Speed10,
+                E1000_STATUS_SPEED_100 => LinkSpeed::Speed100,
+                // 0b11 also means 1000 Mb/s
+                _ => LinkSpeed::Speed1000,
+            };
+            let duplex = if status & E1000_STATUS_FD != 0 {
+                Duplex::Full
+            } else {
+                Duplex::Half
+            };
+            Some(LinkState { speed, duplex })
+        };
+        self.link
+    }
+
+    /// The link state as of the last link status change.
+    /// `None` means the link is down.
+    pub fn link_state(&self) -> Option<LinkState> {
+        self.link
     }
 }
 
-/// called by e1000 driver's interrupt handler to deliver a packet to the
-/// networking stack
-pub fn net_rx(packet: &mut [u8]) {
-    /*
-    struct eth *ethhdr;
-    uint16 type;
+impl<K: KernelFunc> E1000Reset<K> {
+    /// Reset the device and reload its EEPROM, leaving receive, transmit
+    /// and interrupts off
+    pub fn e1000_reset(&self) -> Result<(), E1000Error> {
+        // Let what is in flight finish before the reset pulls the rings
+        // away from under it. The reset stops DMA either way.
+        if !self.e1000_stop_dma() {
+            pr_warn!("e1000, DMA still active, resetting anyway");
+        }
+
+        let ctl = self.regs.read(E1000_CTL);
+        self.regs.write(E1000_CTL, ctl | E1000_CTL_RST);
+        if !self.e1000_wait_clear(E1000_CTL, E1000_CTL_RST, RESET_TIMEOUT_US) {
+            pr_err!("e1000, reset timed out");
+            return Err(E1000Error::ResetTimeout);
+        }
 
-    ethhdr = mbufpullhdr(m, *ethhdr);
-    if (!ethhdr) {
-      mbuffree(m);
-      return;
+        // Reload RA[0] and the other EEPROM defaults the reset cleared.
+        let ctrl_ext = self.regs.read(E1000_CTRL_EXT);
+        self.regs
+            .write(E1000_CTRL_EXT, ctrl_ext | E1000_CTRL_EXT_EE_RST);
+        if !self.e1000_wait_clear(E1000_CTRL_EXT, E1000_CTRL_EXT_EE_RST, RESET_TIMEOUT_US) {
+            pr_err!("e1000, EEPROM reload timed out");
+            return Err(E1000Error::ResetTimeout);
+        }
+
+        self.regs.write(E1000_IMC, !0);
+        self.regs.read(E1000_ICR); // clear ints
+        Ok(())
     }
 
-    type = ntohs(ethhdr->type);
-    if (type == ETHTYPE_IP)
-      net_rx_ip(m);
-    else if (type == ETHTYPE_ARP)
-      net_rx_arp(m);
-    else
-      mbuffree(m);
+    /// Stop receive and transmit and mask interrupts, then wait for DMA
+    /// that is already under way to finish. False if the NIC was still
+    /// mastering the bus at the timeout.
+    fn e1000_stop_dma(&self) -> bool {
+        self.regs.write(E1000_IMC, !0);
+        let rctl = self.regs.read(E1000_RCTL);
+        self.regs.write(E1000_RCTL, rctl & !E1000_RCTL_EN);
+        let tctl = self.regs.read(E1000_TCTL);
+        self.regs.write(E1000_TCTL, tctl & !E1000_TCTL_EN);
+        write_flush(&self.regs);
+        // The frames on the wire and their descriptor write-backs complete.
+        self.kfn
+            .usleep_range(RESET_QUIESCE_US, 2 * RESET_QUIESCE_US);
 
-      */
+        // PCIe parts can be asked to stop issuing requests and report when
+        // the last one is done. Conventional PCI parts read the bit as zero.
+        if self.regs.read(E1000_STAT) & E1000_STATUS_GIO_MASTER_EN == 0 {
+            return true;
+        }
+        let ctl = self.regs.read(E1000_CTL);
+        self.regs
+            .write(E1000_CTL, ctl | E1000_CTL_GIO_MASTER_DISABLE);
+        self.e1000_wait_clear(E1000_STAT, E1000_STATUS_GIO_MASTER_EN, DMA_DRAIN_TIMEOUT_US)
+    }
+
+    /// Quiesce the device before its memory is freed, on remove or system
+    /// shutdown: receive, transmit and interrupts off and DMA drained. If
+    /// DMA does not drain, the device is reset, which stops it for good.
+    ///
+    /// The device is left unusable until [`E1000Device::reinit`].
+    pub fn e1000_shutdown(&self) {
+        if self.e1000_stop_dma() {
+            return;
+        }
+        pr_warn!("e1000, DMA did not drain, resetting");
+        let ctl = self.regs.read(E1000_CTL);
+        self.regs.write(E1000_CTL, ctl | E1000_CTL_RST);
+        if !self.e1000_wait_clear(E1000_CTL, E1000_CTL_RST, RESET_TIMEOUT_US) {
+            pr_err!("e1000, reset timed out");
+        }
+        self.regs.write(E1000_IMC, !0);
+    }
+
+    /// [`Self::e1000_shutdown`] if the NIC still works on the ring at
+    /// `ring_dma`: its base is in `bal`/`bah` and `en` of `ctl` is set.
+    /// For a ring about to be freed.
+    fn e1000_release_ring(&self, ring_dma: usize, bal: Reg, bah: Reg, ctl: Reg, en: u32) {
+        let base = ((self.regs.read(bah) as u64) << 32) | self.regs.read(bal) as u64;
+        if base == ring_dma as u64 && self.regs.read(ctl) & en != 0 {
+            self.e1000_shutdown();
+        }
+    }
+
+    /// Poll until `bits` of `reg` read as zero, for at most `timeout_us`
+    fn e1000_wait_clear(&self, reg: Reg, bits: u32, timeout_us: usize) -> bool {
+        for _ in 0..timeout_us.div_ceil(RESET_POLL_US) {
+            self.kfn.usleep_range(RESET_POLL_US, 2 * RESET_POLL_US);
+            if self.regs.read(reg) & bits == 0 {
+                return true;
+            }
+        }
+        false
+    }
 }
 
-impl<K: KernelFunc> Drop for E1000Device<'_, K> {
+impl<K: KernelFunc> E1000TxRing<K> {
+    /// Allocate the ring and its copy buffers for `config`
+    fn new(mut kfn: K, regs: Arc<E1000Regs>, config: &E1000Config) -> Result<Self, E1000Error> {
+        // 分配的ring内存空间需要16字节对齐
+        let ring = kfn
+            .dma_alloc_coherent(config.tx_ring_size)
+            .map_err(dma_no_memory)?;
+        // 一起申请所有TX内存
+        let mbufs = kfn
+            .dma_alloc_coherent(config.tx_ring_size * config.buffer_size)
+            .map_err(dma_no_memory)?;
+        let mut slots = KVec::with_capacity(config.tx_ring_size, GFP_KERNEL)
+            .map_err(|_| E1000Error::NoMemory)?;
+        for _ in 0..config.tx_ring_size {
+            let slot = TxSlot {
+                map: None,
+                frame: None,
+            };
+            slots
+                .push(slot, GFP_KERNEL)
+                .map_err(|_| E1000Error::NoMemory)?;
+        }
+        // Filled by e1000_init_ring().
+        Ok(E1000TxRing {
+            regs,
+            ring,
+            mbufs,
+            slots,
+            clean: 0,
+            mbuf_size: config.buffer_size,
+            context: None,
+            int_delay: E1000Coalesce::default().tx_delay_us != 0,
+            itr: ItrSample::default(),
+            kfn,
+        })
+    }
+
+    /// Put every descriptor back in its initial state, done, dropping the
+    /// frames still in flight with their mappings
+    fn e1000_init_ring(&mut self) {
+        self.ring.as_mut_slice().fill(TxDesc {
+            addr: 0,
+            length: 0,
+            cso: 0,
+            cmd: 0,
+            status: E1000_TXD_STAT_DD as u8,
+            css: 0,
+            special: 0,
+        });
+        for slot in self.slots.iter_mut() {
+            slot.map = None;
+            slot.frame = None;
+        }
+        self.clean = 0;
+        self.context = None;
+        fence_w();
+    }
+
+    /// Transmitting network packets
+    pub fn e1000_transmit(&mut self, packet: &[u8]) -> i32 {
+        self.e1000_transmit_sg([packet])
+    }
+
+    /// Transmit one frame gathered from `frags` (e.g. an skb's linear part
+    /// followed by its page fragments).
+    ///
+    /// The fragments are laid out over consecutive descriptors, one or more
+    /// per fragment, with EOP on the last. Returns the frame length, or -1
+    /// if there are not enough free descriptors.
+    pub fn e1000_transmit_sg<'p, I>(&mut self, frags: I) -> i32
+    where
+        I: IntoIterator<Item = &'p [u8]>,
+        I::IntoIter: Clone,
+    {
+        self.e1000_transmit_offload(frags, TxOffload::default())
+    }
+
+    /// Like [`Self::e1000_transmit_sg`], with the NIC doing the work asked
+    /// for in `offload`.
+    ///
+    /// Offloaded frames go out on extended data descriptors, preceded by a
+    /// context descriptor for every TSO frame, and for checksummed frames
+    /// whenever the offsets differ from the ones the NIC already holds.
+    pub fn e1000_transmit_offload<'p, I>(&mut self, frags: I, offload: TxOffload) -> i32
+    where
+        I: IntoIterator<Item = &'p [u8]>,
+        I::IntoIter: Clone,
+    {
+        self.e1000_tx_queue(frags, offload, None)
+            .map_or(-1, |(length, _)| length as i32)
+    }
+
+    /// Like [`Self::e1000_transmit_offload`], with the NIC reading `frame`
+    /// in place through a streaming DMA mapping of each fragment.
+    ///
+    /// The mappings and the frame are released as e1000_clean_tx() reclaims
+    /// its descriptors. Frames shorter than [`E1000_TX_COPYBREAK`], or that
+    /// cannot be mapped, are copied instead and consumed right away. Returns
+    /// the frame length, or hands the frame back if it was not queued.
+    pub fn e1000_transmit_frame(
+        &mut self,
+        frame: K::TxFrame,
+        offload: TxOffload,
+    ) -> Result<usize, K::TxFrame> {
+        let mut maps: [Option<K::DmaMapping>; E1000_TX_MAX_FRAGS] = core::array::from_fn(|_| None);
+        let length: usize = frame.fragments().map(<[u8]>::len).sum();
+        let mapped = length >= E1000_TX_COPYBREAK && self.e1000_tx_map(&frame, &mut maps);
+
+        let maps = if mapped { Some(&mut maps) } else { None };
+        let queued = self.e1000_tx_queue(frame.fragments(), offload, maps);
+        // Whatever was mapped for a frame that was not queued is unmapped here.
+        let Some((length, eop)) = queued else {
+            return Err(frame);
+        };
+        if mapped {
+            self.slots[eop].frame = Some(frame);
+        } else {
+            frame.consume();
+        }
+        Ok(length)
+    }
+
+    /// Map every non-empty fragment of `frame` into `maps`, or none at all
+    fn e1000_tx_map(
+        &mut self,
+        frame: &K::TxFrame,
+        maps: &mut [Option<K::DmaMapping>; E1000_TX_MAX_FRAGS],
+    ) -> bool {
+        if frame.fragments().count() > maps.len() {
+            return false;
+        }
+        for (map, frag) in maps.iter_mut().zip(frame.fragments()) {
+            if frag.is_empty() {
+                continue;
+            }
+            match self.kfn.dma_map(frag) {
+                Ok(mapping) => *map = Some(mapping),
+                Err(_) => {
+                    pr_warn!("e1000, TX DMA mapping failed, copying the frame");
+                    maps.iter_mut().for_each(|map| *map = None);
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Put a frame on the TX ring, copied into the ring's buffers or, given
+    /// `maps` holding a mapping per fragment, read from the fragments in
+    /// place. Each mapping moves to the last descriptor using it.
+    ///
+    /// Returns the frame length and the index of its EOP descriptor.
+    fn e1000_tx_queue<'p, I>(
+        &mut self,
+        frags: I,
+        offload: TxOffload,
+        mut maps: Option<&mut [Option<K::DmaMapping>; E1000_TX_MAX_FRAGS]>,
+    ) -> Option<(usize, usize)>
+    where
+        I: IntoIterator<Item = &'p [u8]>,
+        I::IntoIter: Clone,
+    {
+        let frags = frags.into_iter();
+        let tindex = self.regs.read(E1000_TDT) as usize;
+        let ring_len = self.ring.as_slice().len();
+
+        // Fragments larger than a buffer, or than a descriptor can take,
+        // are split further. Each chunk comes with its fragment, its offset
+        // in it and whether it is the fragment's last.
+        let chunk_size = match maps {
+            Some(_) => E1000_TX_MAX_DATA_PER_TXD,
+            None => self.mbuf_size,
+        };
+        let chunks = frags.enumerate().flat_map(move |(f, frag)| {
+            frag.chunks(chunk_size).enumerate().map(move |(c, chunk)| {
+                let offset = c * chunk_size;
+                (f, offset, chunk, offset + chunk.len() == frag.len())
+            })
+        });
+        let count = chunks.clone().count();
+        let length: usize = chunks.clone().map(|(_, _, chunk, _)| chunk.len()).sum();
+        let context = match (offload.tso, offload.csum) {
+            (Some(tso), _) => {
+                let paylen = length.saturating_sub(tso.hdr_len as usize);
+                if paylen == 0 || paylen > TSO_MAX_PAYLOAD {
+                    pr_err!("e1000, cannot segment a {} byte frame", length);
+                    return None;
+                }
+                Some(TxDesc::tso_context(tso, paylen))
+            }
+            (None, Some(csum)) if self.context != Some(csum) => Some(TxDesc::context(csum)),
+            _ => None,
+        };
+        let descs = count + context.is_some() as usize;
+        if descs >= ring_len {
+            pr_err!("The packet: {} to be send is TOO LARGE", length);
+            return None;
+        }
+        //info!("TX Desc = {:#x?}", self.ring[tindex]);
+        if descs > self.e1000_tx_free() {
+            // Reclaim what the NIC is done with before giving up.
+            self.e1000_clean_tx();
+            // Not worth a message: the caller stops the queue until the NIC
+            // catches up, which is ordinary flow control.
+            if descs > self.e1000_tx_free() {
+                return None;
+            }
+        }
+
+        let mut index = tindex;
+        if let Some(desc) = context {
+            self.ring.as_mut_slice()[index] = desc;
+            // A TSO context is not reused, it holds the frame's length.
+            self.context = offload.csum.filter(|_| offload.tso.is_none());
+            index = (index + 1) % ring_len;
+        }
+
+        // Legacy descriptors unless the NIC has work to do on the frame
+        let (dtyp, dext, popts) = match offload {
+            TxOffload { tso: Some(_), .. } => (
+                E1000_TXD_DTYP_D,
+                E1000_TXD_CMD_DEXT | E1000_TXD_CMD_TSE,
+                E1000_TXD_POPTS_IXSM | E1000_TXD_POPTS_TXSM,
+            ),
+            TxOffload { csum: Some(_), .. } => {
+                (E1000_TXD_DTYP_D, E1000_TXD_CMD_DEXT, E1000_TXD_POPTS_TXSM)
+            }
+            _ => (0, 0, 0),
+        };
+        // TIDV/TADV only hold back the interrupts of descriptors with IDE.
+        let ide = if self.int_delay { E1000_TXD_CMD_IDE } else { 0 };
+        let (vle, tag) = match offload.vlan {
+            Some(tag) => (E1000_TXD_CMD_VLE, tag),
+            None => (0, 0),
+        };
+        let mut eop_index = index;
+        for (i, (f, offset, chunk, last)) in chunks.enumerate() {
+            let addr = match maps.as_deref_mut() {
+                Some(maps) => {
+                    let addr = maps[f].as_ref().map_or(0, |map| map.dma_handle()) + offset;
+                    if last {
+                        self.slots[index].map = maps[f].take();
+                    }
+                    addr
+                }
+                None => {
+                    let start = index * self.mbuf_size;
+                    self.mbufs.as_mut_slice()[start..start + chunk.len()].copy_from_slice(chunk);
+                    self.mbufs.dma_handle() + start
+                }
+            };
+
+            let eop = if i == count - 1 { E1000_TXD_CMD_EOP } else { 0 };
+            let desc = &mut self.ring.as_mut_slice()[index];
+            // A context descriptor may have taken this slot before.
+            desc.addr = addr as u64;
+            desc.length = chunk.len() as u16;
+            desc.cso = dtyp as u8;
+            desc.status = 0;
+            desc.css = popts as u8;
+            desc.special = tag;
+            desc.cmd = (E1000_TXD_CMD_RS | ide | dext | vle | eop) as u8;
+            eop_index = index;
+            index = (index + 1) % ring_len;
+        }
+
+        self.regs.write(E1000_TDT, index as u32);
+        // The NIC puts out one frame per TSO segment.
+        let packets = offload.tso.map_or(1, |tso| {
+            (length - tso.hdr_len as usize).div_ceil(tso.mss as usize)
+        });
+        self.itr.count(packets, length);
+
+        write_flush(&self.regs);
+        // sync
+        fence_w();
+
+        Some((length, eop_index))
+    }
+
+    /// Reclaim the TX descriptors of the frames the NIC is done with,
+    /// walking from the last one reclaimed towards TDH. Returns the number
+    /// of frames sent.
+    ///
+    /// The copy buffers belong to the ring slots and are reused as they are;
+    /// mapped fragments are unmapped and their frames consumed. A queue
+    /// stopped on [`Self::e1000_tx_ring_full`] can be woken once that turns
+    /// false.
+    pub fn e1000_clean_tx(&mut self) -> usize {
+        let ring_len = self.ring.as_slice().len();
+        let head = self.regs.read(E1000_TDH) as usize;
+        let mut sent = 0;
+
+        fence();
+        let mut next = self.clean;
+        while next != head {
+            let desc = &self.ring.as_slice()[next];
+            // TDH moves on as descriptors are fetched, DD is set once they
+            // are done with.
+            if desc.status & E1000_TXD_STAT_DD as u8 == 0 {
+                break;
+            }
+            let eop = !desc.is_context() && desc.cmd & E1000_TXD_CMD_EOP as u8 != 0;
+            next = (next + 1) % ring_len;
+            if !eop {
+                continue;
+            }
+            // The NIC may read a frame's headers again until its EOP is
+            // done, so nothing is released before.
+            while self.clean != next {
+                let slot = &mut self.slots[self.clean];
+                slot.map = None;
+                if let Some(frame) = slot.frame.take() {
+                    frame.consume();
+                }
+                self.clean = (self.clean + 1) % ring_len;
+            }
+            sent += 1;
+        }
+        sent
+    }
+
+    /// Number of TX descriptors not in use
+    fn e1000_tx_free(&self) -> usize {
+        let ring_len = self.ring.as_slice().len();
+        let tail = self.regs.read(E1000_TDT) as usize;
+        // One descriptor stays unused so that a full ring is not TDT == TDH.
+        (self.clean + ring_len - tail - 1) % ring_len
+    }
+
+    /// Whether the largest frame that could come next might not fit in the
+    /// free TX descriptors, i.e. the caller should stop its queue.
+    pub fn e1000_tx_ring_full(&self) -> bool {
+        // A context descriptor, and every fragment may end with a short
+        // chunk, whether copied or mapped.
+        let chunk = self.mbuf_size.min(E1000_TX_MAX_DATA_PER_TXD);
+        let needed = 1 + E1000_TX_MAX_FRAGS + E1000_TX_MAX_LEN.div_ceil(chunk);
+        self.e1000_tx_free() < needed.min(self.ring.as_slice().len() - 1)
+    }
+}
+
+// The NIC must be done with a ring before its memory is freed, however
+// the device is torn down. Rings it no longer uses, e.g. after
+// E1000Device::take_over(), are left alone.
+impl<K: KernelFunc> Drop for E1000TxRing<K> {
     fn drop(&mut self) {
-        pr_info!("Drop DMA memory");
-        self.kfn
-            .dma_free_coherent(self.tx_ring.as_ptr() as usize, ALLOC_TX_RING_PAGES);
-        self.kfn
-            .dma_free_coherent(self.rx_ring.as_ptr() as usize, ALLOC_RX_RING_PAGES);
-        self.kfn
-            .dma_free_coherent(self.tx_mbufs[0], ALLOC_TX_BUFFER_PAGES);
-        self.kfn
-            .dma_free_coherent(self.rx_mbufs[0], ALLOC_RX_BUFFER_PAGES);
+        let reset = E1000Reset {
+            regs: self.regs.clone(),
+            kfn: self.kfn.clone(),
+        };
+        let dma = self.ring.dma_handle();
+        reset.e1000_release_ring(dma, E1000_TDBAL, E1000_TDBAH, E1000_TCTL, E1000_TCTL_EN);
+    }
+}
+
+impl<K: KernelFunc> Drop for E1000RxRing<K> {
+    fn drop(&mut self) {
+        let reset = E1000Reset {
+            regs: self.regs.clone(),
+            kfn: self.kfn.clone(),
+        };
+        let dma = self.ring.dma_handle();
+        reset.e1000_release_ring(dma, E1000_RDBAL, E1000_RDBAH, E1000_RCTL, E1000_RCTL_EN);
+    }
+}
+
+impl<K: KernelFunc> E1000RxRing<K> {
+    /// Allocate the ring and the pool of its buffers for `config`
+    fn new(kfn: &mut K, regs: Arc<E1000Regs>, config: &E1000Config) -> Result<Self, E1000Error> {
+        let ring = kfn
+            .dma_alloc_coherent(config.rx_ring_size)
+            .map_err(dma_no_memory)?;
+        let pool = kfn
+            .rx_pool(config.rx_ring_size, config.buffer_size)
+            .map_err(|_| {
+                pr_err!("e1000, RX buffer pool creation failed");
+                E1000Error::NoMemory
+            })?;
+        // Filled by e1000_init_ring().
+        let mut bufs = KVec::with_capacity(config.rx_ring_size, GFP_KERNEL)
+            .map_err(|_| E1000Error::NoMemory)?;
+        for _ in 0..config.rx_ring_size {
+            bufs.push(None, GFP_KERNEL)
+                .map_err(|_| E1000Error::NoMemory)?;
+        }
+        Ok(E1000RxRing {
+            regs,
+            ring,
+            bufs,
+            clean: 0,
+            copybreak: E1000_RX_COPYBREAK,
+            discarding: false,
+            hw_csum_good: 0,
+            hw_csum_err: 0,
+            alloc_rx_buff_failed: 0,
+            itr: ItrSample::default(),
+            kfn: kfn.clone(),
+            pool,
+        })
+    }
+
+    /// See [`E1000Device::take_over`]
+    fn take_over(&mut self, old: &Self) {
+        self.copybreak = old.copybreak;
+        self.hw_csum_good = old.hw_csum_good;
+        self.hw_csum_err = old.hw_csum_err;
+        self.alloc_rx_buff_failed = old.alloc_rx_buff_failed;
+    }
+
+    /// Put every descriptor back in its initial state, empty and pointing
+    /// at its buffer. Buffers are kept, and allocated where missing.
+    fn e1000_init_ring(&mut self) -> Result<(), E1000Error> {
+        let descs = self.ring.as_mut_slice();
+        for (desc, slot) in descs.iter_mut().zip(self.bufs.iter_mut()) {
+            if slot.is_none() {
+                let buf = self.pool.alloc().map_err(|_| {
+                    pr_err!("e1000, RX buffer allocation failed");
+                    E1000Error::NoMemory
+                })?;
+                *slot = Some(buf);
+            }
+            *desc = RxDesc {
+                addr: slot.as_ref().map_or(0, |buf| buf.dma_handle()) as u64,
+                length: 0,
+                csum: 0,
+                status: 0,
+                errors: 0,
+                special: 0,
+            };
+        }
+        self.clean = 0;
+        self.discarding = false;
+        fence_w();
+        Ok(())
+    }
+
+    /// Frames up to this long are copied out of their buffer
+    pub fn rx_copybreak(&self) -> usize {
+        self.copybreak
+    }
+
+    /// Copy received frames of up to `bytes` out of their buffer, which is
+    /// then reused in place; longer frames are handed up in their buffers,
+    /// which get replaced.
+    pub fn set_rx_copybreak(&mut self, bytes: usize) {
+        self.copybreak = bytes;
+    }
+
+    /// Receiving network packets, at most `budget` of them per call.
+    ///
+    /// Each completed frame is lent to `deliver` in its buffers, which it
+    /// may copy the frame out of or take, see [`RxFrame::is_copybreak`].
+    /// Once `deliver` returns, the descriptors are handed back to the
+    /// hardware by e1000_rx_refill(). Frames with CRC, symbol or other
+    /// receive errors are dropped instead, as are frames longer than the
+    /// descriptors the NIC was given. Returns the number of frames taken
+    /// off the ring, zero if none were pending.
+    pub fn e1000_recv<F>(&mut self, budget: usize, mut deliver: F) -> usize
+    where
+        F: FnMut(RxFrame<'_, K>),
+    {
+        let mut received = 0;
+        let ring_len = self.ring.as_slice().len();
+
+        while received < budget {
+            let rindex = self.clean;
+            // DD设为1时，内存中的接收包是完整的
+            // A frame spanning several buffers is complete once the
+            // descriptor with EOP is done.
+            let mut count = 0;
+            let mut complete = false;
+            while count < ring_len {
+                let status = self.ring.as_slice()[(rindex + count) % ring_len].status;
+                if status & E1000_RXD_STAT_DD as u8 == 0 {
+                    break;
+                }
+                count += 1;
+                if status & E1000_RXD_STAT_EOP as u8 != 0 {
+                    complete = true;
+                    break;
+                }
+            }
+            if !complete {
+                // The NIC filled every descriptor it has and waits for more
+                // to finish the frame, which would stall the ring. Drop
+                // what it has, and the rest as it arrives.
+                let tail = self.regs.read(E1000_RDT) as usize;
+                if count == 0 || (rindex + count) % ring_len != tail {
+                    break;
+                }
+                self.discarding = true;
+                received += 1;
+                self.e1000_rx_release(rindex, count);
+                continue;
+            }
+
+            fence();
+            for i in 0..count {
+                let index = (rindex + i) % ring_len;
+                let len = self.ring.as_slice()[index].length as usize;
+                if let Some(buf) = self.bufs[index].as_mut() {
+                    buf.sync_for_cpu(min(len, buf.as_slice().len()));
+                }
+            }
+            let frame = RxFrame {
+                ring: self.ring.as_slice(),
+                bufs: &mut self.bufs,
+                first: rindex,
+                count,
+                copybreak: self.copybreak,
+            };
+            // The hardware counts the errors, see e1000_update_stats().
+            if core::mem::take(&mut self.discarding) {
+                // The end of a frame that was too long.
+            } else if !frame.has_frame_error() {
+                match frame.checksum() {
+                    RxChecksum::Verified => self.hw_csum_good += 1,
+                    RxChecksum::Bad => self.hw_csum_err += 1,
+                    RxChecksum::None => {}
+                }
+                self.itr.count(1, frame.len());
+                deliver(frame);
+            }
+            received += 1;
+            self.e1000_rx_release(rindex, count);
+        }
+
+        received
+    }
+
+    /// Move past the `count` descriptors from `rindex` that e1000_recv()
+    /// is done with, and refill them.
+    fn e1000_rx_release(&mut self, rindex: usize, count: usize) {
+        let ring_len = self.ring.as_slice().len();
+        // Not to be taken for a received frame again while they wait
+        // for e1000_rx_refill(). Buffers left in place are reused.
+        for i in 0..count {
+            let index = (rindex + i) % ring_len;
+            self.ring.as_mut_slice()[index].status = 0;
+            if let Some(buf) = self.bufs[index].as_mut() {
+                buf.sync_for_device();
+            }
+        }
+        self.clean = (rindex + count) % ring_len;
+        self.e1000_rx_refill();
+    }
+
+    /// Give the descriptors e1000_recv() is done with a buffer where
+    /// theirs went up the stack, and hand them back to the NIC up to the
+    /// one before the next to check. If no buffer can be allocated, the
+    /// rest wait for the next call.
+    fn e1000_rx_refill(&mut self) {
+        let ring_len = self.ring.as_slice().len();
+        let old_tail = self.regs.read(E1000_RDT) as usize;
+        let mut tail = old_tail;
+
+        loop {
+            let slot = &mut self.bufs[tail];
+            if slot.is_none() {
+                match self.pool.alloc() {
+                    Ok(buf) => *slot = Some(buf),
+                    Err(_) => {
+                        self.alloc_rx_buff_failed += 1;
+                        break;
+                    }
+                }
+            }
+            let desc = &mut self.ring.as_mut_slice()[tail];
+            desc.addr = slot.as_ref().map_or(0, |buf| buf.dma_handle()) as u64;
+            // The one before the next to check stays with the driver, so
+            // that a full ring is not RDT == RDH.
+            let next = (tail + 1) % ring_len;
+            if next == self.clean {
+                break;
+            }
+            tail = next;
+        }
+
+        if tail != old_tail {
+            fence_w();
+            self.regs.write(E1000_RDT, tail as u32);
+            write_flush(&self.regs);
+        }
     }
 }
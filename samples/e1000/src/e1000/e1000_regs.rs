@@ -0,0 +1,97 @@
+// Typed access to the e1000 register block.
+
+use kernel::io::{Io, Mmio, MmioRaw};
+use kernel::prelude::*;
+
+/// Size of BAR 0: 0x00000 ~ 0x1FFFF, I/O-Mapped Internal Registers and Memories
+pub const E1000_MMIO_SIZE: usize = 0x20000;
+
+/// A 32 bit register at a fixed offset into BAR 0
+///
+/// The offset is checked when the constant is evaluated, so a register
+/// outside the BAR is a build error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Reg(usize);
+
+impl Reg {
+    pub(crate) const fn new(offset: usize) -> Self {
+        assert!(offset.is_multiple_of(4) && offset + 4 <= E1000_MMIO_SIZE);
+        Reg(offset)
+    }
+}
+
+/// `LEN` consecutive 32 bit registers, such as the multicast table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RegArray<const LEN: usize> {
+    base: usize,
+}
+
+impl<const LEN: usize> RegArray<LEN> {
+    pub(crate) const fn new(base: usize) -> Self {
+        assert!(base.is_multiple_of(4) && base + 4 * LEN <= E1000_MMIO_SIZE);
+        RegArray { base }
+    }
+
+    /// Register `I`; an index out of range is a build error
+    pub(crate) const fn at<const I: usize>(self) -> Reg {
+        const { assert!(I < LEN) };
+        Reg(self.base + 4 * I)
+    }
+}
+
+/// The mapped register block of one NIC
+pub struct E1000Regs(MmioRaw<E1000_MMIO_SIZE>);
+
+impl E1000Regs {
+    /// Wrap a mapping of BAR 0 at `addr`, `maxsize` bytes long
+    ///
+    /// # Safety
+    ///
+    /// `addr` must stay mapped to the device registers for as long as the
+    /// result, and everything built from it, is alive.
+    pub unsafe fn new(addr: usize, maxsize: usize) -> Result<Self> {
+        Ok(E1000Regs(MmioRaw::new(addr, maxsize)?))
+    }
+
+    fn io(&self) -> &Mmio<E1000_MMIO_SIZE> {
+        // SAFETY: The caller of `new` keeps the mapping alive.
+        unsafe { Mmio::from_raw(&self.0) }
+    }
+
+    // A `Reg` is often passed down to a helper, where its offset need not
+    // fold to the constant `read32`/`write32` require to link. The checked
+    // accessors cannot fail either: `Reg::new` checked the offset.
+
+    #[inline]
+    pub(crate) fn read(&self, reg: Reg) -> u32 {
+        // All ones, as from a device that went away.
+        self.io().try_read32(reg.0).unwrap_or(!0)
+    }
+
+    #[inline]
+    pub(crate) fn write(&self, reg: Reg, value: u32) {
+        let _ = self.io().try_write32(value, reg.0);
+    }
+
+    /// Write all of `array`, in order
+    pub(crate) fn write_all<const LEN: usize>(&self, array: RegArray<LEN>, values: &[u32; LEN]) {
+        for (i, &value) in values.iter().enumerate() {
+            // Cannot fail: `RegArray::new` checked the whole array fits.
+            let _ = self.io().try_write32(value, array.base + 4 * i);
+        }
+    }
+
+    /// Write register `index` of `array`, for an index only known at run
+    /// time. `EINVAL` if it is out of range.
+    pub(crate) fn write_at<const LEN: usize>(
+        &self,
+        array: RegArray<LEN>,
+        index: usize,
+        value: u32,
+    ) -> Result {
+        if index >= LEN {
+            return Err(EINVAL);
+        }
+        self.io().try_write32(value, array.base + 4 * index)
+    }
+}
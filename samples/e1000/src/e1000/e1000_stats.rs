@@ -0,0 +1,145 @@
+// E1000 hardware statistics.
+// The counters are cleared when read, so they are accumulated here.
+
+/// Number of counters in [`E1000Stats`]
+pub const E1000_STATS_LEN: usize = 42;
+
+/// Accumulated hardware statistics [E1000 13.7], and the receive checksum
+/// results and buffer shortages counted by the driver
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct E1000Stats {
+    /// Frames received with a CRC error
+    pub crcerrs: u64,
+    /// Frames received with an alignment error
+    pub algnerrc: u64,
+    /// Symbol errors (fiber only)
+    pub symerrs: u64,
+    /// Frames received with an RX_ER error
+    pub rxerrc: u64,
+    /// Frames missed because the receive FIFO was full
+    pub mpc: u64,
+    /// Frames sent after a single collision
+    pub scc: u64,
+    /// Frames dropped after 16 collisions
+    pub ecol: u64,
+    /// Frames sent after multiple collisions
+    pub mcc: u64,
+    /// Late collisions
+    pub latecol: u64,
+    /// Total collisions
+    pub colc: u64,
+    /// Deferred transmissions
+    pub dc: u64,
+    /// Transmissions without carrier sense
+    pub tncrs: u64,
+    /// Sequence errors
+    pub sec: u64,
+    /// Carrier extension errors
+    pub cexterr: u64,
+    /// Frames received with a length error
+    pub rlec: u64,
+    /// XON frames received
+    pub xonrxc: u64,
+    /// XON frames sent
+    pub xontxc: u64,
+    /// XOFF frames received
+    pub xoffrxc: u64,
+    /// XOFF frames sent
+    pub xofftxc: u64,
+    /// Unsupported flow control frames received
+    pub fcruc: u64,
+    /// Good frames received
+    pub gprc: u64,
+    /// Broadcast frames received
+    pub bprc: u64,
+    /// Multicast frames received
+    pub mprc: u64,
+    /// Good frames sent
+    pub gptc: u64,
+    /// Octets in good frames received
+    pub gorc: u64,
+    /// Octets in good frames sent
+    pub gotc: u64,
+    /// Frames received while no descriptor was available
+    pub rnbc: u64,
+    /// Undersized frames received
+    pub ruc: u64,
+    /// Undersized frames with a bad CRC received
+    pub rfc: u64,
+    /// Oversized frames received
+    pub roc: u64,
+    /// Oversized frames with a bad CRC received
+    pub rjc: u64,
+    /// Octets received, including bad frames
+    pub tor: u64,
+    /// Octets sent
+    pub tot: u64,
+    /// Frames received, including bad frames
+    pub tpr: u64,
+    /// Frames sent
+    pub tpt: u64,
+    /// Multicast frames sent
+    pub mptc: u64,
+    /// Broadcast frames sent
+    pub bptc: u64,
+    /// TCP segmentation contexts sent
+    pub tsctc: u64,
+    /// TCP segmentation contexts that failed
+    pub tsctfc: u64,
+    /// Frames whose TCP/UDP checksum the NIC verified
+    pub hw_csum_good: u64,
+    /// Frames the NIC found a bad IP or TCP/UDP checksum in
+    pub hw_csum_err: u64,
+    /// RX buffers that could not be replaced after going up the stack
+    pub alloc_rx_buff_failed: u64,
+}
+
+impl E1000Stats {
+    /// The counters with the names `ethtool -S` shows for them
+    pub fn ethtool_stats(&self) -> [(&'static str, u64); E1000_STATS_LEN] {
+        [
+            ("rx_packets", self.gprc),
+            ("tx_packets", self.gptc),
+            ("rx_bytes", self.gorc),
+            ("tx_bytes", self.gotc),
+            ("rx_broadcast", self.bprc),
+            ("tx_broadcast", self.bptc),
+            ("rx_multicast", self.mprc),
+            ("tx_multicast", self.mptc),
+            ("rx_total_packets", self.tpr),
+            ("tx_total_packets", self.tpt),
+            ("rx_total_bytes", self.tor),
+            ("tx_total_bytes", self.tot),
+            ("rx_crc_errors", self.crcerrs),
+            ("rx_align_errors", self.algnerrc),
+            ("rx_symbol_errors", self.symerrs),
+            ("rx_errors", self.rxerrc),
+            ("rx_missed_errors", self.mpc),
+            ("rx_no_buffer_count", self.rnbc),
+            ("rx_length_errors", self.rlec),
+            ("rx_short_length_errors", self.ruc),
+            ("rx_fragments", self.rfc),
+            ("rx_long_length_errors", self.roc),
+            ("rx_jabbers", self.rjc),
+            ("rx_carrier_ext_errors", self.cexterr),
+            ("rx_sequence_errors", self.sec),
+            ("tx_single_coll_ok", self.scc),
+            ("tx_multi_coll_ok", self.mcc),
+            ("tx_abort_late_coll", self.latecol),
+            ("tx_aborted_errors", self.ecol),
+            ("collisions", self.colc),
+            ("tx_deferred_ok", self.dc),
+            ("tx_carrier_errors", self.tncrs),
+            ("rx_xon", self.xonrxc),
+            ("tx_xon", self.xontxc),
+            ("rx_xoff", self.xoffrxc),
+            ("tx_xoff", self.xofftxc),
+            ("rx_flow_control_unsupported", self.fcruc),
+            ("tx_tcp_seg_good", self.tsctc),
+            ("tx_tcp_seg_failed", self.tsctfc),
+            ("rx_csum_offload_good", self.hw_csum_good),
+            ("rx_csum_offload_errors", self.hw_csum_err),
+            ("alloc_rx_buff_failed", self.alloc_rx_buff_failed),
+        ]
+    }
+}
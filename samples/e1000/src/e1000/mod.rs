@@ -1,4 +1,16 @@
+mod e1000_coalesce;
+mod e1000_config;
+// The register map is kept whole, not only what the driver uses yet.
+#[allow(dead_code)]
 mod e1000_const;
+mod e1000_error;
 mod e1000_inner;
+mod e1000_regs;
+mod e1000_stats;
 
+pub use e1000_coalesce::*;
+pub use e1000_config::*;
+pub use e1000_error::*;
 pub use e1000_inner::*;
+pub use e1000_regs::*;
+pub use e1000_stats::*;
@@ -1,43 +1,44 @@
 #![no_std]
 //! Rust e1000 network device.
 
-#![allow(unused)]
 #![allow(missing_docs)]
 
-use core::slice::from_raw_parts_mut;
-use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
-use kernel::transmute::AsBytes;
+use core::ffi::{c_int, c_uint, c_void};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use kernel::transmute::{AsBytes, FromBytes};
 use kernel::{
     bindings, c_str, device,
     devres::Devres,
-    dma, driver, pci,
-    sync::{Arc, ArcBorrow, CondVar, SpinLock, UniqueArc},
+    dma,
+    error::to_result,
+    irq, new_spinlock, pci,
+    sync::{Arc, ArcBorrow, SpinLock},
     types::ARef,
 };
-use kernel::{io::Io, prelude::*, workqueue};
+use kernel::{
+    prelude::*,
+    workqueue::{self, Work, WorkItem},
+};
 
 #[macro_use]
 pub mod linux;
 pub mod e1000;
+mod ethtool;
 pub mod utils;
 
-use e1000::E1000Device;
-
-use core::{
-    option::Option::{self, None, Some},
-    result::Result::{Err, Ok},
-    *,
+use e1000::{
+    Duplex, E1000Config, E1000Device, E1000Eerd, E1000Error, E1000Hw, E1000Regs, E1000Reset,
+    E1000RxRing, E1000Stats, E1000TxRing, LinkState, RxChecksum, RxFrame, RxMode, TxChecksum,
+    TxOffload, TxTso,
 };
-
-// const RXBUFFER: u32 = 2048;
-// /// Intel E1000 ID
-// const VENDOR_ID_INTEL: u32 = 0x8086;
-// const DEVICE_ID_INTEL_I219: u32 = 0x15fc;
-// const DEVICE_ID_INTEL_82540EM: u32 = 0x100e;
-// const DEVICE_ID_INTEL_82574L: u32 = 0x10d3;
-// //const MAC_HWADDR: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
-// //const MAC_HWADDR: [u8; 6] = [0x90, 0xe2, 0xfc, 0xb5, 0x36, 0x95];
-// const MAC_HWADDR: [u8; 6] = [0x52, 0x54, 0x00, 0x6c, 0xf8, 0x88];
+use linux::helpers::{rust_e1000_dma_map_to_device, rust_e1000_dma_unmap_to_device};
+use linux::netdev::{
+    NETIF_F_HW_CSUM, NETIF_F_HW_VLAN_CTAG_FILTER, NETIF_F_HW_VLAN_CTAG_RX, NETIF_F_HW_VLAN_CTAG_TX,
+    NETIF_F_RXCSUM, NETIF_F_SG, NETIF_F_TSO, Napi, NetDev, NetDevice, PagePool, PoolFrag,
+    RtnlGuard, SkBuff, is_valid_ether_addr,
+};
+use linux::sync::lock_irqsave;
+use linux::workqueue::DelayedWork;
 
 kernel::module_pci_driver! {
     type: E1000Driver,
@@ -47,80 +48,827 @@ kernel::module_pci_driver! {
     license: "GPL",
 }
 
-struct Regs;
-
-impl Regs {
-    const TEST: usize = 0x0;
-    const OFFSET: usize = 0x4;
-    const DATA: usize = 0x8;
-    const COUNT: usize = 0xC;
-    const END: usize = 0x10;
-}
-
-type Bar0 = pci::Bar<{ Regs::END }>;
+type Bar0 = pci::Bar<{ e1000::E1000_MMIO_SIZE }>;
 
 struct E1000Driver {
     _pdev: ARef<pci::Device>,
+    // Freed first: on a shared line the handler can run until then, and it
+    // uses the net_device.
+    _irq: Pin<KBox<irq::Registration<E1000IrqHandler>>>,
+    // Freed before the adapter, which holds its NAPI context.
+    netdev: NetDevice,
+    adapter: Arc<E1000Adapter>,
     _bar: Pin<KBox<Devres<Bar0>>>,
-    inner: E1000Device<'static, Kernfn>,
 }
 
+/// State shared by the net_device callbacks, the IRQ handler and NAPI.
+#[pin_data]
+struct E1000Adapter {
+    ndev: NetDev,
+    // Needed to rebuild `dev` with a different configuration.
+    pdev: ARef<pci::Device>,
+    // Address and length of the BAR 0 mapping, kept alive by the driver data.
+    mmio: (usize, usize),
+    // The halves of the device, locked apart so that transmit and receive
+    // do not hold each other up. Taken in this order when more than one
+    // is needed.
+    #[pin]
+    hw: SpinLock<E1000Hw<Kernfn>>,
+    #[pin]
+    tx: SpinLock<E1000TxRing<Kernfn>>,
+    #[pin]
+    rx: SpinLock<E1000RxRing<Kernfn>>,
+    // Resets and quiesces the hardware. It sleeps, so it is used without
+    // the locks, while the interface is down.
+    hw_reset: E1000Reset<Kernfn>,
+    #[pin]
+    napi: Napi,
+    // The data path is started, see `up` and `down`. Not the same as the
+    // interface running: a failed reset leaves it down while IFF_UP is set.
+    active: AtomicBool,
+    // The last reset failed; the device is reset again when next opened.
+    broken: AtomicBool,
+    // Frames `e1000_xmit_frame` threw away, which the NIC never counts.
+    tx_dropped: AtomicU64,
+    // Runs while the data path is started, see `e1000_stats_work`.
+    #[pin]
+    stats_work: DelayedWork,
+    // Queued by the TX watchdog, see `E1000Adapter::reset`.
+    #[pin]
+    reset_work: Work<E1000Adapter>,
+}
+
+kernel::impl_has_work! {
+    impl HasWork<Self> for E1000Adapter { self.reset_work }
+}
+
+impl WorkItem for E1000Adapter {
+    type Pointer = Arc<E1000Adapter>;
+
+    fn run(adapter: Arc<E1000Adapter>) {
+        adapter.reset();
+    }
+}
+
+impl E1000Adapter {
+    /// Get the adapter stored in the net_device's private pointer.
+    ///
+    /// # Safety
+    ///
+    /// `ndev` must be the net_device registered by [`E1000Driver::probe`].
+    unsafe fn from_netdev<'a>(ndev: *mut bindings::net_device) -> ArcBorrow<'a, Self> {
+        // SAFETY: The caller guarantees that `ndev` is our registered net_device.
+        let ndev = unsafe { NetDev::from_raw(ndev) };
+        // SAFETY: probe stored `Arc::as_ptr` of the adapter, which outlives the registration.
+        unsafe { ArcBorrow::from_raw(ndev.driver_data().cast()) }
+    }
+
+    /// Get the adapter that embeds `napi`.
+    ///
+    /// # Safety
+    ///
+    /// `napi` must be the context added in [`E1000Driver::probe`].
+    unsafe fn from_napi<'a>(napi: *mut bindings::napi_struct) -> ArcBorrow<'a, Self> {
+        let napi = napi.cast::<Napi>();
+        // SAFETY: The caller guarantees that `napi` is the `napi` field of an adapter.
+        let adapter = unsafe { kernel::container_of!(napi, E1000Adapter, napi) };
+        // SAFETY: The adapter lives in an `Arc` that outlives the NAPI context.
+        unsafe { ArcBorrow::from_raw(adapter.cast()) }
+    }
+
+    /// Get the adapter that embeds the `work_struct` of `stats_work`.
+    ///
+    /// # Safety
+    ///
+    /// `work` must be that of the item initialised in [`E1000Driver::probe`].
+    unsafe fn from_stats_work<'a>(work: *mut bindings::work_struct) -> ArcBorrow<'a, Self> {
+        // SAFETY: The caller guarantees that `work` is in an adapter's `stats_work`.
+        let stats_work: *const DelayedWork = unsafe { DelayedWork::from_work(work) };
+        // SAFETY: As above.
+        let adapter = unsafe { kernel::container_of!(stats_work, E1000Adapter, stats_work) };
+        // SAFETY: The work is cancelled before the adapter's `Arc` goes away.
+        unsafe { ArcBorrow::from_raw(adapter.cast()) }
+    }
+
+    /// Move up to `budget` completed frames from the RX ring to the stack.
+    ///
+    /// Frames go up in the page pool buffers they were received into, the
+    /// ring getting new ones; those up to the copybreak are copied into a
+    /// fresh skb instead and their buffer reused. The skbs are handed to the
+    /// stack after the RX lock is released.
+    fn clean_rx(&self, budget: usize) -> usize {
+        let mut skbs = KVec::new();
+        // The NIC always verifies; whether the stack trusts it is up to ethtool.
+        let rxcsum = self.ndev.has_features(NETIF_F_RXCSUM);
+        let received = lock_irqsave(&self.rx).e1000_recv(budget, |mut frame| {
+            let skb = if frame.is_copybreak() || frame.segments().count() > RX_MAX_BUFFERS {
+                self.copy_rx_frame(&frame)
+            } else {
+                build_rx_skb(&mut frame)
+            };
+            let Some(mut skb) = skb else {
+                pr_warn!("e1000: dropping rx packet, skb allocation failed\n");
+                return;
+            };
+            if rxcsum && frame.checksum() == RxChecksum::Verified {
+                skb.set_checksum_unnecessary();
+            }
+            if let Some(tci) = frame.vlan_tag() {
+                skb.put_vlan_tag(tci);
+            }
+            if skbs.push(skb, GFP_ATOMIC).is_err() {
+                pr_warn!("e1000: dropping rx packet, out of memory\n");
+            }
+        });
+
+        for skb in skbs {
+            self.ndev.napi_rx(&self.napi, skb);
+        }
+        received
+    }
+
+    /// Copy `frame` into a fresh skb, leaving its buffers on the ring.
+    fn copy_rx_frame(&self, frame: &RxFrame<'_, Kernfn>) -> Option<SkBuff> {
+        let len = frame.len();
+        let mut skb = self.ndev.alloc_rx_skb(len)?;
+        let mut data = skb.put(len);
+        for segment in frame.segments() {
+            let (head, rest) = core::mem::take(&mut data).split_at_mut(segment.len());
+            head.copy_from_slice(segment);
+            data = rest;
+        }
+        Some(skb)
+    }
+
+    /// Reclaim the TX descriptors the NIC is done with, and restart the
+    /// queue if it was stopped for want of them.
+    fn clean_tx(&self) {
+        let mut tx = lock_irqsave(&self.tx);
+        tx.e1000_clean_tx();
+        // Under the lock, so that it cannot race with the stop in xmit.
+        if self.ndev.is_queue_stopped() && !tx.e1000_tx_ring_full() {
+            self.ndev.wake_queue();
+        }
+    }
+
+    /// Start the data path: NAPI, interrupts and the transmit queue.
+    fn up(&self) {
+        self.active.store(true, Ordering::Relaxed);
+        self.napi.enable();
+        let link = {
+            let mut hw = lock_irqsave(&self.hw);
+            hw.e1000_irq_enable();
+            hw.e1000_check_link()
+        };
+        self.update_carrier(link);
+        self.ndev.tx_wake_all();
+        self.stats_work.schedule(E1000_STATS_PERIOD_MS);
+    }
+
+    /// Quiesce the data path; the reverse of [`Self::up`]. Does nothing if
+    /// it is not started: NAPI must not be disabled twice.
+    fn down(&self) {
+        if !self.active.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        self.ndev.tx_disable();
+        lock_irqsave(&self.hw).e1000_irq_disable();
+        self.napi.disable();
+        self.stats_work.cancel_sync();
+        self.ndev.carrier_off();
+    }
+
+    /// The statistics of the hardware and the receive path
+    fn stats(&self) -> E1000Stats {
+        let mut hw = lock_irqsave(&self.hw);
+        let rx = lock_irqsave(&self.rx);
+        hw.stats(&rx)
+    }
+
+    /// Count a frame the transmit path gives up on; the caller frees it
+    fn tx_drop(&self) -> bindings::netdev_tx_t {
+        self.tx_dropped.fetch_add(1, Ordering::Relaxed);
+        bindings::netdev_tx_NETDEV_TX_OK
+    }
+
+    /// A register handle for a new `E1000Device`
+    fn regs(&self) -> Result<E1000Regs> {
+        let (addr, maxsize) = self.mmio;
+        // SAFETY: The driver data holds the BAR mapping for as long as the
+        // adapter, and with it every device built here, is alive.
+        unsafe { E1000Regs::new(addr, maxsize) }
+    }
+
+    /// Replace the device with one using `config`, keeping the station
+    /// address and statistics. Called with the RTNL lock held.
+    fn reconfigure(&self, config: E1000Config) -> Result {
+        config.validate()?;
+        let regs = self.regs()?;
+        let kfn = Kernfn::new(self.pdev.clone());
+        let running = self.ndev.is_running();
+        if running {
+            self.down();
+        }
+
+        // The new device resets the hardware, which clears the counters.
+        lock_irqsave(&self.hw).e1000_update_stats();
+        // The NIC has to be done with the old rings before they are freed.
+        self.hw_reset.e1000_shutdown();
+        // Allocates with GFP_KERNEL, so it cannot happen under the locks.
+        let result = match E1000Device::new(kfn, regs, config) {
+            Ok(mut new) => {
+                let old = {
+                    let mut hw = lock_irqsave(&self.hw);
+                    let mut tx = lock_irqsave(&self.tx);
+                    let mut rx = lock_irqsave(&self.rx);
+                    new.take_over(&hw, &rx);
+                    E1000Device {
+                        hw: core::mem::replace(&mut *hw, new.hw),
+                        tx: core::mem::replace(&mut *tx, new.tx),
+                        rx: core::mem::replace(&mut *rx, new.rx),
+                    }
+                };
+                // The old rings are freed outside the locks.
+                drop(old);
+                self.broken.store(false, Ordering::Relaxed);
+                Ok(())
+            }
+            // Carry on with the old device.
+            Err(e) => match self.reinit() {
+                Ok(()) => Err(e.into()),
+                Err(reinit) => {
+                    // Stay down rather than run on a device in an unknown
+                    // state. The interface stays up without carrier, and
+                    // the next close finds the data path already stopped.
+                    pr_err!("e1000: reset failed: {:?}\n", reinit);
+                    return Err(e.into());
+                }
+            },
+        };
+
+        if running {
+            self.up();
+        }
+        result
+    }
+
+    /// Reset the NIC and rebuild the rings of the device. Called with the
+    /// data path down and the RTNL lock held.
+    fn reinit(&self) -> Result<(), E1000Error> {
+        // The reset clears the counters.
+        lock_irqsave(&self.hw).e1000_update_stats();
+        // Nothing else drives the hardware while the data path is down:
+        // interrupts and NAPI are off, and configuration is held off by the
+        // RTNL lock. The locks are taken only to rebuild the rings.
+        let result = self.hw_reset.e1000_reset().and_then(|()| {
+            let mut hw = lock_irqsave(&self.hw);
+            let mut tx = lock_irqsave(&self.tx);
+            let mut rx = lock_irqsave(&self.rx);
+            hw.reinit(&mut tx, &mut rx)
+        });
+        self.broken.store(result.is_err(), Ordering::Relaxed);
+        result
+    }
+
+    /// Recover from a hung transmitter: reset the NIC and rebuild its rings.
+    /// Runs from the workqueue, as it sleeps and takes the RTNL lock.
+    fn reset(&self) {
+        let _rtnl = RtnlGuard::lock();
+        if !self.ndev.is_running() {
+            return;
+        }
+        self.down();
+        match self.reinit() {
+            Ok(()) => self.up(),
+            // Stay down rather than run on a device in an unknown state. The
+            // interface stays up without carrier; closing it finds the data
+            // path already stopped, and opening it again retries the reset.
+            Err(e) => pr_err!("e1000: reset failed: {:?}\n", e),
+        }
+    }
+
+    /// Wait for a reset queued by the TX watchdog to finish.
+    fn flush_reset(&self) {
+        // SAFETY: `reset_work` was initialised in probe and is pinned in the
+        // adapter's `Arc`.
+        unsafe { bindings::flush_work(Work::raw_get(&self.reset_work)) };
+    }
+
+    /// Report `link` to the networking core, logging transitions.
+    fn update_carrier(&self, link: Option<LinkState>) {
+        match link {
+            Some(link) if !self.ndev.carrier_ok() => {
+                let duplex = match link.duplex {
+                    Duplex::Full => "Full",
+                    Duplex::Half => "Half",
+                };
+                pr_info!(
+                    "e1000: Link is Up {} Mbps {} Duplex\n",
+                    link.speed.mbps(),
+                    duplex
+                );
+                self.ndev.carrier_on();
+            }
+            None if self.ndev.carrier_ok() => {
+                pr_info!("e1000: Link is Down\n");
+                self.ndev.carrier_off();
+            }
+            _ => {}
+        }
+    }
+}
+
+struct E1000IrqHandler {
+    adapter: Arc<E1000Adapter>,
+}
+
+impl irq::Handler for E1000IrqHandler {
+    fn handle(&self, _dev: &device::Device<device::Bound>) -> irq::IrqReturn {
+        let mut hw = self.adapter.hw.lock();
+        // Reading ICR acknowledges the causes; zero means the (shared) line
+        // was raised by another device.
+        let cause = hw.e1000_intr();
+        if cause.is_empty() {
+            return irq::IrqReturn::None;
+        }
+        // Stay masked until the NAPI poll has drained the ring.
+        hw.e1000_irq_disable();
+        let link = hw.link_state();
+        drop(hw);
+
+        if cause.link_changed() {
+            self.adapter.update_carrier(link);
+        }
+        self.adapter.napi.schedule();
+        irq::IrqReturn::Handled
+    }
+}
+
+/// How often the statistics registers are folded into the totals, in
+/// milliseconds. At 1 Gb/s the 32-bit octet counters wrap in about 34 s.
+const E1000_STATS_PERIOD_MS: u32 = 2000;
+
+unsafe extern "C" fn e1000_stats_work(work: *mut bindings::work_struct) {
+    // SAFETY: Only `stats_work` is initialised with this function.
+    let adapter = unsafe { E1000Adapter::from_stats_work(work) };
+    // The registers clear on read, so they are added up before they wrap
+    // even if nobody asks for the statistics.
+    lock_irqsave(&adapter.hw).e1000_update_stats();
+    adapter.stats_work.schedule(E1000_STATS_PERIOD_MS);
+}
+
+unsafe extern "C" fn e1000_poll(napi: *mut bindings::napi_struct, budget: c_int) -> c_int {
+    // SAFETY: Only our NAPI context is polled with this callback.
+    let adapter = unsafe { E1000Adapter::from_napi(napi) };
+    let budget = budget as usize;
+
+    adapter.clean_tx();
+    let work_done = adapter.clean_rx(budget);
+    if work_done < budget && adapter.napi.complete_done(work_done) {
+        let mut hw = lock_irqsave(&adapter.hw);
+        {
+            // One poll per interrupt: the traffic it handled picks the next ITR.
+            let mut tx = lock_irqsave(&adapter.tx);
+            let mut rx = lock_irqsave(&adapter.rx);
+            hw.e1000_update_itr(&mut tx, &mut rx);
+        }
+        hw.e1000_irq_enable();
+    }
+    work_done as c_int
+}
+
+unsafe extern "C" fn e1000_open(ndev: *mut bindings::net_device) -> c_int {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    // The last reset failed: try again before starting on the device.
+    if adapter.broken.load(Ordering::Relaxed) {
+        if let Err(e) = adapter.reinit() {
+            return Error::from(e).to_errno();
+        }
+    }
+    adapter.up();
+    0
+}
+
+unsafe extern "C" fn e1000_stop(ndev: *mut bindings::net_device) -> c_int {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    adapter.down();
+    0
+}
+
+unsafe extern "C" fn e1000_start_xmit(
+    skb: *mut bindings::sk_buff,
+    ndev: *mut bindings::net_device,
+) -> bindings::netdev_tx_t {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    // SAFETY: `ndo_start_xmit` passes ownership of the skb to the driver.
+    let mut skb = unsafe { SkBuff::from_raw(skb) };
+
+    let mut offload = TxOffload {
+        vlan: skb.vlan_tag(),
+        ..TxOffload::default()
+    };
+    match skb.tso_prepare() {
+        Ok(Some(gso)) => {
+            offload.tso = TxTso::new(gso.ip_start, gso.tcp_start, gso.hdr_len, gso.mss);
+            if offload.tso.is_none() {
+                pr_warn!("e1000: dropping GSO skb the NIC cannot segment\n");
+                return adapter.tx_drop();
+            }
+        }
+        Ok(None) => {}
+        Err(_) => return adapter.tx_drop(),
+    }
+
+    // The NIC sums from `start` to the end of the frame, which covers any
+    // protocol; only offsets a context descriptor cannot hold are left to
+    // software.
+    if let (None, Some((start, offset))) = (offload.tso, skb.checksum_partial()) {
+        offload.csum = TxChecksum::new(start, offset);
+        if offload.csum.is_none() && skb.checksum_help().is_err() {
+            return adapter.tx_drop();
+        }
+    }
+
+    // The linear part and the page fragments are mapped onto consecutive
+    // descriptors; the core keeps the skb until the NIC has read it. Only
+    // the TX half is locked, so NAPI can receive on another CPU meanwhile.
+    let mut tx = lock_irqsave(&adapter.tx);
+    if let Err(skb) = tx.e1000_transmit_frame(skb, offload) {
+        if tx.e1000_tx_ring_full() {
+            // Should not happen, the queue is stopped ahead of time below.
+            // Hand the skb back for the stack to retry once we wake it.
+            adapter.ndev.stop_queue();
+            let _ = skb.into_raw();
+            return bindings::netdev_tx_NETDEV_TX_BUSY;
+        }
+        // A frame the NIC cannot take at all is dropped.
+        drop(tx);
+        drop(skb);
+        return adapter.tx_drop();
+    }
+    // Stop before the next frame can fail to fit; clean_tx() restarts us.
+    if tx.e1000_tx_ring_full() {
+        adapter.ndev.stop_queue();
+    }
+    bindings::netdev_tx_NETDEV_TX_OK
+}
+
+unsafe extern "C" fn e1000_set_mac_address(
+    ndev: *mut bindings::net_device,
+    addr: *mut c_void,
+) -> c_int {
+    // SAFETY: The networking core only calls us with our registered net_device
+    // and a `struct sockaddr`; `eth_mac_addr` validates and stores the address.
+    let ret = unsafe { bindings::eth_mac_addr(ndev, addr) };
+    if ret != 0 {
+        return ret;
+    }
+
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    let mac = adapter.ndev.mac_address();
+    lock_irqsave(&adapter.hw).set_mac_address(&mac);
+    0
+}
+
+unsafe extern "C" fn e1000_set_rx_mode(ndev: *mut bindings::net_device) {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    let ndev = adapter.ndev;
+    let mut mode = RxMode {
+        promisc: ndev.is_promisc(),
+        allmulti: ndev.is_allmulti(),
+    };
+
+    // Without a list, accept everything that could be on it.
+    let unicast = ndev.unicast_addrs().unwrap_or_else(|_| {
+        mode.promisc = true;
+        KVec::new()
+    });
+    let multicast = ndev.multicast_addrs().unwrap_or_else(|_| {
+        mode.allmulti = true;
+        KVec::new()
+    });
+    lock_irqsave(&adapter.hw).e1000_set_rx_mode(mode, &unicast, &multicast);
+}
+
+unsafe extern "C" fn e1000_get_stats64(
+    ndev: *mut bindings::net_device,
+    stats: *mut bindings::rtnl_link_stats64,
+) {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    let hw = adapter.stats();
+    // SAFETY: `stats` is valid for writes for the duration of the call.
+    let stats = unsafe { &mut *stats };
+
+    stats.rx_packets = hw.gprc;
+    stats.tx_packets = hw.gptc;
+    stats.rx_bytes = hw.gorc;
+    stats.tx_bytes = hw.gotc;
+    stats.multicast = hw.mprc;
+    stats.collisions = hw.colc;
+
+    stats.rx_length_errors = hw.ruc + hw.roc;
+    stats.rx_crc_errors = hw.crcerrs;
+    stats.rx_frame_errors = hw.algnerrc;
+    stats.rx_missed_errors = hw.mpc;
+    stats.rx_errors = hw.rxerrc + hw.crcerrs + hw.algnerrc + hw.ruc + hw.roc + hw.cexterr;
+
+    stats.tx_aborted_errors = hw.ecol;
+    stats.tx_window_errors = hw.latecol;
+    stats.tx_carrier_errors = hw.tncrs;
+    stats.tx_errors = hw.ecol + hw.latecol;
+    stats.tx_dropped = adapter.tx_dropped.load(Ordering::Relaxed);
+}
+
+unsafe extern "C" fn e1000_change_mtu(ndev: *mut bindings::net_device, new_mtu: c_int) -> c_int {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    // The core has already checked `new_mtu` against min_mtu/max_mtu.
+    let config = E1000Config {
+        mtu: new_mtu as usize,
+        ..lock_irqsave(&adapter.hw).config()
+    };
+    if let Err(e) = adapter.reconfigure(config) {
+        return e.to_errno();
+    }
+    adapter.ndev.set_mtu(config.mtu);
+    0
+}
+
+unsafe extern "C" fn e1000_fix_features(
+    ndev: *mut bindings::net_device,
+    features: bindings::netdev_features_t,
+) -> bindings::netdev_features_t {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let ndev = unsafe { NetDev::from_raw(ndev) };
+    // CTRL.VME switches tag insertion and stripping together, so the two
+    // features can only be toggled as a pair. Follow the one the user
+    // changed, or stripping if both were; `ndev->features` is the current set.
+    let wanted = |f| features & f != 0;
+    let changed = |f| ndev.has_features(f) != wanted(f);
+    let on = if changed(NETIF_F_HW_VLAN_CTAG_TX) && !changed(NETIF_F_HW_VLAN_CTAG_RX) {
+        wanted(NETIF_F_HW_VLAN_CTAG_TX)
+    } else {
+        wanted(NETIF_F_HW_VLAN_CTAG_RX)
+    };
+    let pair = NETIF_F_HW_VLAN_CTAG_RX | NETIF_F_HW_VLAN_CTAG_TX;
+    if on {
+        features | pair
+    } else {
+        features & !pair
+    }
+}
+
+unsafe extern "C" fn e1000_set_features(
+    ndev: *mut bindings::net_device,
+    features: bindings::netdev_features_t,
+) -> c_int {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    // `ndev->features` still holds the old set until we return.
+    let changed = |f| adapter.ndev.has_features(f) != (features & f != 0);
+    let mut hw = lock_irqsave(&adapter.hw);
+    if changed(NETIF_F_HW_VLAN_CTAG_RX) {
+        hw.e1000_set_vlan_offload(features & NETIF_F_HW_VLAN_CTAG_RX != 0);
+    }
+    if changed(NETIF_F_HW_VLAN_CTAG_FILTER) {
+        hw.e1000_set_vlan_filtering(features & NETIF_F_HW_VLAN_CTAG_FILTER != 0);
+    }
+    0
+}
+
+unsafe extern "C" fn e1000_vlan_rx_add_vid(
+    ndev: *mut bindings::net_device,
+    _proto: bindings::__be16,
+    vid: u16,
+) -> c_int {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    lock_irqsave(&adapter.hw).e1000_vlan_rx_add_vid(vid);
+    0
+}
+
+unsafe extern "C" fn e1000_vlan_rx_kill_vid(
+    ndev: *mut bindings::net_device,
+    _proto: bindings::__be16,
+    vid: u16,
+) -> c_int {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    lock_irqsave(&adapter.hw).e1000_vlan_rx_kill_vid(vid);
+    0
+}
+
+unsafe extern "C" fn e1000_tx_timeout(ndev: *mut bindings::net_device, _txqueue: c_uint) {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    pr_warn!("e1000: transmit timed out, resetting\n");
+    // Called from the watchdog timer; the reset has to wait for process
+    // context. Already queued is fine.
+    let _ = workqueue::system().enqueue(Arc::from(adapter));
+}
+
+static E1000_NETDEV_OPS: bindings::net_device_ops = bindings::net_device_ops {
+    ndo_open: Some(e1000_open),
+    ndo_stop: Some(e1000_stop),
+    ndo_start_xmit: Some(e1000_start_xmit),
+    ndo_set_mac_address: Some(e1000_set_mac_address),
+    ndo_set_rx_mode: Some(e1000_set_rx_mode),
+    ndo_validate_addr: Some(bindings::eth_validate_addr),
+    ndo_get_stats64: Some(e1000_get_stats64),
+    ndo_change_mtu: Some(e1000_change_mtu),
+    ndo_fix_features: Some(e1000_fix_features),
+    ndo_set_features: Some(e1000_set_features),
+    ndo_vlan_rx_add_vid: Some(e1000_vlan_rx_add_vid),
+    ndo_vlan_rx_kill_vid: Some(e1000_vlan_rx_kill_vid),
+    ndo_tx_timeout: Some(e1000_tx_timeout),
+    // SAFETY: All other callbacks are optional and may be null.
+    ..unsafe { core::mem::zeroed() }
+};
+
+/// Most buffers a received frame can be built into an skb from: the head
+/// plus MAX_SKB_FRAGS page fragments
+const RX_MAX_BUFFERS: usize = 18;
+
+/// Build an skb around the page pool buffers `frame` was received into,
+/// taking them off the ring.
+fn build_rx_skb(frame: &mut RxFrame<'_, Kernfn>) -> Option<SkBuff> {
+    let mut bufs = frame.take_buffers();
+    let (head, len) = bufs.next()?;
+    let mut skb = head.into_skb(len)?;
+    for (buf, len) in bufs {
+        buf.add_to(&mut skb, len);
+    }
+    Some(skb)
+}
+
+#[derive(Clone)]
 struct Kernfn {
     dev: ARef<pci::Device>,
-    // TODO: use linked list.
-    alloc_coherent: KVec<Option<dma::CoherentAllocation<u8>>>,
+}
+
+impl Kernfn {
+    fn new(dev: ARef<pci::Device>) -> Self {
+        Self { dev }
+    }
+}
+
+/// A [`dma::CoherentAllocation`] as seen by the driver core.
+struct CoherentBuffer<T: AsBytes + FromBytes>(dma::CoherentAllocation<T>);
+
+impl<T: AsBytes + FromBytes> e1000::DmaBuffer<T> for CoherentBuffer<T> {
+    fn dma_handle(&self) -> usize {
+        self.0.dma_handle() as usize
+    }
+
+    fn as_slice(&self) -> &[T] {
+        // SAFETY: The core only reads what the NIC has handed back, see the
+        // DD bits, and orders those reads with fences.
+        unsafe { self.0.as_slice(0, self.0.count()) }.unwrap_or_default()
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        // SAFETY: The core only writes what the NIC does not own, and hands
+        // it over with a fence and a tail register write.
+        unsafe { self.0.as_slice_mut(0, self.0.count()) }.unwrap_or_default()
+    }
+}
+
+/// A streaming mapping of transmit data, unmapped on drop.
+struct StreamingMapping {
+    dev: ARef<pci::Device>,
+    handle: bindings::dma_addr_t,
+    len: usize,
+}
+
+impl e1000::DmaMapping for StreamingMapping {
+    fn dma_handle(&self) -> usize {
+        self.handle as usize
+    }
+}
+
+impl Drop for StreamingMapping {
+    fn drop(&mut self) {
+        let dev = self.dev.as_ref().as_raw();
+        // SAFETY: `handle` is a live mapping of `len` bytes on `dev`.
+        unsafe { rust_e1000_dma_unmap_to_device(dev, self.handle, self.len) };
+    }
+}
+
+impl e1000::RxPool for PagePool {
+    type Buffer = PoolFrag;
+
+    fn alloc(&mut self) -> Result<PoolFrag> {
+        PagePool::alloc(self)
+    }
+}
+
+impl e1000::RxBuffer for PoolFrag {
+    fn dma_handle(&self) -> usize {
+        PoolFrag::dma_handle(self) as usize
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        PoolFrag::as_slice(self)
+    }
+
+    fn sync_for_cpu(&mut self, len: usize) {
+        PoolFrag::sync_for_cpu(self, len)
+    }
+
+    fn sync_for_device(&mut self) {
+        PoolFrag::sync_for_device(self)
+    }
+}
+
+// The core holds on to the skb until the NIC has read it, then consumes it.
+impl e1000::TxFrame for SkBuff {
+    fn fragments(&self) -> impl Iterator<Item = &[u8]> + Clone {
+        SkBuff::fragments(self)
+    }
+
+    fn consume(self) {
+        SkBuff::consume(self)
+    }
 }
 
 impl e1000::KernelFunc for Kernfn {
-    const PAGE_SIZE: usize = 1 << 12;
+    type DmaBuffer<T: AsBytes + FromBytes> = CoherentBuffer<T>;
+    type DmaMapping = StreamingMapping;
+    type TxFrame = SkBuff;
+    type RxBuffer = PoolFrag;
+    type RxPool = PagePool;
 
-    fn dma_alloc_coherent(&mut self, pages: usize) -> (usize, usize) {
+    fn dma_alloc_coherent<T: AsBytes + FromBytes>(
+        &mut self,
+        count: usize,
+    ) -> Result<CoherentBuffer<T>> {
         // SAFETY: We transmute the device reference to Bound state. This is safe because
         // the device was properly bound during probe() when enable_device_mem() was called.
         // The pci device is valid for DMA operations.
         let dev_ref = self.dev.as_ref() as *const _ as *const device::Device<device::Bound>;
-        let alloc = unsafe {
-            dma::CoherentAllocation::<u8>::alloc_coherent(
-                &*dev_ref,
-                pages * Self::PAGE_SIZE,
-                GFP_KERNEL,
-            )
-        }
-        .unwrap();
-
-        let vaddr = alloc.start_ptr() as usize;
-        let paddr = alloc.dma_handle() as usize;
-        self.alloc_coherent.push(Some(alloc), GFP_KERNEL);
+        let alloc =
+            unsafe { dma::CoherentAllocation::<T>::alloc_coherent(&*dev_ref, count, GFP_KERNEL) }?;
         pr_info!(
-            "Allocated {} pages, vaddr: {:#x}, paddr: {:#x}\n",
-            pages,
-            vaddr,
-            paddr
+            "Allocated {} bytes, paddr: {:#x}\n",
+            count * core::mem::size_of::<T>(),
+            alloc.dma_handle()
         );
+        Ok(CoherentBuffer(alloc))
+    }
+
+    fn dma_map(&mut self, data: &[u8]) -> Result<StreamingMapping> {
+        let dev = self.dev.as_ref().as_raw();
+        let mut handle = 0;
+        // SAFETY: `data` is part of an skb the core keeps alive, and so
+        // mapped, until the NIC is done with it.
+        to_result(unsafe {
+            rust_e1000_dma_map_to_device(dev, data.as_ptr().cast(), data.len(), &mut handle)
+        })?;
+        Ok(StreamingMapping {
+            dev: self.dev.clone(),
+            handle,
+            len: data.len(),
+        })
+    }
 
-        (vaddr, paddr)
+    fn rx_pool(&mut self, count: usize, size: usize) -> Result<PagePool> {
+        PagePool::new(self.dev.as_ref(), count, size)
     }
 
-    fn dma_free_coherent(&mut self, vaddr: usize, pages: usize) {
-        pr_info!("Deallocating addr: {:#x}\n", vaddr);
-        if let Some(i) = self.alloc_coherent.iter().position(|opt| match opt {
-            None => false,
-            Some(i) => i.start_ptr() as usize == vaddr,
-        }) {
-            // move to last
-            let old_len = self.alloc_coherent.len();
-            self.alloc_coherent.swap(i, old_len - 1);
-            self.alloc_coherent.pop();
-        }
+    fn udelay(&self, us: usize) {
+        // SAFETY: FFI call without preconditions.
+        unsafe { bindings::__udelay(us as _) };
     }
-}
 
-pub(crate) const E1000_DEVICE_ID: u32 = 0x100E;
+    fn usleep_range(&self, min_us: usize, max_us: usize) {
+        // SAFETY: FFI call without preconditions; callers are in process
+        // context.
+        unsafe {
+            bindings::usleep_range_state(min_us as _, max_us as _, bindings::TASK_UNINTERRUPTIBLE)
+        };
+    }
+}
 
 const DEVICE_ID_INTEL_I219: u32 = 0x15fc;
 const DEVICE_ID_INTEL_82540EM: u32 = 0x100e;
 const DEVICE_ID_INTEL_82574L: u32 = 0x10d3;
 
+/// What a supported part can offload
+#[derive(Debug)]
+struct E1000Caps {
+    /// TCP segmentation offload. Left off on the I219, whose TSO is known
+    /// to hang the transmitter.
+    tso: bool,
+    /// Where EERD keeps its bits, for reading the station address
+    eerd: E1000Eerd,
+}
+
 kernel::pci_device_table!(
     PCI_TABLE,
     MODULE_PCI_TABLE,
@@ -129,21 +877,60 @@ kernel::pci_device_table!(
     [
         (
             (pci::DeviceId::from_id(pci::Vendor::INTEL, DEVICE_ID_INTEL_I219)),
-            ()
+            E1000Caps {
+                tso: false,
+                eerd: E1000Eerd::None
+            }
         ),
         (
             (pci::DeviceId::from_id(pci::Vendor::INTEL, DEVICE_ID_INTEL_82540EM)),
-            ()
+            E1000Caps {
+                tso: true,
+                eerd: E1000Eerd::I8254x
+            }
         ),
         (
             (pci::DeviceId::from_id(pci::Vendor::INTEL, DEVICE_ID_INTEL_82574L)),
-            ()
+            E1000Caps {
+                tso: true,
+                eerd: E1000Eerd::I8257x
+            }
         )
     ]
 );
 
+impl Drop for E1000Driver {
+    // Remove, or a failed probe: the fields are freed after this. The rings
+    // quiesce the NIC as they are dropped with the adapter.
+    fn drop(&mut self) {
+        // Closes the interface; after this a queued reset finds it down.
+        self.netdev.unregister();
+        self.adapter.flush_reset();
+    }
+}
+
+impl E1000Driver {
+    /// Request the interrupt, preferring MSI over the legacy INTx line
+    /// (which may be shared)
+    fn request_irq(
+        pdev: &pci::Device<device::Core>,
+        adapter: &Arc<E1000Adapter>,
+    ) -> Result<Pin<KBox<irq::Registration<E1000IrqHandler>>>> {
+        let irq_vecs = pdev.alloc_irq_vectors(1, 1, pci::IrqTypes::all())?;
+        let irq_init = pdev.request_irq(
+            *irq_vecs.start(),
+            irq::Flags::SHARED,
+            c_str!("rust_e1000dev"),
+            E1000IrqHandler {
+                adapter: adapter.clone(),
+            },
+        )?;
+        KBox::pin_init(irq_init, GFP_KERNEL)
+    }
+}
+
 impl pci::Driver for E1000Driver {
-    type IdInfo = ();
+    type IdInfo = E1000Caps;
     const ID_TABLE: pci::IdTable<Self::IdInfo> = &PCI_TABLE;
 
     fn probe(
@@ -156,39 +943,125 @@ impl pci::Driver for E1000Driver {
         pdev.set_master();
 
         // Initialize the bar resource first
-        let bar_init = pdev.iomap_region_sized::<{ Regs::END }>(0, c_str!("rust_e1000dev"));
+        let bar_init =
+            pdev.iomap_region_sized::<{ e1000::E1000_MMIO_SIZE }>(0, c_str!("rust_e1000dev"));
         let bar_box = KBox::pin_init(bar_init, GFP_KERNEL)?;
 
         // Get the register address from the bar
         let lk_bar = bar_box.try_access().ok_or(ENXIO)?;
-        let regs = lk_bar.addr();
+        let mmio = (lk_bar.addr(), lk_bar.maxsize());
+        // The guard holds the RCU read lock, and probe sleeps from here on.
+        drop(lk_bar);
+        // SAFETY: `bar_box` ends up in the driver data, which outlives the device.
+        let regs = unsafe { E1000Regs::new(mmio.0, mmio.1) }?;
 
         // Create the pdev ARef
         let pdev_aref: ARef<pci::Device> = pdev.into();
 
         // Create the kernel functions
-        let kfn = Kernfn {
-            dev: pdev_aref.clone(),
-            alloc_coherent: Vec::new(),
-        };
+        let kfn = Kernfn::new(pdev_aref.clone());
 
         // Initialize the E1000 device
-        let e1000_device = E1000Device::<Kernfn>::new(kfn, regs).unwrap();
+        let config = E1000Config {
+            eerd: id_info.eerd,
+            ..E1000Config::default()
+        };
+        let E1000Device { mut hw, tx, rx } = E1000Device::<Kernfn>::new(kfn, regs, config)?;
+
+        let mut netdev = NetDevice::new_ether(pdev.as_ref(), &E1000_NETDEV_OPS)?;
+        netdev.set_ethtool_ops(&ethtool::E1000_ETHTOOL_OPS);
+        netdev.set_mtu_range(e1000::E1000_MIN_MTU, e1000::E1000_MAX_MTU);
+        netdev.set_unicast_filtering();
+        netdev.add_features(NETIF_F_SG | NETIF_F_HW_CSUM | NETIF_F_RXCSUM);
+        if id_info.tso {
+            netdev.add_features(NETIF_F_TSO);
+        }
+        netdev.add_features(
+            NETIF_F_HW_VLAN_CTAG_TX | NETIF_F_HW_VLAN_CTAG_RX | NETIF_F_HW_VLAN_CTAG_FILTER,
+        );
+        hw.e1000_set_vlan_offload(true);
+        hw.e1000_set_vlan_filtering(true);
+        let mac = hw.mac_address();
+        if is_valid_ether_addr(&mac) {
+            netdev.set_perm_mac_address(&mac);
+        } else {
+            pr_warn!(
+                "e1000: invalid MAC address {:02x?}, using a random one\n",
+                mac
+            );
+            netdev.set_random_mac_address();
+            hw.set_mac_address(&netdev.handle().mac_address());
+        }
+
+        let hw_reset = hw.reset_handle();
+        let adapter = Arc::pin_init(
+            pin_init!(E1000Adapter {
+                ndev: netdev.handle(),
+                pdev: pdev_aref.clone(),
+                mmio,
+                hw <- new_spinlock!(hw, "E1000Adapter::hw"),
+                tx <- new_spinlock!(tx, "E1000Adapter::tx"),
+                rx <- new_spinlock!(rx, "E1000Adapter::rx"),
+                hw_reset,
+                napi: Napi::new(),
+                active: AtomicBool::new(false),
+                broken: AtomicBool::new(false),
+                tx_dropped: AtomicU64::new(0),
+                stats_work: DelayedWork::new(),
+                reset_work <- kernel::new_work!("E1000Adapter::reset_work"),
+            }),
+            GFP_KERNEL,
+        )?;
+        // SAFETY: The adapter is pinned in its Arc, which the driver data keeps
+        // alive until after the net_device (and with it the NAPI context) is freed.
+        unsafe { adapter.napi.add(netdev.handle(), e1000_poll) };
+        // SAFETY: As above; `down` cancels the work before the adapter can go.
+        unsafe { adapter.stats_work.init(e1000_stats_work) };
+
+        // The callbacks borrow the adapter through this pointer; the driver
+        // data keeps the Arc alive until the net_device is unregistered.
+        netdev.set_driver_data(Arc::as_ptr(&adapter).cast());
+        // No link until the device is opened.
+        netdev.handle().carrier_off();
+
+        // Before the net_device is registered: once it is, it can be opened
+        // and the interrupts enabled.
+        let irq = match Self::request_irq(pdev, &adapter) {
+            Ok(irq) => irq,
+            Err(e) => {
+                // Before the adapter, which holds its NAPI context.
+                drop(netdev);
+                return Err(e);
+            }
+        };
 
         // Create the driver data structure
-        let drvdata = Self {
+        let mut drvdata = Self {
             _pdev: pdev_aref,
+            _irq: irq,
+            netdev,
+            adapter,
             _bar: bar_box,
-            inner: e1000_device,
         };
-
-        let wq = workqueue::system();
-        wq.try_spawn(GFP_KERNEL, || {
-            unsafe { bindings::msleep(5000) };
-            pr_info!("e1000 background");
-        })
-        .unwrap();
+        // On failure the driver data is torn down as on remove.
+        drvdata.netdev.register()?;
+        pr_info!("e1000: registered net_device\n");
 
         Ok(drvdata)
     }
+
+    fn shutdown(_pdev: &pci::Device<device::Core>, this: Pin<&Self>) {
+        let ndev = this.netdev.handle();
+        // No more transmits or watchdog timeouts.
+        ndev.detach();
+        this.adapter.flush_reset();
+        {
+            let _rtnl = RtnlGuard::lock();
+            if ndev.is_running() {
+                this.adapter.down();
+            }
+        }
+        // Leave nothing running into the next kernel or across power off.
+        this.adapter.hw_reset.e1000_shutdown();
+    }
 }
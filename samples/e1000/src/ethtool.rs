@@ -0,0 +1,222 @@
+//! `ethtool` callbacks.
+
+use core::ffi::{c_int, c_void};
+use kernel::{bindings, prelude::*};
+
+use crate::E1000Adapter;
+use crate::e1000::{E1000_MAX_RING_SIZE, E1000_STATS_LEN, E1000Coalesce, E1000Config};
+use crate::linux::sync::lock_irqsave;
+
+unsafe extern "C" fn e1000_get_sset_count(_ndev: *mut bindings::net_device, sset: c_int) -> c_int {
+    match sset as u32 {
+        bindings::ethtool_stringset_ETH_SS_STATS => E1000_STATS_LEN as c_int,
+        _ => EOPNOTSUPP.to_errno(),
+    }
+}
+
+unsafe extern "C" fn e1000_get_strings(
+    _ndev: *mut bindings::net_device,
+    stringset: u32,
+    data: *mut u8,
+) {
+    if stringset != bindings::ethtool_stringset_ETH_SS_STATS {
+        return;
+    }
+    let len = bindings::ETH_GSTRING_LEN as usize;
+    // SAFETY: The core sized `data` with `e1000_get_sset_count`: one
+    // `ETH_GSTRING_LEN` slot per counter.
+    let data = unsafe { core::slice::from_raw_parts_mut(data, E1000_STATS_LEN * len) };
+    let names = crate::e1000::E1000Stats::default().ethtool_stats();
+    for ((name, _), slot) in names.iter().zip(data.chunks_exact_mut(len)) {
+        slot.fill(0);
+        slot[..name.len()].copy_from_slice(name.as_bytes());
+    }
+}
+
+unsafe extern "C" fn e1000_get_ethtool_stats(
+    ndev: *mut bindings::net_device,
+    _stats: *mut bindings::ethtool_stats,
+    data: *mut u64,
+) {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    let stats = adapter.stats();
+    // SAFETY: The core sized `data` with `e1000_get_sset_count`.
+    let data = unsafe { core::slice::from_raw_parts_mut(data, E1000_STATS_LEN) };
+    for ((_, value), out) in stats.ethtool_stats().iter().zip(data) {
+        *out = *value;
+    }
+}
+
+unsafe extern "C" fn e1000_get_ringparam(
+    ndev: *mut bindings::net_device,
+    ring: *mut bindings::ethtool_ringparam,
+    _kernel_ring: *mut bindings::kernel_ethtool_ringparam,
+    _extack: *mut bindings::netlink_ext_ack,
+) {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    let config = lock_irqsave(&adapter.hw).config();
+    // SAFETY: `ring` is valid for writes for the duration of the call.
+    let ring = unsafe { &mut *ring };
+
+    ring.rx_max_pending = E1000_MAX_RING_SIZE as u32;
+    ring.tx_max_pending = E1000_MAX_RING_SIZE as u32;
+    ring.rx_pending = config.rx_ring_size as u32;
+    ring.tx_pending = config.tx_ring_size as u32;
+}
+
+unsafe extern "C" fn e1000_set_ringparam(
+    ndev: *mut bindings::net_device,
+    ring: *mut bindings::ethtool_ringparam,
+    _kernel_ring: *mut bindings::kernel_ethtool_ringparam,
+    _extack: *mut bindings::netlink_ext_ack,
+) -> c_int {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    // SAFETY: `ring` is valid for reads for the duration of the call.
+    let ring = unsafe { &*ring };
+    if ring.rx_mini_pending != 0 || ring.rx_jumbo_pending != 0 {
+        return EINVAL.to_errno();
+    }
+
+    let old = lock_irqsave(&adapter.hw).config();
+    let config = E1000Config {
+        tx_ring_size: E1000Config::ring_size_for(ring.tx_pending as usize),
+        rx_ring_size: E1000Config::ring_size_for(ring.rx_pending as usize),
+        ..old
+    };
+    if config == old {
+        return 0;
+    }
+    match adapter.reconfigure(config) {
+        Ok(()) => 0,
+        Err(e) => e.to_errno(),
+    }
+}
+
+// `ETHTOOL_COALESCE_*` are `BIT()` macros, which bindgen cannot evaluate.
+const ETHTOOL_COALESCE_RX_USECS: u32 = 1 << 0;
+const ETHTOOL_COALESCE_RX_USECS_IRQ: u32 = 1 << 2;
+const ETHTOOL_COALESCE_TX_USECS: u32 = 1 << 4;
+const ETHTOOL_COALESCE_TX_USECS_IRQ: u32 = 1 << 6;
+const ETHTOOL_COALESCE_USE_ADAPTIVE_RX: u32 = 1 << 9;
+const ETHTOOL_COALESCE_USE_ADAPTIVE_TX: u32 = 1 << 10;
+const ETHTOOL_COALESCE_RX_USECS_HIGH: u32 = 1 << 17;
+
+// ethtool has no field for an interrupt rate. The ITR limit is shown as the
+// shortest gap between interrupts in rx-usecs-high, the delay timers as
+// rx/tx-usecs (per packet) and rx/tx-usecs-irq (absolute). ITR is shared by
+// both directions, so adaptive-rx and adaptive-tx are one setting.
+unsafe extern "C" fn e1000_get_coalesce(
+    ndev: *mut bindings::net_device,
+    ec: *mut bindings::ethtool_coalesce,
+    _kernel_coal: *mut bindings::kernel_ethtool_coalesce,
+    _extack: *mut bindings::netlink_ext_ack,
+) -> c_int {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    let coalesce = lock_irqsave(&adapter.hw).coalesce();
+    // SAFETY: `ec` is valid for writes for the duration of the call.
+    let ec = unsafe { &mut *ec };
+
+    ec.rx_coalesce_usecs = coalesce.rx_delay_us;
+    ec.rx_coalesce_usecs_irq = coalesce.rx_abs_delay_us;
+    ec.tx_coalesce_usecs = coalesce.tx_delay_us;
+    ec.tx_coalesce_usecs_irq = coalesce.tx_abs_delay_us;
+    ec.rx_coalesce_usecs_high = match coalesce.itr {
+        0 => 0,
+        itr => 1_000_000 / itr,
+    };
+    ec.use_adaptive_rx_coalesce = coalesce.adaptive as u32;
+    ec.use_adaptive_tx_coalesce = coalesce.adaptive as u32;
+    0
+}
+
+unsafe extern "C" fn e1000_set_coalesce(
+    ndev: *mut bindings::net_device,
+    ec: *mut bindings::ethtool_coalesce,
+    _kernel_coal: *mut bindings::kernel_ethtool_coalesce,
+    _extack: *mut bindings::netlink_ext_ack,
+) -> c_int {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    // SAFETY: `ec` is valid for reads for the duration of the call.
+    let ec = unsafe { &*ec };
+
+    let coalesce = E1000Coalesce {
+        itr: match ec.rx_coalesce_usecs_high {
+            0 => 0,
+            usecs => 1_000_000 / usecs,
+        },
+        adaptive: ec.use_adaptive_rx_coalesce != 0 || ec.use_adaptive_tx_coalesce != 0,
+        rx_delay_us: ec.rx_coalesce_usecs,
+        rx_abs_delay_us: ec.rx_coalesce_usecs_irq,
+        tx_delay_us: ec.tx_coalesce_usecs,
+        tx_abs_delay_us: ec.tx_coalesce_usecs_irq,
+    };
+    let mut hw = lock_irqsave(&adapter.hw);
+    let mut tx = lock_irqsave(&adapter.tx);
+    match hw.set_coalesce(&mut tx, coalesce) {
+        Ok(()) => 0,
+        Err(e) => e.to_errno(),
+    }
+}
+
+// rx-copybreak: frames up to this many bytes are copied out of the RX ring
+// rather than handed up in the buffer they were received into.
+unsafe extern "C" fn e1000_get_tunable(
+    ndev: *mut bindings::net_device,
+    tuna: *const bindings::ethtool_tunable,
+    data: *mut c_void,
+) -> c_int {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    // SAFETY: `tuna` is valid for reads for the duration of the call.
+    if unsafe { (*tuna).id } != bindings::tunable_id_ETHTOOL_RX_COPYBREAK {
+        return EINVAL.to_errno();
+    }
+    let copybreak = lock_irqsave(&adapter.rx).rx_copybreak();
+    // SAFETY: The core sized `data` for the u32 of ETHTOOL_RX_COPYBREAK.
+    unsafe { data.cast::<u32>().write(copybreak as u32) };
+    0
+}
+
+unsafe extern "C" fn e1000_set_tunable(
+    ndev: *mut bindings::net_device,
+    tuna: *const bindings::ethtool_tunable,
+    data: *const c_void,
+) -> c_int {
+    // SAFETY: The networking core only calls us with our registered net_device.
+    let adapter = unsafe { E1000Adapter::from_netdev(ndev) };
+    // SAFETY: `tuna` is valid for reads for the duration of the call.
+    if unsafe { (*tuna).id } != bindings::tunable_id_ETHTOOL_RX_COPYBREAK {
+        return EINVAL.to_errno();
+    }
+    // SAFETY: The core sized `data` for the u32 of ETHTOOL_RX_COPYBREAK.
+    let copybreak = unsafe { data.cast::<u32>().read() };
+    lock_irqsave(&adapter.rx).set_rx_copybreak(copybreak as usize);
+    0
+}
+
+pub(crate) static E1000_ETHTOOL_OPS: bindings::ethtool_ops = bindings::ethtool_ops {
+    supported_coalesce_params: ETHTOOL_COALESCE_RX_USECS
+        | ETHTOOL_COALESCE_RX_USECS_IRQ
+        | ETHTOOL_COALESCE_TX_USECS
+        | ETHTOOL_COALESCE_TX_USECS_IRQ
+        | ETHTOOL_COALESCE_USE_ADAPTIVE_RX
+        | ETHTOOL_COALESCE_USE_ADAPTIVE_TX
+        | ETHTOOL_COALESCE_RX_USECS_HIGH,
+    get_link: Some(bindings::ethtool_op_get_link),
+    get_sset_count: Some(e1000_get_sset_count),
+    get_strings: Some(e1000_get_strings),
+    get_ethtool_stats: Some(e1000_get_ethtool_stats),
+    get_ringparam: Some(e1000_get_ringparam),
+    set_ringparam: Some(e1000_set_ringparam),
+    get_coalesce: Some(e1000_get_coalesce),
+    set_coalesce: Some(e1000_set_coalesce),
+    get_tunable: Some(e1000_get_tunable),
+    set_tunable: Some(e1000_set_tunable),
+    // SAFETY: All other callbacks are optional and may be null.
+    ..unsafe { core::mem::zeroed() }
+};
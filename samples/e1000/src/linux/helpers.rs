@@ -0,0 +1,133 @@
+//! Bindings for the C wrappers in `helpers.c`.
+
+use kernel::bindings;
+
+unsafe extern "C" {
+    pub(crate) fn rust_e1000_set_netdev_dev(
+        ndev: *mut bindings::net_device,
+        dev: *mut bindings::device,
+    );
+    pub(crate) fn rust_e1000_netdev_alloc_skb_ip_align(
+        ndev: *mut bindings::net_device,
+        length: u32,
+    ) -> *mut bindings::sk_buff;
+    pub(crate) fn rust_e1000_skb_eth_type_trans(
+        skb: *mut bindings::sk_buff,
+        ndev: *mut bindings::net_device,
+    );
+    pub(crate) fn rust_e1000_skb_checksum_unnecessary(skb: *mut bindings::sk_buff);
+    pub(crate) fn rust_e1000_skb_vlan_tag(skb: *const bindings::sk_buff) -> i32;
+    pub(crate) fn rust_e1000_skb_put_vlan_tag(skb: *mut bindings::sk_buff, tci: u16);
+    pub(crate) fn rust_e1000_skb_csum_partial(
+        skb: *const bindings::sk_buff,
+        start: *mut u32,
+        offset: *mut u32,
+    ) -> bool;
+    pub(crate) fn rust_e1000_skb_tso_prep(
+        skb: *mut bindings::sk_buff,
+        ip_start: *mut u32,
+        tcp_start: *mut u32,
+        hdr_len: *mut u32,
+        mss: *mut u32,
+    ) -> i32;
+    pub(crate) fn rust_e1000_skb_nr_frags(skb: *const bindings::sk_buff) -> u32;
+    pub(crate) fn rust_e1000_skb_frag_address(
+        skb: *const bindings::sk_buff,
+        i: u32,
+    ) -> *const core::ffi::c_void;
+    pub(crate) fn rust_e1000_skb_frag_size(skb: *const bindings::sk_buff, i: u32) -> u32;
+    pub(crate) fn rust_e1000_dma_map_to_device(
+        dev: *mut bindings::device,
+        addr: *const core::ffi::c_void,
+        len: usize,
+        handle: *mut bindings::dma_addr_t,
+    ) -> i32;
+    pub(crate) fn rust_e1000_dma_unmap_to_device(
+        dev: *mut bindings::device,
+        handle: bindings::dma_addr_t,
+        len: usize,
+    );
+    pub(crate) fn rust_e1000_page_pool_create(
+        dev: *mut bindings::device,
+        pool_size: u32,
+        buf_size: u32,
+        pool: *mut *mut bindings::page_pool,
+    ) -> i32;
+    pub(crate) fn rust_e1000_page_pool_destroy(pool: *mut bindings::page_pool);
+    pub(crate) fn rust_e1000_rx_frag_alloc(
+        pool: *mut bindings::page_pool,
+        buf_size: u32,
+        page: *mut *mut bindings::page,
+        offset: *mut u32,
+    ) -> i32;
+    pub(crate) fn rust_e1000_rx_frag_put(pool: *mut bindings::page_pool, page: *mut bindings::page);
+    pub(crate) fn rust_e1000_rx_frag_data(
+        page: *mut bindings::page,
+        offset: u32,
+    ) -> *mut core::ffi::c_void;
+    pub(crate) fn rust_e1000_rx_frag_dma(
+        page: *mut bindings::page,
+        offset: u32,
+    ) -> bindings::dma_addr_t;
+    pub(crate) fn rust_e1000_rx_frag_sync_for_cpu(
+        pool: *mut bindings::page_pool,
+        page: *mut bindings::page,
+        offset: u32,
+        len: u32,
+    );
+    pub(crate) fn rust_e1000_rx_frag_sync_for_device(
+        pool: *mut bindings::page_pool,
+        page: *mut bindings::page,
+        offset: u32,
+        len: u32,
+    );
+    pub(crate) fn rust_e1000_rx_build_skb(
+        page: *mut bindings::page,
+        offset: u32,
+        buf_size: u32,
+        len: u32,
+    ) -> *mut bindings::sk_buff;
+    pub(crate) fn rust_e1000_skb_add_rx_frag(
+        skb: *mut bindings::sk_buff,
+        page: *mut bindings::page,
+        offset: u32,
+        buf_size: u32,
+        len: u32,
+    );
+    pub(crate) fn rust_e1000_dev_kfree_skb_any(skb: *mut bindings::sk_buff);
+    pub(crate) fn rust_e1000_dev_consume_skb_any(skb: *mut bindings::sk_buff);
+    pub(crate) fn rust_e1000_local_irq_save() -> core::ffi::c_ulong;
+    pub(crate) fn rust_e1000_local_irq_restore(flags: core::ffi::c_ulong);
+    pub(crate) fn rust_e1000_netif_napi_add(
+        ndev: *mut bindings::net_device,
+        napi: *mut bindings::napi_struct,
+        poll: Option<super::netdev::NapiPoll>,
+    );
+    pub(crate) fn rust_e1000_eth_hw_addr_random(ndev: *mut bindings::net_device);
+    pub(crate) fn rust_e1000_netdev_set_unicast_flt(ndev: *mut bindings::net_device);
+    pub(crate) fn rust_e1000_netdev_uc_addrs(
+        ndev: *mut bindings::net_device,
+        addrs: *mut u8,
+        max: u32,
+    ) -> u32;
+    pub(crate) fn rust_e1000_netdev_mc_addrs(
+        ndev: *mut bindings::net_device,
+        addrs: *mut u8,
+        max: u32,
+    ) -> u32;
+    pub(crate) fn rust_e1000_netif_carrier_ok(ndev: *const bindings::net_device) -> bool;
+    pub(crate) fn rust_e1000_netif_running(ndev: *const bindings::net_device) -> bool;
+    pub(crate) fn rust_e1000_netif_tx_disable(ndev: *mut bindings::net_device);
+    pub(crate) fn rust_e1000_netif_tx_wake_all_queues(ndev: *mut bindings::net_device);
+    pub(crate) fn rust_e1000_netif_stop_queue(ndev: *mut bindings::net_device);
+    pub(crate) fn rust_e1000_netif_wake_queue(ndev: *mut bindings::net_device);
+    pub(crate) fn rust_e1000_netif_queue_stopped(ndev: *const bindings::net_device) -> bool;
+    pub(crate) fn rust_e1000_init_delayed_work(
+        dwork: *mut bindings::delayed_work,
+        func: Option<super::workqueue::WorkFn>,
+    );
+    pub(crate) fn rust_e1000_schedule_delayed_work(
+        dwork: *mut bindings::delayed_work,
+        msecs: u32,
+    ) -> bool;
+}
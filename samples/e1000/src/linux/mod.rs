@@ -1,4 +1,7 @@
-pub mod volatile;
-
 #[macro_use]
 pub mod print;
+
+pub(crate) mod helpers;
+pub mod netdev;
+pub mod sync;
+pub mod workqueue;
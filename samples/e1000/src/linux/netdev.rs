@@ -0,0 +1,700 @@
+//! Minimal `net_device` and `sk_buff` wrappers.
+//!
+//! The kernel crate has no netdev abstraction yet, so the driver talks to the
+//! C API through `bindings` and `helpers.c`. The unsafe parts are kept here.
+
+use super::helpers::*;
+use core::ffi::{c_int, c_void};
+use core::ptr::NonNull;
+use core::slice::{from_raw_parts, from_raw_parts_mut};
+use kernel::{bindings, device, error::to_result, prelude::*, types::Opaque};
+
+/// Length of an ethernet hardware address.
+pub const ETH_ALEN: usize = 6;
+
+/// Whether `addr` can be used as a station address (not multicast, not all zeroes).
+pub fn is_valid_ether_addr(addr: &[u8; ETH_ALEN]) -> bool {
+    addr[0] & 0x01 == 0 && addr.iter().any(|&b| b != 0)
+}
+
+/// An owned ethernet `net_device`.
+///
+/// It is unregistered (if it was registered) and freed on drop.
+pub struct NetDevice {
+    dev: NetDev,
+    registered: bool,
+}
+
+impl NetDevice {
+    /// Allocate a single queue ethernet device whose sysfs parent is `parent`.
+    pub fn new_ether(
+        parent: &device::Device,
+        ops: &'static bindings::net_device_ops,
+    ) -> Result<Self> {
+        // SAFETY: FFI call without preconditions.
+        let ptr = unsafe { bindings::alloc_etherdev_mqs(0, 1, 1) };
+        let ptr = NonNull::new(ptr).ok_or(ENOMEM)?;
+        // SAFETY: `ptr` is a freshly allocated net_device that is not registered yet.
+        unsafe {
+            (*ptr.as_ptr()).netdev_ops = ops;
+            rust_e1000_set_netdev_dev(ptr.as_ptr(), parent.as_raw());
+        }
+        Ok(Self {
+            dev: NetDev(ptr),
+            registered: false,
+        })
+    }
+
+    /// Get a copyable handle to the device, for use in callbacks.
+    pub fn handle(&self) -> NetDev {
+        self.dev
+    }
+
+    /// Set the callbacks behind `ethtool`.
+    pub fn set_ethtool_ops(&mut self, ops: &'static bindings::ethtool_ops) {
+        // SAFETY: The device is valid and we have exclusive access to it.
+        unsafe { (*self.dev.as_raw()).ethtool_ops = ops };
+    }
+
+    /// Tell the core that the device filters secondary unicast addresses
+    /// itself, rather than needing promiscuous mode for them.
+    pub fn set_unicast_filtering(&mut self) {
+        // SAFETY: The device is valid and we have exclusive access to it.
+        unsafe { rust_e1000_netdev_set_unicast_flt(self.dev.as_raw()) };
+    }
+
+    /// Set the range of MTUs the core accepts before calling `ndo_change_mtu`.
+    pub fn set_mtu_range(&mut self, min: usize, max: usize) {
+        // SAFETY: The device is valid and we have exclusive access to it.
+        unsafe {
+            (*self.dev.as_raw()).min_mtu = min as u32;
+            (*self.dev.as_raw()).max_mtu = max as u32;
+        }
+    }
+
+    /// Enable `features` and let the user toggle them with `ethtool -K`.
+    pub fn add_features(&mut self, features: u64) {
+        // SAFETY: The device is valid and we have exclusive access to it.
+        unsafe {
+            (*self.dev.as_raw()).features |= features;
+            (*self.dev.as_raw()).hw_features |= features;
+        }
+    }
+
+    /// Store the pointer later returned by [`NetDev::driver_data`].
+    pub fn set_driver_data(&mut self, data: *const c_void) {
+        // SAFETY: The device is valid and we have exclusive access to it.
+        unsafe { (*self.dev.as_raw()).ml_priv = data as *mut c_void };
+    }
+
+    /// Set both the current and the permanent hardware address.
+    pub fn set_perm_mac_address(&mut self, addr: &[u8; ETH_ALEN]) {
+        self.dev.set_mac_address(addr);
+        // SAFETY: The device is valid and `perm_addr` is larger than `ETH_ALEN`.
+        unsafe { (*self.dev.as_raw()).perm_addr[..ETH_ALEN].copy_from_slice(addr) };
+    }
+
+    /// Use a random, locally administered hardware address.
+    pub fn set_random_mac_address(&mut self) {
+        // SAFETY: The device is valid and not registered yet.
+        unsafe { rust_e1000_eth_hw_addr_random(self.dev.as_raw()) };
+    }
+
+    /// Register the device with the networking core.
+    pub fn register(&mut self) -> Result {
+        // SAFETY: The device is valid and not registered yet.
+        to_result(unsafe { bindings::register_netdev(self.dev.as_raw()) })?;
+        self.registered = true;
+        Ok(())
+    }
+
+    /// Unregister the device, closing it first if it is up. No callback
+    /// runs once this returns. Does nothing if it is not registered.
+    pub fn unregister(&mut self) {
+        if self.registered {
+            // SAFETY: The device is valid and registered.
+            unsafe { bindings::unregister_netdev(self.dev.as_raw()) };
+            self.registered = false;
+        }
+    }
+}
+
+impl Drop for NetDevice {
+    fn drop(&mut self) {
+        self.unregister();
+        // SAFETY: The device is valid and no longer registered.
+        unsafe { bindings::free_netdev(self.dev.as_raw()) };
+    }
+}
+
+/// Scatter-gather I/O (`NETIF_F_SG`).
+pub const NETIF_F_SG: u64 = 1 << bindings::NETIF_F_SG_BIT;
+/// Checksum offload for any protocol (`NETIF_F_HW_CSUM`).
+pub const NETIF_F_HW_CSUM: u64 = 1 << bindings::NETIF_F_HW_CSUM_BIT;
+/// TCP segmentation offload over IPv4 (`NETIF_F_TSO`).
+pub const NETIF_F_TSO: u64 = 1 << bindings::NETIF_F_TSO_BIT;
+/// Receive checksum offload (`NETIF_F_RXCSUM`).
+pub const NETIF_F_RXCSUM: u64 = 1 << bindings::NETIF_F_RXCSUM_BIT;
+/// 802.1Q tag insertion on transmit (`NETIF_F_HW_VLAN_CTAG_TX`).
+pub const NETIF_F_HW_VLAN_CTAG_TX: u64 = 1 << bindings::NETIF_F_HW_VLAN_CTAG_TX_BIT;
+/// 802.1Q tag stripping on receive (`NETIF_F_HW_VLAN_CTAG_RX`).
+pub const NETIF_F_HW_VLAN_CTAG_RX: u64 = 1 << bindings::NETIF_F_HW_VLAN_CTAG_RX_BIT;
+/// Receive filtering by VLAN id (`NETIF_F_HW_VLAN_CTAG_FILTER`).
+pub const NETIF_F_HW_VLAN_CTAG_FILTER: u64 = 1 << bindings::NETIF_F_HW_VLAN_CTAG_FILTER_BIT;
+
+/// A borrowed handle to a [`NetDevice`].
+///
+/// The handle is only valid while the owning [`NetDevice`] is alive.
+#[derive(Clone, Copy)]
+pub struct NetDev(NonNull<bindings::net_device>);
+
+// SAFETY: The C net_device API may be used from any thread.
+unsafe impl Send for NetDev {}
+// SAFETY: The C functions used through `&NetDev` do their own locking.
+unsafe impl Sync for NetDev {}
+
+impl NetDev {
+    /// Create a handle from a raw pointer.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to a valid `net_device` that outlives every use of the handle.
+    pub unsafe fn from_raw(ptr: *mut bindings::net_device) -> Self {
+        // SAFETY: The caller guarantees that `ptr` is valid, hence not null.
+        Self(unsafe { NonNull::new_unchecked(ptr) })
+    }
+
+    /// Get the raw `net_device` pointer.
+    pub fn as_raw(self) -> *mut bindings::net_device {
+        self.0.as_ptr()
+    }
+
+    /// Get the pointer stored with [`NetDevice::set_driver_data`].
+    pub fn driver_data(self) -> *const c_void {
+        // SAFETY: The device is valid by the type invariant.
+        unsafe { (*self.as_raw()).ml_priv }
+    }
+
+    /// Set the current hardware address.
+    pub fn set_mac_address(self, addr: &[u8; ETH_ALEN]) {
+        // SAFETY: The device is valid and `addr` holds `ETH_ALEN` bytes.
+        unsafe { bindings::dev_addr_mod(self.as_raw(), 0, addr.as_ptr().cast(), ETH_ALEN) };
+    }
+
+    /// The current hardware address.
+    pub fn mac_address(self) -> [u8; ETH_ALEN] {
+        let mut addr = [0u8; ETH_ALEN];
+        // SAFETY: The device is valid and `dev_addr` holds at least `ETH_ALEN` bytes.
+        unsafe {
+            let dev_addr = (*self.as_raw()).dev_addr;
+            addr.copy_from_slice(from_raw_parts(dev_addr, ETH_ALEN));
+        }
+        addr
+    }
+
+    /// Set the MTU, from `ndo_change_mtu` once the hardware accepted it.
+    pub fn set_mtu(self, mtu: usize) {
+        // SAFETY: The device is valid; `ndo_change_mtu` runs under the RTNL
+        // lock, which serializes writers of `mtu`.
+        unsafe { (*self.as_raw()).mtu = mtu as u32 };
+    }
+
+    /// Whether all of `features` are currently enabled.
+    pub fn has_features(self, features: u64) -> bool {
+        // SAFETY: The device is valid by the type invariant; a racy read
+        // only delays when a feature change takes effect.
+        unsafe { (*self.as_raw()).features & features == features }
+    }
+
+    /// Whether the interface is in promiscuous mode (`IFF_PROMISC`).
+    pub fn is_promisc(self) -> bool {
+        // SAFETY: The device is valid by the type invariant.
+        unsafe { (*self.as_raw()).flags & bindings::net_device_flags_IFF_PROMISC != 0 }
+    }
+
+    /// Whether the interface receives all multicasts (`IFF_ALLMULTI`).
+    pub fn is_allmulti(self) -> bool {
+        // SAFETY: The device is valid by the type invariant.
+        unsafe { (*self.as_raw()).flags & bindings::net_device_flags_IFF_ALLMULTI != 0 }
+    }
+
+    /// The secondary unicast addresses. Only call from `ndo_set_rx_mode`,
+    /// which runs with the address lists locked, in atomic context.
+    pub fn unicast_addrs(self) -> Result<KVec<[u8; ETH_ALEN]>> {
+        self.hw_addrs(rust_e1000_netdev_uc_addrs)
+    }
+
+    /// The multicast addresses; see [`Self::unicast_addrs`].
+    pub fn multicast_addrs(self) -> Result<KVec<[u8; ETH_ALEN]>> {
+        self.hw_addrs(rust_e1000_netdev_mc_addrs)
+    }
+
+    fn hw_addrs(
+        self,
+        copy: unsafe extern "C" fn(*mut bindings::net_device, *mut u8, u32) -> u32,
+    ) -> Result<KVec<[u8; ETH_ALEN]>> {
+        // SAFETY: The device is valid; with `max` 0 nothing is written.
+        let count = unsafe { copy(self.as_raw(), core::ptr::null_mut(), 0) };
+        let mut addrs = KVec::new();
+        addrs.extend_with(count as usize, [0; ETH_ALEN], GFP_ATOMIC)?;
+        // SAFETY: `addrs` has room for `count` addresses; the list cannot
+        // change while the caller holds the address list lock.
+        unsafe { copy(self.as_raw(), addrs.as_mut_ptr().cast(), count) };
+        Ok(addrs)
+    }
+
+    /// Whether the interface is up (opened).
+    pub fn is_running(self) -> bool {
+        // SAFETY: The device is valid by the type invariant.
+        unsafe { rust_e1000_netif_running(self.as_raw()) }
+    }
+
+    /// Mark the device as gone: the queues stop and the core, including
+    /// the TX watchdog, leaves it alone.
+    pub fn detach(self) {
+        // SAFETY: The device is valid by the type invariant.
+        unsafe { bindings::netif_device_detach(self.as_raw()) };
+    }
+
+    /// Stop the transmit queues, waiting for a running `ndo_start_xmit` to return.
+    pub fn tx_disable(self) {
+        // SAFETY: The device is valid by the type invariant.
+        unsafe { rust_e1000_netif_tx_disable(self.as_raw()) };
+    }
+
+    /// Stop the transmit queue, e.g. because the ring is full.
+    pub fn stop_queue(self) {
+        // SAFETY: The device is valid by the type invariant.
+        unsafe { rust_e1000_netif_stop_queue(self.as_raw()) };
+    }
+
+    /// Restart a transmit queue stopped with [`Self::stop_queue`].
+    pub fn wake_queue(self) {
+        // SAFETY: The device is valid by the type invariant.
+        unsafe { rust_e1000_netif_wake_queue(self.as_raw()) };
+    }
+
+    /// Whether the transmit queue is stopped.
+    pub fn is_queue_stopped(self) -> bool {
+        // SAFETY: The device is valid by the type invariant.
+        unsafe { rust_e1000_netif_queue_stopped(self.as_raw()) }
+    }
+
+    /// Restart the transmit queues.
+    pub fn tx_wake_all(self) {
+        // SAFETY: The device is valid by the type invariant.
+        unsafe { rust_e1000_netif_tx_wake_all_queues(self.as_raw()) };
+    }
+
+    /// Whether the link is reported as up.
+    pub fn carrier_ok(self) -> bool {
+        // SAFETY: The device is valid by the type invariant.
+        unsafe { rust_e1000_netif_carrier_ok(self.as_raw()) }
+    }
+
+    /// Report the link as up; may be called from any context.
+    pub fn carrier_on(self) {
+        // SAFETY: The device is valid by the type invariant.
+        unsafe { bindings::netif_carrier_on(self.as_raw()) };
+    }
+
+    /// Report the link as down; may be called from any context.
+    pub fn carrier_off(self) {
+        // SAFETY: The device is valid by the type invariant.
+        unsafe { bindings::netif_carrier_off(self.as_raw()) };
+    }
+
+    /// Allocate an skb for a received frame of `len` bytes.
+    pub fn alloc_rx_skb(self, len: usize) -> Option<SkBuff> {
+        // SAFETY: The device is valid by the type invariant.
+        let skb = unsafe { rust_e1000_netdev_alloc_skb_ip_align(self.as_raw(), len as u32) };
+        NonNull::new(skb).map(SkBuff)
+    }
+
+    /// Hand a received frame to the networking stack from NAPI context.
+    pub fn napi_rx(self, napi: &Napi, skb: SkBuff) {
+        let skb = skb.into_raw();
+        // SAFETY: `skb` is a valid skb that we own; GRO takes ownership of it.
+        unsafe {
+            rust_e1000_skb_eth_type_trans(skb, self.as_raw());
+            bindings::napi_gro_receive(napi.as_raw(), skb);
+        }
+    }
+}
+
+/// NAPI poll callback: returns the amount of work done, at most `budget`.
+pub type NapiPoll = unsafe extern "C" fn(napi: *mut bindings::napi_struct, budget: c_int) -> c_int;
+
+/// A `napi_struct` embedded in driver data.
+#[repr(transparent)]
+pub struct Napi(Opaque<bindings::napi_struct>);
+
+impl Napi {
+    /// Create a NAPI context; it must be added with [`Napi::add`] before use.
+    pub fn new() -> Self {
+        Self(Opaque::zeroed())
+    }
+
+    /// Get the raw `napi_struct` pointer.
+    pub fn as_raw(&self) -> *mut bindings::napi_struct {
+        self.0.get()
+    }
+
+    /// Attach the context to `ndev` with the default weight.
+    ///
+    /// # Safety
+    ///
+    /// `self` must not move and must outlive `ndev`; `free_netdev` removes it again.
+    pub unsafe fn add(&self, ndev: NetDev, poll: NapiPoll) {
+        // SAFETY: Both pointers are valid; the caller guarantees the lifetime rules.
+        unsafe { rust_e1000_netif_napi_add(ndev.as_raw(), self.as_raw(), Some(poll)) };
+    }
+
+    /// Allow the context to be scheduled.
+    pub fn enable(&self) {
+        // SAFETY: The context was added to a net_device.
+        unsafe { bindings::napi_enable(self.as_raw()) };
+    }
+
+    /// Wait for a running poll to finish and prevent further scheduling.
+    pub fn disable(&self) {
+        // SAFETY: The context was added to a net_device.
+        unsafe { bindings::napi_disable(self.as_raw()) };
+    }
+
+    /// Schedule a poll, unless one is already pending or the context is disabled.
+    pub fn schedule(&self) -> bool {
+        // SAFETY: The context was added to a net_device.
+        unsafe {
+            if bindings::napi_schedule_prep(self.as_raw()) {
+                bindings::__napi_schedule(self.as_raw());
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Mark the poll as done; returns false if the context was rescheduled meanwhile.
+    pub fn complete_done(&self, work_done: usize) -> bool {
+        // SAFETY: The context was added to a net_device and is being polled.
+        unsafe { bindings::napi_complete_done(self.as_raw(), work_done as c_int) }
+    }
+}
+
+impl Default for Napi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The RTNL lock, which serialises configuration of all net_devices, held
+/// until dropped.
+pub struct RtnlGuard(());
+
+impl RtnlGuard {
+    /// Take the RTNL lock. May sleep.
+    pub fn lock() -> Self {
+        // SAFETY: FFI call without preconditions; the lock is released on drop.
+        unsafe { bindings::rtnl_lock() };
+        RtnlGuard(())
+    }
+}
+
+impl Drop for RtnlGuard {
+    fn drop(&mut self) {
+        // SAFETY: The lock was taken in `lock`.
+        unsafe { bindings::rtnl_unlock() };
+    }
+}
+
+/// Where the headers of a TCP/IPv4 GSO skb are, and the segment size.
+pub struct TsoParams {
+    /// Offset of the IP header
+    pub ip_start: usize,
+    /// Offset of the TCP header
+    pub tcp_start: usize,
+    /// Length of all headers
+    pub hdr_len: usize,
+    /// Payload bytes per segment
+    pub mss: usize,
+}
+
+/// An owned `sk_buff`, freed on drop.
+pub struct SkBuff(NonNull<bindings::sk_buff>);
+
+// SAFETY: An skb nobody else holds may be used and freed from any thread,
+// e.g. completed from the NAPI poll after being queued by `ndo_start_xmit`.
+unsafe impl Send for SkBuff {}
+
+impl SkBuff {
+    /// Take ownership of a raw skb.
+    ///
+    /// # Safety
+    ///
+    /// `skb` must be a valid skb whose ownership is transferred to the returned value.
+    pub unsafe fn from_raw(skb: *mut bindings::sk_buff) -> Self {
+        // SAFETY: The caller guarantees that `skb` is valid, hence not null.
+        Self(unsafe { NonNull::new_unchecked(skb) })
+    }
+
+    /// Give up ownership of the skb.
+    pub fn into_raw(self) -> *mut bindings::sk_buff {
+        let skb = self.0.as_ptr();
+        core::mem::forget(self);
+        skb
+    }
+
+    /// The linear part of the frame.
+    pub fn data(&self) -> &[u8] {
+        let skb = self.0.as_ptr();
+        // SAFETY: `data` points to `len - data_len` bytes of linear data.
+        unsafe { from_raw_parts((*skb).data, ((*skb).len - (*skb).data_len) as usize) }
+    }
+
+    /// The frame as the linear part followed by the page fragments.
+    pub fn fragments(&self) -> impl Iterator<Item = &[u8]> + Clone {
+        let skb = self.0.as_ptr();
+        // SAFETY: The skb is valid and owned by us.
+        let nr_frags = unsafe { rust_e1000_skb_nr_frags(skb) };
+        let frags = (0..nr_frags).map(move |i| {
+            // SAFETY: `i` is below `nr_frags`; fragment pages are in the
+            // direct map on the 64-bit targets this driver supports.
+            unsafe {
+                let addr = rust_e1000_skb_frag_address(skb, i);
+                let len = rust_e1000_skb_frag_size(skb, i);
+                from_raw_parts(addr.cast::<u8>(), len as usize)
+            }
+        });
+        core::iter::once(self.data()).chain(frags)
+    }
+
+    /// For a `CHECKSUM_PARTIAL` skb, the offsets from the start of the frame
+    /// of the range to checksum and of the checksum field.
+    pub fn checksum_partial(&self) -> Option<(usize, usize)> {
+        let (mut start, mut offset) = (0, 0);
+        // SAFETY: The skb is valid and owned by us.
+        if unsafe { rust_e1000_skb_csum_partial(self.0.as_ptr(), &mut start, &mut offset) } {
+            Some((start as usize, offset as usize))
+        } else {
+            None
+        }
+    }
+
+    /// For a TCP/IPv4 GSO skb, prepare the headers for segmentation by the
+    /// device (zero IP length and checksum, TCP checksum seeded without the
+    /// length) and return where they are.
+    pub fn tso_prepare(&mut self) -> Result<Option<TsoParams>> {
+        let (mut ip_start, mut tcp_start, mut hdr_len, mut mss) = (0, 0, 0, 0);
+        // SAFETY: The skb is valid and owned by us; the helper unshares the
+        // header before writing to it.
+        let ret = unsafe {
+            rust_e1000_skb_tso_prep(
+                self.0.as_ptr(),
+                &mut ip_start,
+                &mut tcp_start,
+                &mut hdr_len,
+                &mut mss,
+            )
+        };
+        to_result(ret)?;
+        Ok((ret > 0).then_some(TsoParams {
+            ip_start: ip_start as usize,
+            tcp_start: tcp_start as usize,
+            hdr_len: hdr_len as usize,
+            mss: mss as usize,
+        }))
+    }
+
+    /// Fill in the checksum that was left to the device, in software.
+    pub fn checksum_help(&mut self) -> Result {
+        // SAFETY: The skb is valid and owned by us.
+        to_result(unsafe { bindings::skb_checksum_help(self.0.as_ptr()) })
+    }
+
+    /// Tell the stack the NIC verified the checksums, so it need not.
+    pub fn set_checksum_unnecessary(&mut self) {
+        // SAFETY: The skb is valid and owned by us.
+        unsafe { rust_e1000_skb_checksum_unnecessary(self.0.as_ptr()) };
+    }
+
+    /// The 802.1Q tag the stack left for the device to insert, if any.
+    pub fn vlan_tag(&self) -> Option<u16> {
+        // SAFETY: The skb is valid and owned by us.
+        let tci = unsafe { rust_e1000_skb_vlan_tag(self.0.as_ptr()) };
+        u16::try_from(tci).ok()
+    }
+
+    /// Record the 802.1Q tag the device stripped from the frame.
+    pub fn put_vlan_tag(&mut self, tci: u16) {
+        // SAFETY: The skb is valid and owned by us.
+        unsafe { rust_e1000_skb_put_vlan_tag(self.0.as_ptr(), tci) };
+    }
+
+    /// Extend the linear area by `len` bytes and return the new tail room.
+    pub fn put(&mut self, len: usize) -> &mut [u8] {
+        // SAFETY: The skb is owned by us; `skb_put` checks the tailroom.
+        unsafe {
+            let tail = bindings::skb_put(self.0.as_ptr(), len as u32);
+            from_raw_parts_mut(tail.cast::<u8>(), len)
+        }
+    }
+
+    /// Free the skb after it has been transmitted successfully.
+    pub fn consume(self) {
+        // SAFETY: We own the skb and give it up here.
+        unsafe { rust_e1000_dev_consume_skb_any(self.into_raw()) };
+    }
+}
+
+impl Drop for SkBuff {
+    fn drop(&mut self) {
+        // SAFETY: We own the skb; this is the drop (not consume) path.
+        unsafe { rust_e1000_dev_kfree_skb_any(self.0.as_ptr()) };
+    }
+}
+
+/// A page pool of receive buffers, destroyed on drop.
+///
+/// Fragments still out, e.g. in skbs, are released to the kernel as they
+/// come back.
+pub struct PagePool {
+    pool: NonNull<bindings::page_pool>,
+    buf_size: u32,
+}
+
+// SAFETY: The page pool may be used from any thread; allocations are
+// serialized by `&mut self`.
+unsafe impl Send for PagePool {}
+
+impl PagePool {
+    /// Create a pool for `dev` to receive into, sized for `size` buffers of
+    /// `buf_size` bytes.
+    pub fn new(dev: &device::Device, size: usize, buf_size: usize) -> Result<Self> {
+        let mut pool = core::ptr::null_mut();
+        // SAFETY: `dev` is a valid device; `pool` is valid for writes.
+        to_result(unsafe {
+            rust_e1000_page_pool_create(dev.as_raw(), size as u32, buf_size as u32, &mut pool)
+        })?;
+        Ok(Self {
+            pool: NonNull::new(pool).ok_or(ENOMEM)?,
+            buf_size: buf_size as u32,
+        })
+    }
+
+    /// Allocate a buffer mapped for the device to write. Does not sleep.
+    pub fn alloc(&mut self) -> Result<PoolFrag> {
+        let (mut page, mut offset) = (core::ptr::null_mut(), 0);
+        // SAFETY: The pool is valid by the type invariant; `page` and
+        // `offset` are valid for writes.
+        to_result(unsafe {
+            rust_e1000_rx_frag_alloc(self.pool.as_ptr(), self.buf_size, &mut page, &mut offset)
+        })?;
+        Ok(PoolFrag {
+            pool: self.pool,
+            page: NonNull::new(page).ok_or(ENOMEM)?,
+            offset,
+            size: self.buf_size,
+        })
+    }
+}
+
+impl Drop for PagePool {
+    fn drop(&mut self) {
+        // SAFETY: The pool is valid and no more buffers are allocated from it.
+        unsafe { rust_e1000_page_pool_destroy(self.pool.as_ptr()) };
+    }
+}
+
+/// A page pool fragment mapped for the device to write, returned to the
+/// pool on drop.
+pub struct PoolFrag {
+    pool: NonNull<bindings::page_pool>,
+    page: NonNull<bindings::page>,
+    offset: u32,
+    size: u32,
+}
+
+// SAFETY: A fragment nobody else holds may be used and released from any
+// thread.
+unsafe impl Send for PoolFrag {}
+
+impl PoolFrag {
+    /// The address the device writes to
+    pub fn dma_handle(&self) -> bindings::dma_addr_t {
+        // SAFETY: The page is a live page pool page by the type invariant.
+        unsafe { rust_e1000_rx_frag_dma(self.page.as_ptr(), self.offset) }
+    }
+
+    /// The buffer, as last synced for the CPU.
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: The fragment holds `size` bytes after its headroom.
+        unsafe {
+            let data = rust_e1000_rx_frag_data(self.page.as_ptr(), self.offset);
+            from_raw_parts(data.cast::<u8>(), self.size as usize)
+        }
+    }
+
+    /// Make the first `len` bytes the device wrote visible to the CPU.
+    pub fn sync_for_cpu(&mut self, len: usize) {
+        // SAFETY: The fragment is mapped on the pool's device.
+        unsafe {
+            rust_e1000_rx_frag_sync_for_cpu(
+                self.pool.as_ptr(),
+                self.page.as_ptr(),
+                self.offset,
+                len as u32,
+            )
+        };
+    }
+
+    /// Give the buffer back to the device to write again.
+    pub fn sync_for_device(&mut self) {
+        // SAFETY: The fragment is mapped on the pool's device.
+        unsafe {
+            rust_e1000_rx_frag_sync_for_device(
+                self.pool.as_ptr(),
+                self.page.as_ptr(),
+                self.offset,
+                self.size,
+            )
+        };
+    }
+
+    /// Build an skb around the first `len` bytes; the skb takes the
+    /// fragment over and returns it to the pool when freed. Must be called
+    /// from NAPI context.
+    pub fn into_skb(self, len: usize) -> Option<SkBuff> {
+        // SAFETY: The fragment has room for the skb around its data, see
+        // `rust_e1000_rx_truesize`.
+        let skb = unsafe {
+            rust_e1000_rx_build_skb(self.page.as_ptr(), self.offset, self.size, len as u32)
+        };
+        // Without an skb the fragment is dropped back into the pool.
+        let skb = NonNull::new(skb)?;
+        core::mem::forget(self);
+        Some(SkBuff(skb))
+    }
+
+    /// Append the first `len` bytes to `skb` as a page fragment, which the
+    /// skb takes over.
+    pub fn add_to(self, skb: &mut SkBuff, len: usize) {
+        // SAFETY: We own both; the skb takes our reference to the page.
+        unsafe {
+            rust_e1000_skb_add_rx_frag(
+                skb.0.as_ptr(),
+                self.page.as_ptr(),
+                self.offset,
+                self.size,
+                len as u32,
+            )
+        };
+        core::mem::forget(self);
+    }
+}
+
+impl Drop for PoolFrag {
+    fn drop(&mut self) {
+        // SAFETY: We hold a reference to the page, taken from `pool`.
+        unsafe { rust_e1000_rx_frag_put(self.pool.as_ptr(), self.page.as_ptr()) };
+    }
+}
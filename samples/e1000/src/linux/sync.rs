@@ -0,0 +1,50 @@
+//! `spin_lock_irqsave` on top of the kernel crate's [`SpinLock`].
+
+use super::helpers::*;
+use core::ffi::c_ulong;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use kernel::sync::{SpinLock, lock::spinlock::SpinLockGuard};
+
+/// A [`SpinLock`] guard held with local interrupts disabled.
+///
+/// The lock is released before interrupts are restored, as `spin_unlock_irqrestore` does.
+pub struct IrqSaveGuard<'a, T: ?Sized> {
+    guard: ManuallyDrop<SpinLockGuard<'a, T>>,
+    flags: c_ulong,
+}
+
+/// Lock `lock` with local interrupts disabled.
+///
+/// Needed for state shared with the hard interrupt handler.
+pub fn lock_irqsave<T: ?Sized>(lock: &SpinLock<T>) -> IrqSaveGuard<'_, T> {
+    // SAFETY: FFI call without preconditions; the flags are restored on drop.
+    let flags = unsafe { rust_e1000_local_irq_save() };
+    IrqSaveGuard {
+        guard: ManuallyDrop::new(lock.lock()),
+        flags,
+    }
+}
+
+impl<T: ?Sized> Deref for IrqSaveGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized> DerefMut for IrqSaveGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T: ?Sized> Drop for IrqSaveGuard<'_, T> {
+    fn drop(&mut self) {
+        // SAFETY: The guard is not used after this point.
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        // SAFETY: `flags` was returned by the matching `rust_e1000_local_irq_save`.
+        unsafe { rust_e1000_local_irq_restore(self.flags) };
+    }
+}
@@ -0,0 +1,67 @@
+//! A `delayed_work` embedded in driver data.
+//!
+//! The kernel crate's workqueue has no delayed items to build on, so this
+//! goes through `helpers.c` like the netdev wrappers.
+
+use super::helpers::*;
+use kernel::{bindings, types::Opaque};
+
+/// Work callback, given the `work_struct` inside the [`DelayedWork`].
+pub type WorkFn = unsafe extern "C" fn(work: *mut bindings::work_struct);
+
+/// A `delayed_work` run on the system workqueue.
+#[repr(transparent)]
+pub struct DelayedWork(Opaque<bindings::delayed_work>);
+
+impl DelayedWork {
+    /// Create the item; it must be set up with [`DelayedWork::init`] before use.
+    pub fn new() -> Self {
+        Self(Opaque::zeroed())
+    }
+
+    /// Get the raw `delayed_work` pointer.
+    pub fn as_raw(&self) -> *mut bindings::delayed_work {
+        self.0.get()
+    }
+
+    /// Get the item whose `work_struct` is `work`.
+    ///
+    /// # Safety
+    ///
+    /// `work` must be the `work_struct` of a [`DelayedWork`] that outlives `'a`.
+    pub unsafe fn from_work<'a>(work: *mut bindings::work_struct) -> &'a Self {
+        // SAFETY: The caller guarantees that `work` is embedded in a
+        // `delayed_work`, which `Self` is a transparent wrapper of.
+        unsafe { &*kernel::container_of!(work, bindings::delayed_work, work).cast::<Self>() }
+    }
+
+    /// Make the item run `func`.
+    ///
+    /// # Safety
+    ///
+    /// `self` must not move, and must not be freed while queued: see
+    /// [`DelayedWork::cancel_sync`].
+    pub unsafe fn init(&self, func: WorkFn) {
+        // SAFETY: The pointer is valid; the caller guarantees the lifetime rules.
+        unsafe { rust_e1000_init_delayed_work(self.as_raw(), Some(func)) };
+    }
+
+    /// Run the item in `msecs` milliseconds, unless it is already queued.
+    pub fn schedule(&self, msecs: u32) -> bool {
+        // SAFETY: The item was initialised.
+        unsafe { rust_e1000_schedule_delayed_work(self.as_raw(), msecs) }
+    }
+
+    /// Cancel the item and wait for a running one to finish, which cannot
+    /// queue itself again meanwhile.
+    pub fn cancel_sync(&self) -> bool {
+        // SAFETY: The item was initialised.
+        unsafe { bindings::cancel_delayed_work_sync(self.as_raw()) }
+    }
+}
+
+impl Default for DelayedWork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
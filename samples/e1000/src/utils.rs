@@ -18,12 +18,3 @@ pub(crate) fn fence_w() {
     #[cfg(not(target_arch = "riscv64"))]
     core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
 }
-pub(crate) fn fence_r() {
-    #[cfg(target_arch = "riscv64")]
-    unsafe {
-        asm!("fence ir, ir");
-    }
-
-    #[cfg(not(target_arch = "riscv64"))]
-    core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
-}